@@ -1,22 +1,38 @@
 use std::{
     ffi::{OsStr, OsString},
     path::PathBuf,
+    sync::LazyLock,
 };
 
-use clap::Parser;
-use once_cell::sync::Lazy;
+use clap::{ArgAction, Args, CommandFactory, FromArgMatches as _, Parser, Subcommand};
 
-use crate::ffmpeg::EncodingProfile;
+use anyhow::Result;
+use log::LevelFilter;
 
-static DB_FILE: Lazy<OsString> = Lazy::new(|| {
+use crate::{
+    config::Config,
+    ffmpeg::{self, EncodingProfile},
+    fzf::{self, FzfArgs},
+    sub::{FpsCorrection, LangPair, StreamPreference, Strip, TimestampFormat},
+    template::OutputTemplate,
+    util,
+};
+
+/// In the user's data dir, or next to the executable if there is none.
+static DB_FILE: LazyLock<OsString> = LazyLock::new(|| {
+    util::data_dir()
+        .map_or_else(legacy_db_file, |dir| dir.join("magiclip").join("db.json"))
+        .into_os_string()
+});
+
+/// Where the DB used to be by default, next to the executable.
+pub fn legacy_db_file() -> PathBuf {
     std::env::current_exe()
         .unwrap()
         .parent()
         .unwrap()
-        .to_owned()
         .join("db.json")
-        .into_os_string()
-});
+}
 
 fn db_file() -> &'static OsStr {
     &DB_FILE
@@ -24,20 +40,837 @@ fn db_file() -> &'static OsStr {
 
 #[derive(Clone, Debug, PartialEq, Eq, Parser)]
 #[command(version, about, long_about = None)]
-pub struct Args {
-    #[arg(short, long, default_value = db_file())]
+#[command(subcommand_negates_reqs = true)]
+pub struct Cli {
+    #[arg(short, long, global = true, default_value = db_file())]
     pub db_file: PathBuf,
 
+    /// Log more (`-v` debug, `-vv` trace). `RUST_LOG` takes precedence.
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Log less (`-q` only warnings, `-qq` only errors).
+    #[arg(short, long, global = true, action = ArgAction::Count, conflicts_with = "verbose")]
+    pub quiet: u8,
+
+    /// How log records get written to stderr.
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    /// Retry ffmpeg/ffprobe this many times (waiting longer each time) when
+    /// they fail for passing reasons, like a busy system or a stalling network
+    /// mount.
+    #[arg(long, global = true, default_value_t = 2)]
+    pub retries: u32,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// `clip` is the default when no subcommand is given
+    #[command(flatten)]
+    clip: ClipArgs,
+}
+
+impl Cli {
+    /// Like `parse()`, but with defaults from the config file (see `Config`).
+    pub fn parse_with_config() -> Result<Self> {
+        let matches = Config::load()?
+            .apply(<Self as CommandFactory>::command())?
+            .get_matches();
+        Ok(Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit()))
+    }
+
+    pub fn log_level(&self) -> LevelFilter {
+        match (self.verbose, self.quiet) {
+            (0, 0) => LevelFilter::Info,
+            (1, _) => LevelFilter::Debug,
+            (_, 0) => LevelFilter::Trace,
+            (_, 1) => LevelFilter::Warn,
+            (_, _) => LevelFilter::Error,
+        }
+    }
+
+    pub fn command(self) -> Command {
+        self.command.unwrap_or(Command::Clip(self.clip))
+    }
+}
+
+impl Command {
+    /// Applies `util::resolve_path()` to all paths given.
+    pub fn resolve_paths(&mut self) -> Result<()> {
+        match self {
+            Command::Scan(scan) => scan.resolve_paths(),
+            Command::Clip(clip) => {
+                for path in [
+                    &mut clip.clip_dir,
+                    &mut clip.concat,
+                    &mut clip.source,
+                    &mut clip.save_selection,
+                    &mut clip.replay_selection,
+                    &mut clip.from_list,
+                ]
+                .into_iter()
+                .flatten()
+                {
+                    *path = util::resolve_path(path)?;
+                }
+                clip.scan.resolve_paths()
+            }
+            Command::ExtractSubs(extract) => {
+                extract.out_dir = util::resolve_path(&extract.out_dir)?;
+                extract.scan.resolve_paths()
+            }
+            Command::List(_)
+            | Command::Prune
+            | Command::Stats
+            | Command::CheckDb(_)
+            | Command::PlayLine(_) => Ok(()),
+        }
+    }
+}
+
+impl ClipArgs {
+    /// `--output-template`, or the one of the layout options.
+    pub fn output_template(&self) -> OutputTemplate {
+        self.output_template.clone().unwrap_or_else(|| {
+            OutputTemplate::from_flags(
+                self.subdir_per_profile,
+                self.subdir_per_source,
+                self.hash_suffix,
+            )
+        })
+    }
+
+    /// `util::FilenameLens` fitting the clip dir's filesystem, with
+    /// `--filename-text-len` if given.
+    pub fn filename_lens(&self) -> util::FilenameLens {
+        let fitting =
+            util::FilenameLens::fitting(self.clip_dir.as_deref().and_then(util::name_max));
+        util::FilenameLens {
+            text: self.filename_text_len.unwrap_or(fitting.text),
+            ..fitting
+        }
+    }
+}
+
+impl ScanArgs {
+    /// No videos to scan given at all.
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty() && self.paths_from.is_none()
+    }
+
+    fn resolve_paths(&mut self) -> Result<()> {
+        for path in &mut self.paths {
+            *path = util::resolve_path(path)?;
+        }
+        for dir in [
+            &mut self.temp_dir,
+            &mut self.thumbnails,
+            &mut self.video_dir,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *dir = util::resolve_path(dir)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Subcommand)]
+#[allow(clippy::large_enum_variant)] // only exists once
+pub enum Command {
+    /// Scan videos and add them to the DB, without clipping anything.
+    Scan(ScanArgs),
+    /// Scan, select subtitle lines with fzf and clip them (default).
+    Clip(ClipArgs),
+    /// Print subtitle lines cached in the DB. Doesn't scan or touch the
+    /// videos, so it also works while they're offline.
+    List(ListArgs),
+    /// Remove DB entries whose videos are gone.
+    Prune,
+    /// Print what's in the DB: videos, subtitle lines and which videos
+    /// changed or are gone. Doesn't modify the DB.
+    Stats,
+    /// Check whether the DB can be read, reporting the entries that can't
+    /// (e.g. after a crash while saving). For when every other command fails
+    /// to load it.
+    CheckDb(CheckDbArgs),
+    /// Play a line of the finder, see `--preview-clip`.
+    #[command(hide = true)]
+    PlayLine(PlayLineArgs),
+    /// Extract the subtitles of all videos to SRT files, mirroring the
+    /// folder structure. Doesn't touch the DB.
+    ExtractSubs(ExtractArgs),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Args)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct ScanArgs {
+    /// Paths to video folders (or files) which get scanned recursively and added to the DB.
+    /// Without any (and without `--paths-from`), `clip` only searches what's
+    /// already in the DB, skipping the scan entirely.
+    #[arg()]
+    pub paths: Vec<PathBuf>,
+
+    /// Also scan the videos listed (one path per line) in this file, or stdin
+    /// if `-`. They're taken as they are, without walking any dirs.
+    #[arg(long)]
+    pub paths_from: Option<PathBuf>,
+
+    /// Skip files and dirs (including everything below) matching this glob,
+    /// relative to the scanned folder, e.g. `sample` or `extras/**/*.mkv`.
+    /// Can be given multiple times.
+    #[arg(long)]
+    pub exclude: Vec<String>,
+
+    /// Skip malformed entries in subtitle files (logging them) instead of
+    /// failing the whole file. Only applies to newly indexed videos.
+    #[arg(long, default_value_t = false)]
+    pub lenient_subs: bool,
+
+    /// Read image-based sub streams (DVD/`VobSub`, Blu-ray/PGS) via OCR with `tesseract`,
+    /// instead of skipping them. Slow. Only applies to newly indexed videos.
+    #[arg(long, default_value_t = false)]
+    pub ocr: bool,
+
+    /// How many videos get indexed at once (default: one per core). Lower it to
+    /// keep the machine (and disks) usable during big scans.
+    #[arg(long)]
+    pub scan_jobs: Option<usize>,
+
+    /// Run ffmpeg/ffprobe/tesseract with this niceness (via `nice`) while
+    /// scanning, e.g. 19 for the lowest priority.
+    #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+    pub nice: i32,
+
+    /// Index videos with identical content (by size and a partial hash) only
+    /// once, so copies don't show up twice when searching.
+    #[arg(long, default_value_t = false)]
+    pub dedup: bool,
+
+    /// Never remove DB entries of missing videos, just mark them stale (e.g. for
+    /// flaky network mounts).
+    #[arg(long, default_value_t = false)]
+    pub keep_missing: bool,
+
+    /// Re-index all videos, even those that seem unchanged (e.g. after fixing
+    /// subtitle files in place, or upgrading magiclip).
+    #[arg(long, default_value_t = false)]
+    pub rescan: bool,
+
+    /// Re-index videos whose full path matches this glob (globs without `/`
+    /// match any file or dir name, e.g. `*S01*`), even if they seem unchanged.
+    /// Can be given multiple times.
+    #[arg(long, conflicts_with = "rescan")]
+    pub rescan_path: Vec<String>,
+
+    /// Keep the subtitles extracted from videos in a cache next to the DB, by
+    /// the videos' content, so re-indexing a video whose content didn't change
+    /// reuses them instead of running ffmpeg again.
+    #[arg(long, default_value_t = false)]
+    pub extract_cache: bool,
+
+    /// Size limit of `--extract-cache` in MiB, the least recently used
+    /// videos' subtitles get removed after a scan to stay below it.
+    #[arg(long, default_value_t = 256)]
+    pub extract_cache_mib: u64,
+
+    /// Extract subtitles into temp dirs under this dir instead of the system's
+    /// temp dir (`$TMPDIR`), e.g. when `/tmp` is a small tmpfs. Only `--ocr`
+    /// and `--extract-cache` need temp files, text subtitles are read from
+    /// ffmpeg directly otherwise.
+    #[arg(long)]
+    pub temp_dir: Option<PathBuf>,
+
+    /// Write a contact sheet (a 4x4 grid of thumbnails spread over the video)
+    /// of each newly indexed video into this dir, as `<video name> [<hash>].jpg`
+    /// with the hash telling videos of the same name apart. Videos having one
+    /// already are skipped.
+    #[arg(long, value_name = "DIR")]
+    pub thumbnails: Option<PathBuf>,
+
+    /// Also index subtitle files (`.srt`, `.ass`, `.vtt`) without a video
+    /// named alike next to them, e.g. downloaded separately. They're searched
+    /// like videos' subtitles, but clipping them needs their video, looked for
+    /// in `--video-dir`.
+    #[arg(long, default_value_t = false)]
+    pub loose_subs: bool,
+
+    /// Where the videos of `--loose-subs` are, named like the subtitle files
+    /// (minus a language suffix like `.en`).
+    #[arg(long, value_name = "DIR", requires = "loose_subs")]
+    pub video_dir: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum LogFormat {
+    /// for humans
+    #[default]
+    Text,
+    /// one JSON object per record, with fields like `file` or `clip` where
+    /// they apply, for other tools to parse
+    Json,
+}
+
+/// What lets the user pick lines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Finder {
+    /// the external `fzf`
+    #[default]
+    Fzf,
+    /// a built-in one, no fzf needed. Shows each line's time and its
+    /// neighbours in a side panel, but can't `--preview-clip`.
+    Tui,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ClipBy {
+    /// subtitle lines from the DB
+    #[default]
+    Subtitles,
+    /// chapters of the scanned videos (not kept in the DB, so they get probed
+    /// every time)
+    Chapters,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ClipFormat {
+    /// a video (or audio) file per selected line
+    #[default]
+    Video,
+    /// no clips, but an SRT per source video with the selected lines at their
+    /// original times, for players jumping around the original
+    SrtWindow,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ClipOrder {
+    /// by video, then time in the video
+    Chrono,
+    /// as selected in fzf
+    Selection,
+    /// by subtitle text
+    Alpha,
+}
+
+/// `START-END` of `--range`, in ms.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+fn parse_range(range: &str) -> Result<TimeRange, String> {
+    let parse = |timestamp| {
+        ffmpeg::parse_timestamp(timestamp)
+            .ok_or_else(|| format!("invalid timestamp {timestamp:?}, expected [[HH:]MM:]SS[.fff]"))
+    };
+    let (start, end) = range
+        .split_once('-')
+        .ok_or_else(|| format!("expected START-END, got {range:?}"))?;
+    let (start, end) = (parse(start)?, parse(end)?);
+    if end <= start {
+        return Err(format!("range ends before it starts: {range:?}"));
+    }
+    Ok(TimeRange { start, end })
+}
+
+/// `SRC:DST` of `--fps-correction`, each like `25`, `23.976` or `24000/1001`.
+fn parse_fps_correction(correction: &str) -> Result<FpsCorrection, String> {
+    let parse = |fps: &str| {
+        let fps = fps.trim();
+        let rational = if let Some((num, den)) = fps.split_once('/') {
+            num.parse().ok().zip(den.parse().ok())
+        } else {
+            let (whole, fraction) = fps.split_once('.').unwrap_or((fps, ""));
+            let den = u32::try_from(fraction.len())
+                .ok()
+                .and_then(|digits| 10u64.checked_pow(digits));
+            format!("{whole}{fraction}").parse().ok().zip(den)
+        };
+        rational.ok_or_else(|| format!("invalid frame rate {fps:?}"))
+    };
+    let (source, target) = correction
+        .split_once(':')
+        .ok_or_else(|| format!("expected SRC:DST, got {correction:?}"))?;
+    FpsCorrection::new(parse(source)?, parse(target)?)
+        .ok_or_else(|| format!("invalid frame rates {correction:?}"))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Args)]
+pub struct ExtractArgs {
+    /// Where the SRT files go, as `{video_stem}.{stream}.{lang}.srt` in the
+    /// video's folder relative to its scanned path.
     #[arg(short, long)]
-    pub clip_dir: PathBuf,
+    pub out_dir: PathBuf,
+
+    #[command(flatten)]
+    pub scan: ScanArgs,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Args)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct ClipArgs {
+    #[arg(short, long, required = true)]
+    pub clip_dir: Option<PathBuf>,
 
     #[arg(long, default_value_t = false)]
     pub subdir_per_profile: bool,
 
+    /// Put clips into a subdir named after their source video (inside the
+    /// profile subdir, if any).
+    #[arg(long, default_value_t = false)]
+    pub subdir_per_source: bool,
+
+    /// End clip filenames with 8 hex digits hashed from the source video's
+    /// path and the subtitle's number and start, so the same line at the same
+    /// time in different videos can't collide. The same clip gets the same
+    /// suffix every run, so `--skip-existing` keeps working.
+    #[arg(long, default_value_t = false)]
+    pub hash_suffix: bool,
+
+    /// Where clips go inside the clip dir, like
+    /// `{video_stem}/{lang}/{start}_{text}.{ext}`, with dirs created as
+    /// needed. Tokens: `{text}`, `{start}`, `{num}` (of the subtitle),
+    /// `{path}` and `{video_stem}` (of the video), `{lang}` and `{stream}` (of
+    /// the subtitle stream), `{hash}` (see `--hash-suffix`), `{profile}` and
+    /// `{ext}`. Has to end with `.{ext}`. Defaults to
+    /// `{text} [{start}] ({path}).{ext}`, with the dirs and suffix of the
+    /// options above.
+    #[arg(
+        long,
+        conflicts_with_all = ["subdir_per_profile", "subdir_per_source", "hash_suffix"],
+    )]
+    pub output_template: Option<OutputTemplate>,
+
+    /// How the subtitle's start is rendered in clip filenames. `frames` needs
+    /// to probe each source video for its frame rate.
+    #[arg(long, value_enum, default_value_t = TimestampFormat::Hms)]
+    pub timestamp_format: TimestampFormat,
+
+    /// Merge subtitles stacked on each other in a stream (overlapping by more
+    /// than half of the shorter one, e.g. a sign translation over dialogue)
+    /// into one line, so they don't make identical clips. The DB keeps them
+    /// apart.
+    #[arg(long, default_value_t = false)]
+    pub merge_overlapping: bool,
+
+    /// Subtitles were made for SRC frames per second, but the videos play at
+    /// DST (e.g. `23.976:25` for PAL speed-up), so their times get scaled,
+    /// everywhere from the finder to the clips.
+    #[arg(long, value_name = "SRC:DST", value_parser = parse_fps_correction)]
+    pub fps_correction: Option<FpsCorrection>,
+
+    /// Shift all subtitles this many ms later (earlier if negative), for
+    /// tracks that are off by the same amount throughout, everywhere from the
+    /// finder to the clips. Times get rescaled by `--fps-correction` first,
+    /// then shifted (stopping at 0), and then padded by `--pad-start` and
+    /// `--pad-end` when clipping.
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value_t = 0,
+        allow_negative_numbers = true
+    )]
+    pub sub_offset: i64,
+
+    /// What to pick clips from in the finder.
+    #[arg(long, value_enum, default_value_t = ClipBy::Subtitles)]
+    pub by: ClipBy,
+
+    /// What to make of the selected lines.
+    #[arg(long, value_enum, default_value_t = ClipFormat::Video)]
+    pub clip_format: ClipFormat,
+
+    /// Cut the subtitle text in clip filenames to this many bytes (UTF-8, so
+    /// 64 hold 32 Cyrillic chars), at a word boundary (with `…`).
+    /// Defaults to 64, or less if the clip dir's filesystem only takes names
+    /// shorter than 255 bytes (e.g. eCryptfs).
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub filename_text_len: Option<usize>,
+
+    /// Encoding profile. Can be given multiple times to clip each line in
+    /// several formats, which need `--subdir-per-profile` if they share a
+    /// container.
     #[arg(short, long, default_value = "av1")]
-    pub profile: EncodingProfile,
+    pub profile: Vec<EncodingProfile>,
 
-    /// Paths to video folders (or files) which get scanned recursively and added to the DB.
-    #[arg()]
-    pub paths: Vec<PathBuf>,
+    /// Output container (file extension), overriding the profile's default,
+    /// e.g. `webm` or `mp4`.
+    #[arg(long)]
+    pub container: Option<String>,
+
+    /// Override the video profiles' constant quality (CRF, or CQ for NVENC):
+    /// higher is smaller and worse. Ignored for FLAC and COPY.
+    #[arg(long, value_name = "N")]
+    pub crf: Option<u8>,
+
+    /// Override the video profiles' encoder preset: lower is slower and better
+    /// (0-13 for AV1, 1-7 for NVENC). Ignored for FLAC and COPY.
+    #[arg(long, value_name = "N")]
+    pub preset: Option<u8>,
+
+    /// Only keep the audio track in this language (e.g. `jpn`), dropping the
+    /// others.
+    #[arg(long, conflicts_with = "audio_stream")]
+    pub audio_lang: Option<String>,
+
+    /// Only keep the n-th (0-based) audio track, dropping the others.
+    #[arg(long)]
+    pub audio_stream: Option<usize>,
+
+    /// Don't re-encode clips whose output file already exists.
+    #[arg(long, default_value_t = false, conflicts_with = "overwrite")]
+    pub skip_existing: bool,
+
+    /// Replace clips whose output file already exists (otherwise they fail).
+    #[arg(long, default_value_t = false)]
+    pub overwrite: bool,
+
+    /// Normalize the loudness of the clips' audio (EBU R128, to -16 LUFS), for
+    /// sources with wildly different levels.
+    #[arg(long, default_value_t = false)]
+    pub loudnorm: bool,
+
+    /// Fade video and audio in at the start of clips and out at their end,
+    /// over this many ms (at most half the clip each). 0 for hard cuts.
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    pub fade: u64,
+
+    /// Encode clips in this dir and only move them into the clip dir once
+    /// finished, instead of staging them as hidden files next to their final
+    /// place. May be on another filesystem (e.g. a fast disk), clips then get
+    /// copied over.
+    #[arg(long)]
+    pub stage_dir: Option<PathBuf>,
+
+    /// Next to each clip, write an SRT with just its subtitle line, timed to
+    /// match the clip.
+    #[arg(long, default_value_t = false)]
+    pub export_srt: bool,
+
+    /// Check each clip with ffprobe after making it: readable, with the
+    /// expected streams and about as long as asked for. Clips failing this get
+    /// deleted and count as failed.
+    #[arg(long, default_value_t = false)]
+    pub verify_clips: bool,
+
+    /// Next to each clip, write a JPEG of its middle frame.
+    #[arg(long, default_value_t = false)]
+    pub poster: bool,
+
+    /// Clip this time range of `--source` directly (e.g. `00:12:03.5-00:12:08`),
+    /// bypassing the DB and fzf. For moments without any subtitle.
+    #[arg(long, requires = "source", value_parser = parse_range)]
+    pub range: Option<TimeRange>,
+
+    /// The video to clip `--range` from.
+    #[arg(long, requires = "range")]
+    pub source: Option<PathBuf>,
+
+    /// Write the selected lines to this file, for `--replay-selection`.
+    #[arg(long)]
+    pub save_selection: Option<PathBuf>,
+
+    /// Instead of asking fzf, take the lines saved by `--save-selection` to
+    /// this file. Lines no longer in the DB get skipped with a warning.
+    #[arg(long)]
+    pub replay_selection: Option<PathBuf>,
+
+    /// Instead of asking fzf, clip the lines listed in this file: CSV with a
+    /// `text` and an optional `video_path` column (matching the end of the
+    /// videos' paths), or a JSON array of such objects if it ends with `.json`.
+    /// Lines not found, or found in several places, get skipped with a warning.
+    #[arg(long, conflicts_with = "replay_selection")]
+    pub from_list: Option<PathBuf>,
+
+    /// After clipping, also join all clips (in selection order) into this
+    /// file. Its extension has to match the clips' container.
+    #[arg(long)]
+    pub concat: Option<PathBuf>,
+
+    /// Start clips this many ms before the subtitle (buffer against badly
+    /// synced subs).
+    #[arg(long, default_value_t = 0)]
+    pub pad_start: u64,
+
+    /// End clips this many ms after the subtitle.
+    #[arg(long, default_value_t = 0)]
+    pub pad_end: u64,
+
+    /// Match fzf queries regardless of case and accents (`cafe` finds
+    /// `Café`), while still showing and naming clips by the original text.
+    #[arg(long, default_value_t = false)]
+    pub fold_search: bool,
+
+    /// Show lines of the original language with the translation shown at the
+    /// same time (of videos with subs in both), searching both. Clips are still
+    /// named by the original. E.g. `ja:en`, 2- and 3-letter codes both match.
+    #[arg(long, value_name = "ORIGINAL:TRANSLATION")]
+    pub pair_langs: Option<LangPair>,
+
+    /// Only search one sub stream per video, leaving out e.g. signs-only and
+    /// forced ones. The others stay in the DB.
+    #[arg(long, value_enum)]
+    pub prefer_stream: Option<StreamPreference>,
+
+    /// Search all sub streams, even if `--prefer-stream` is set (in the config).
+    #[arg(long, default_value_t = false, conflicts_with = "prefer_stream")]
+    pub all_streams: bool,
+
+    /// Remove these from the subtitle text that's shown, searched and put in
+    /// clip filenames (comma separated or repeated). Exported SRTs keep the
+    /// original text.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub strip: Vec<Strip>,
+
+    /// Leave out subtitles in the first this many seconds of each video, e.g.
+    /// to hide the intro song.
+    #[arg(long, default_value_t = 0)]
+    pub skip_intro: u64,
+
+    /// Leave out subtitles in the last this many seconds of each video, e.g.
+    /// to hide the credits. Needs the videos to be reachable, for their length.
+    #[arg(long, default_value_t = 0)]
+    pub skip_outro: u64,
+
+    /// Prefix lines in fzf with the episode (`S01E04`, or a date) parsed from
+    /// the video's filename, if there is one.
+    #[arg(long, default_value_t = false)]
+    pub show_episode: bool,
+
+    /// Also clip the N subtitle lines before and after each selected one.
+    /// Overlapping clips of the same video get joined into one.
+    #[arg(long, default_value_t = 0)]
+    pub context: usize,
+
+    /// Select exactly two lines of the same video, and clip everything from
+    /// the start of the earlier one to the end of the later one as a single
+    /// clip, however many lines are in between.
+    #[arg(long, default_value_t = false, conflicts_with = "context")]
+    pub span: bool,
+
+    /// Number clips (`001_…`) in this order, which `--concat` follows, too.
+    /// `chrono` is by video path, then start.
+    #[arg(long, value_enum)]
+    pub sort: Option<ClipOrder>,
+
+    /// Only show (and match) the dialogue in fzf, without timestamps, stream
+    /// tags and paths.
+    #[arg(long, default_value_t = false)]
+    pub text_only: bool,
+
+    /// Only offer lines whose dialogue matches this in the finder, best
+    /// matches first: the exact line, then the whole phrase, then all its
+    /// words, then the text within words, then fuzzy matches (all case- and
+    /// accent-insensitive).
+    #[arg(long)]
+    pub query: Option<String>,
+
+    /// Only offer the best this many lines matching `--query`.
+    #[arg(long, requires = "query")]
+    pub limit: Option<usize>,
+
+    /// Extra options for fzf, split like a shell would, e.g.
+    /// `--fzf-args "--height 40% --layout=reverse"`. `$FZF_DEFAULT_OPTS` is
+    /// honored as well. Options changing fzf's input or output format (like
+    /// `--print0` or `--no-multi`) are refused.
+    #[arg(long, value_parser = fzf::parse_args, default_value = "", hide_default_value = true)]
+    pub fzf_args: FzfArgs,
+
+    /// What to pick lines in.
+    #[arg(long, value_enum, default_value_t = Finder::Fzf)]
+    pub finder: Finder,
+
+    /// Let Ctrl-P in fzf play the current line's segment (with ffplay, or
+    /// mpv), to check it before clipping. Writes no files but a list of the
+    /// segments for the lifetime of the finder.
+    #[arg(long, default_value_t = false)]
+    pub preview_clip: bool,
+
+    /// Stop after scanning and formatting the lines, before the finder, e.g.
+    /// to time just those with `--time-phases`.
+    #[arg(long, default_value_t = false)]
+    pub no_clip: bool,
+
+    /// Print how long each phase took and its throughput (videos scanned and
+    /// lines formatted per second), e.g. to tune `--scan-jobs`.
+    #[arg(long, default_value_t = false)]
+    pub time_phases: bool,
+
+    /// Skip selected subtitles shorter than this (in ms).
+    #[arg(long)]
+    pub min_duration: Option<u64>,
+
+    /// Skip selected subtitles longer than this (in ms).
+    #[arg(long)]
+    pub max_duration: Option<u64>,
+
+    #[command(flatten)]
+    pub scan: ScanArgs,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Args)]
+pub struct ListArgs {
+    /// Only print lines containing the given text, dialogue or path (case- and
+    /// accent-insensitive). Lines whose dialogue matches it best come first,
+    /// ranked like with `clip --query`.
+    #[arg(long)]
+    pub query: Option<String>,
+
+    /// Only print the best this many lines matching `--query`.
+    #[arg(long, requires = "query")]
+    pub limit: Option<usize>,
+
+    /// Instead of subtitle lines, print the videos whose subtitle streams or
+    /// files couldn't be read when indexing them, with the errors.
+    #[arg(long, default_value_t = false, conflicts_with = "query")]
+    pub errors: bool,
+
+    /// End lines with NUL instead of a newline, for `xargs -0` and the like,
+    /// as subtitles can span several lines.
+    #[arg(long, default_value_t = false, conflicts_with = "errors")]
+    pub print0: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Args)]
+pub struct CheckDbArgs {
+    /// Rewrite the DB with only the readable entries, backing up the original
+    /// next to it first.
+    #[arg(long, default_value_t = false)]
+    pub repair: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Args)]
+pub struct PlayLineArgs {
+    /// written by `fzf::select()`
+    pub targets: PathBuf,
+    /// of the line in the finder
+    pub index: usize,
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser as _;
+
+    use super::{Cli, Command, FpsCorrection};
+
+    #[test]
+    fn clip_is_default() {
+        let cli = Cli::try_parse_from(["magiclip", "-c", "clips", "videos"]).unwrap();
+        let Command::Clip(clip) = cli.command() else {
+            panic!("expected clip");
+        };
+        assert_eq!(clip.clip_dir.unwrap().to_str(), Some("clips"));
+        assert_eq!(clip.scan.paths.len(), 1);
+    }
+
+    #[test]
+    fn subcommand_without_clip_dir() {
+        let cli = Cli::try_parse_from(["magiclip", "--db-file", "db.json", "list"]).unwrap();
+        assert_eq!(cli.db_file.to_str(), Some("db.json"));
+        assert!(matches!(cli.command(), Command::List(_)));
+
+        let cli = Cli::try_parse_from(["magiclip", "scan", "videos", "-d", "db.json"]).unwrap();
+        assert_eq!(cli.db_file.to_str(), Some("db.json"));
+        assert!(matches!(cli.command(), Command::Scan(_)));
+    }
+
+    #[test]
+    fn extract_subs() {
+        let cli =
+            Cli::try_parse_from(["magiclip", "extract-subs", "-o", "srts", "videos"]).unwrap();
+        let Command::ExtractSubs(extract) = cli.command() else {
+            panic!("expected extract-subs");
+        };
+        assert_eq!(extract.out_dir.to_str(), Some("srts"));
+        assert_eq!(extract.scan.paths.len(), 1);
+        assert!(Cli::try_parse_from(["magiclip", "extract-subs", "videos"]).is_err());
+    }
+
+    #[test]
+    fn range() {
+        let cli = Cli::try_parse_from([
+            "magiclip",
+            "-c",
+            "clips",
+            "--range",
+            "12:03.5-12:08",
+            "--source",
+            "v.mkv",
+        ])
+        .unwrap();
+        let Command::Clip(clip) = cli.command() else {
+            panic!("expected clip");
+        };
+        assert_eq!(
+            clip.range,
+            Some(super::TimeRange {
+                start: 723_500,
+                end: 728_000
+            })
+        );
+
+        let parse = |range| Cli::try_parse_from(["magiclip", "-c", "c", "--range", range]);
+        assert!(parse("1-2").is_err(), "requires --source");
+        let parse = |range| {
+            Cli::try_parse_from(["magiclip", "-c", "c", "--range", range, "--source", "v.mkv"])
+        };
+        assert!(parse("8-3").is_err());
+        assert!(parse("8").is_err());
+    }
+
+    #[test]
+    fn fps_correction() {
+        let parse = super::parse_fps_correction;
+        let pal = FpsCorrection::new((24000, 1001), (25, 1));
+        assert_eq!(parse("24000/1001:25").ok(), pal);
+        assert_eq!(
+            parse("23.976:25").ok(),
+            FpsCorrection::new((23976, 1000), (25, 1))
+        );
+        assert!(parse("25").is_err());
+        assert!(parse("25:x").is_err());
+        assert!(parse("0:25").is_err());
+        assert!(parse("25:1/0").is_err());
+    }
+
+    #[test]
+    fn search_only() {
+        let cli = Cli::try_parse_from(["magiclip", "-c", "clips"]).unwrap();
+        let Command::Clip(clip) = cli.command() else {
+            panic!("expected clip");
+        };
+        assert!(clip.scan.is_empty());
+
+        let cli = Cli::try_parse_from(["magiclip", "-c", "clips", "--paths-from", "-"]).unwrap();
+        let Command::Clip(clip) = cli.command() else {
+            panic!("expected clip");
+        };
+        assert!(!clip.scan.is_empty());
+    }
+
+    #[test]
+    fn log_level() {
+        use log::LevelFilter;
+
+        let level = |args: &[&str]| {
+            Cli::try_parse_from(["magiclip", "list"].iter().chain(args))
+                .unwrap()
+                .log_level()
+        };
+        assert_eq!(level(&[]), LevelFilter::Info);
+        assert_eq!(level(&["-v"]), LevelFilter::Debug);
+        assert_eq!(level(&["-vvv"]), LevelFilter::Trace);
+        assert_eq!(level(&["-q"]), LevelFilter::Warn);
+        assert_eq!(level(&["-q", "-q"]), LevelFilter::Error);
+        assert!(Cli::try_parse_from(["magiclip", "list", "-v", "-q"]).is_err());
+    }
+
+    #[test]
+    fn clip_requires_clip_dir() {
+        assert!(Cli::try_parse_from(["magiclip", "videos"]).is_err());
+        assert!(Cli::try_parse_from(["magiclip", "clip", "videos"]).is_err());
+    }
 }
@@ -0,0 +1,323 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context as _, Result};
+use itertools::Itertools as _;
+
+use crate::util;
+
+/// Overrides where the config file is read from.
+pub const CONFIG_ENV: &str = "MAGICLIP_CONFIG";
+
+/// Defaults for the command line options, from `magiclip/config.toml` in the
+/// config dir (see `util::config_dir()`). Keys are long option names (`-` or
+/// `_` both work), e.g.
+///
+/// ```toml
+/// clip-dir = "~/clips"
+/// profile = "av1"
+/// exclude = ["sample", "extras"]
+/// ```
+///
+/// Options given on the command line win. Only flat keys with strings, numbers,
+/// booleans and arrays of those are supported, no tables.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    /// option name and its values (several for arrays)
+    values: Vec<(String, Vec<String>)>,
+}
+
+impl Config {
+    /// Reads the config file named by `$MAGICLIP_CONFIG`, or else the default
+    /// one if it exists.
+    pub fn load() -> Result<Self> {
+        let (path, required) = match std::env::var_os(CONFIG_ENV) {
+            Some(path) => (PathBuf::from(path), true),
+            None => match util::config_dir() {
+                Some(dir) => (dir.join("magiclip").join("config.toml"), false),
+                None => return Ok(Self::default()),
+            },
+        };
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self::default())
+            }
+            Err(e) => return Err(e).with_context(|| format!("reading config {path:?}")),
+        };
+        Self::parse(&content).with_context(|| format!("parsing config {path:?}"))
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        Ok(Self {
+            values: parse_flat(content)?,
+        })
+    }
+
+    /// Makes the config's values the defaults of the matching options of
+    /// `command` and all of its subcommands. Keys not naming any option are an
+    /// error, so typos don't go unnoticed.
+    pub fn apply(&self, command: clap::Command) -> Result<clap::Command> {
+        let mut known = Vec::new();
+        collect_option_names(&command, &mut known);
+        let unknown = self
+            .values
+            .iter()
+            .map(|(key, _)| key)
+            .filter(|key| !known.contains(key))
+            .collect_vec();
+        if !unknown.is_empty() {
+            bail!("unknown options in config: {}", unknown.iter().join(", "));
+        }
+        Ok(self.apply_defaults(command))
+    }
+
+    fn apply_defaults(&self, mut command: clap::Command) -> clap::Command {
+        for (key, values) in &self.values {
+            let ids = command
+                .get_arguments()
+                .filter(|arg| option_name(arg) == *key)
+                .map(|arg| arg.get_id().clone())
+                .collect_vec();
+            for id in ids {
+                command = command.mut_arg(id, |arg| {
+                    arg.required(false).default_values(values.iter().cloned())
+                });
+            }
+        }
+        let subcommands = command
+            .get_subcommands()
+            .map(|sub| sub.get_name().to_owned())
+            .collect_vec();
+        for name in subcommands {
+            command = command.mut_subcommand(name, |sub| self.apply_defaults(sub));
+        }
+        command
+    }
+}
+
+/// The keys (with `_` as `-`) and values (several for arrays) of the flat TOML
+/// `content`, see `Config`.
+pub fn parse_flat(content: &str) -> Result<Vec<(String, Vec<String>)>> {
+    let mut values: Vec<(String, Vec<String>)> = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let n = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            bail!("line {n}: tables aren't supported, only top-level keys");
+        }
+        let (key, value) = line
+            .split_once('=')
+            .with_context(|| format!("line {n}: expected `key = value`"))?;
+        let key = key.trim();
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            bail!("line {n}: invalid key {key:?}");
+        }
+        let key = key.replace('_', "-");
+        if values.iter().any(|(other, _)| *other == key) {
+            bail!("line {n}: {key:?} given twice");
+        }
+        let value = parse_value(value.trim()).with_context(|| format!("line {n}"))?;
+        values.push((key, value));
+    }
+    Ok(values)
+}
+
+/// Long name of options, id of positionals (e.g. `paths`).
+fn option_name(arg: &clap::Arg) -> String {
+    arg.get_long()
+        .map_or_else(|| arg.get_id().to_string(), ToOwned::to_owned)
+}
+
+fn collect_option_names(command: &clap::Command, names: &mut Vec<String>) {
+    names.extend(command.get_arguments().map(option_name));
+    for sub in command.get_subcommands() {
+        collect_option_names(sub, names);
+    }
+}
+
+fn parse_value(value: &str) -> Result<Vec<String>> {
+    let mut chars = value.chars().peekable();
+    let result = if value.starts_with('[') {
+        chars.next();
+        let mut items = Vec::new();
+        loop {
+            skip_whitespace(&mut chars);
+            if chars.next_if_eq(&']').is_some() {
+                break;
+            }
+            items.push(parse_scalar(&mut chars)?);
+            skip_whitespace(&mut chars);
+            match chars.next() {
+                Some(',') => {}
+                Some(']') => break,
+                _ => bail!("expected `,` or `]` in array (arrays can't span lines)"),
+            }
+        }
+        items
+    } else {
+        vec![parse_scalar(&mut chars)?]
+    };
+    skip_whitespace(&mut chars);
+    match chars.next() {
+        None | Some('#') => Ok(result),
+        Some(c) => bail!("unexpected {c:?} after value"),
+    }
+}
+
+fn skip_whitespace(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+/// A string (basic `"…"` or literal `'…'`), number or boolean.
+fn parse_scalar(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String> {
+    let mut result = String::new();
+    match chars.peek() {
+        Some('"') => {
+            chars.next();
+            loop {
+                match chars.next().context("unterminated string")? {
+                    '"' => break,
+                    '\\' => result.push(match chars.next().context("unterminated string")? {
+                        'n' => '\n',
+                        't' => '\t',
+                        '"' => '"',
+                        '\\' => '\\',
+                        c => bail!("unsupported escape `\\{c}`"),
+                    }),
+                    c => result.push(c),
+                }
+            }
+        }
+        Some('\'') => {
+            chars.next();
+            loop {
+                match chars.next().context("unterminated string")? {
+                    '\'' => break,
+                    c => result.push(c),
+                }
+            }
+        }
+        _ => {
+            while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || "+-._".contains(*c))
+            {
+                result.push(c);
+            }
+            let is_number = result.starts_with(|c: char| c.is_ascii_digit() || "+-".contains(c));
+            if !is_number && result != "true" && result != "false" {
+                bail!("expected a quoted string, number or boolean, got {result:?}");
+            }
+            // TOML allows `1_000`
+            result.retain(|c| c != '_');
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+    use clap::{CommandFactory, FromArgMatches as _};
+
+    use super::Config;
+    use crate::cli::{Cli, Command};
+
+    fn config(values: &[(&str, &[&str])]) -> Config {
+        Config {
+            values: values
+                .iter()
+                .map(|(key, values)| {
+                    (
+                        (*key).to_owned(),
+                        values.iter().map(|v| (*v).to_owned()).collect(),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn parse() -> Result<()> {
+        let parsed = Config::parse(
+            r#"
+            # comment
+            clip_dir = "~/clips" # trailing comment
+            db-file = 'C:\magiclip\db.json'
+            pad-start = 1_500
+            nice = -5
+            dedup = true
+            exclude = ["sample", 'extras/**', ]
+            paths = []
+            "#,
+        )?;
+        assert_eq!(
+            parsed,
+            config(&[
+                ("clip-dir", &["~/clips"]),
+                ("db-file", &[r"C:\magiclip\db.json"]),
+                ("pad-start", &["1500"]),
+                ("nice", &["-5"]),
+                ("dedup", &["true"]),
+                ("exclude", &["sample", "extras/**"]),
+                ("paths", &[]),
+            ])
+        );
+
+        for invalid in [
+            "[clip]",
+            "clip-dir",
+            "clip-dir = clips",
+            "clip-dir = \"clips",
+            "exclude = [\"a\" \"b\"]",
+            "a.b = 1",
+            "nice = 1\nnice = 2",
+        ] {
+            assert!(Config::parse(invalid).is_err(), "{invalid}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn apply() -> Result<()> {
+        let parse = |config: &Config, args: &[&str]| -> Result<Cli> {
+            let matches = config
+                .apply(<Cli as CommandFactory>::command())?
+                .try_get_matches_from(std::iter::once("magiclip").chain(args.iter().copied()))?;
+            Ok(Cli::from_arg_matches(&matches)?)
+        };
+        let typo = config(&[("clip-dri", &["x"])]);
+        let config = config(&[
+            ("clip-dir", &["from-config"]),
+            ("db-file", &["config.json"]),
+            ("exclude", &["sample", "extras"]),
+            ("dedup", &["true"]),
+        ]);
+
+        let Command::Clip(clip) = parse(&config, &[])?.command() else {
+            panic!("clip is the default");
+        };
+        assert_eq!(clip.clip_dir, Some("from-config".into()));
+        assert_eq!(clip.scan.exclude, ["sample", "extras"]);
+        assert!(clip.scan.dedup);
+
+        let cli = parse(&config, &["scan", "--exclude", "x", "-d", "cli.json"])?;
+        assert_eq!(cli.db_file, std::path::PathBuf::from("cli.json"));
+        let Command::Scan(scan) = cli.command() else {
+            panic!("not scan");
+        };
+        assert_eq!(scan.exclude, ["x"]);
+        assert!(scan.dedup);
+
+        assert!(Config::default()
+            .apply(<Cli as CommandFactory>::command())
+            .is_ok_and(|command| command.try_get_matches_from(["magiclip"]).is_err()));
+        assert!(typo.apply(<Cli as CommandFactory>::command()).is_err());
+        Ok(())
+    }
+}
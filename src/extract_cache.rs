@@ -0,0 +1,151 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{Context as _, Result};
+use itertools::Itertools as _;
+use log::warn;
+
+use crate::sub::db::Fingerprint;
+
+/// Name of the cache dir, next to the DB file.
+pub const EXTRACT_CACHE_DIRNAME: &str = "extract-cache";
+
+/// Sub streams extracted from videos, by the videos' `Fingerprint`, so
+/// re-indexing a video whose content didn't change (only its timestamps or
+/// tags did) skips ffmpeg. Laid out as `{fingerprint}/{stream}.srt`.
+#[derive(Debug, Clone)]
+pub struct ExtractCache {
+    dir: PathBuf,
+}
+
+impl ExtractCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn file(&self, fingerprint: Fingerprint, stream: usize) -> PathBuf {
+        self.dir
+            .join(fingerprint.to_string())
+            .join(format!("{stream}.srt"))
+    }
+
+    /// The cached extraction of `stream` of the video, if any. Marks it as
+    /// recently used.
+    pub fn get(&self, fingerprint: Fingerprint, stream: usize) -> Option<PathBuf> {
+        let file = self.file(fingerprint, stream);
+        // a failed touch just makes it go sooner
+        let _ = File::options()
+            .append(true)
+            .open(&file)
+            .and_then(|f| f.set_modified(SystemTime::now()));
+        file.is_file().then_some(file)
+    }
+
+    /// Copies `extracted` (of `stream` of the video) into the cache.
+    pub fn put(&self, fingerprint: Fingerprint, stream: usize, extracted: &Path) -> Result<()> {
+        let file = self.file(fingerprint, stream);
+        let dir = file.parent().expect("[ASSERT] cache files are in a dir");
+        std::fs::create_dir_all(dir).with_context(|| format!("creating {dir:?}"))?;
+        // via a temp file, so concurrent readers never see half of it
+        let temp = tempfile::NamedTempFile::new_in(dir)?;
+        std::fs::copy(extracted, temp.path())
+            .with_context(|| format!("copying {extracted:?} into {dir:?}"))?;
+        temp.persist(&file)
+            .with_context(|| format!("persisting {file:?}"))?;
+        Ok(())
+    }
+
+    /// Removes the least recently used videos' extractions until the cache
+    /// takes up at most `max_bytes`. Returns how many videos' got removed.
+    pub fn prune(&self, max_bytes: u64) -> Result<usize> {
+        let dirs = match std::fs::read_dir(&self.dir) {
+            Ok(dirs) => dirs,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e).with_context(|| format!("reading {dir:?}", dir = self.dir)),
+        };
+        let mut videos = Vec::new();
+        for dir in dirs {
+            let dir = dir?.path();
+            let is_key = dir
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(Fingerprint::parse)
+                .is_some();
+            if !is_key || !dir.is_dir() {
+                warn!("Skipping {dir:?} in the extract cache, it's not a video's dir");
+                continue;
+            }
+            let (mut size, mut used) = (0, SystemTime::UNIX_EPOCH);
+            for file in std::fs::read_dir(&dir)? {
+                let meta = file?.metadata()?;
+                size += meta.len();
+                used = used.max(meta.modified()?);
+            }
+            videos.push((used, size, dir));
+        }
+        let mut total = videos.iter().map(|(_, size, _)| size).sum::<u64>();
+        let mut removed = 0;
+        for (_, size, dir) in videos.into_iter().sorted() {
+            if total <= max_bytes {
+                break;
+            }
+            std::fs::remove_dir_all(&dir).with_context(|| format!("removing {dir:?}"))?;
+            total -= size;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use anyhow::Result;
+    use tempfile::TempDir;
+
+    use super::ExtractCache;
+    use crate::sub::db::Fingerprint;
+
+    #[test]
+    fn get_put_prune() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let video = |name: &str, content: &str| -> Result<Fingerprint> {
+            let path = temp_dir.path().join(name);
+            std::fs::write(&path, content)?;
+            Fingerprint::of(&path)
+        };
+        let (old, new) = (video("old.mkv", "old")?, video("new.mkv", "new!")?);
+        let extracted = temp_dir.path().join("0.srt");
+        std::fs::write(&extracted, "1\n00:00:01,000 --> 00:00:02,000\nHi\n")?;
+        let cache = ExtractCache::new(temp_dir.path().join("cache"));
+
+        assert_eq!(cache.get(old, 0), None);
+        assert_eq!(cache.prune(0)?, 0);
+        cache.put(old, 0, &extracted)?;
+        cache.put(new, 0, &extracted)?;
+        cache.put(new, 1, &extracted)?;
+        let cached = cache.get(old, 0).unwrap();
+        assert_eq!(std::fs::read(cached)?, std::fs::read(&extracted)?);
+        assert_eq!(cache.get(old, 1), None);
+
+        let srt_len = std::fs::metadata(&extracted)?.len();
+        std::fs::File::options()
+            .append(true)
+            .open(cache.file(old, 0))?
+            .set_modified(SystemTime::now() - Duration::from_mins(1))?;
+        assert_eq!(cache.prune(3 * srt_len)?, 0);
+        assert_eq!(cache.prune(2 * srt_len)?, 1);
+        assert_eq!(cache.get(old, 0), None);
+        assert!(cache.get(new, 1).is_some());
+
+        // strays get left alone
+        std::fs::write(temp_dir.path().join("cache").join("notes.txt"), "mine")?;
+        assert_eq!(cache.prune(0)?, 1);
+        assert!(temp_dir.path().join("cache").join("notes.txt").is_file());
+        Ok(())
+    }
+}
@@ -1,32 +1,292 @@
 use std::{
+    borrow::Cow,
     collections::HashMap,
-    io::BufRead,
+    io::{BufRead as _, BufReader, Read as _},
     ops::Not as _,
     path::{Path, PathBuf},
-    process::Command,
-    sync::LazyLock,
+    process::{Command, ExitStatus, Output, Stdio},
+    sync::{
+        atomic::{AtomicI32, AtomicU32, Ordering},
+        LazyLock,
+    },
+    time::Duration,
 };
 
-use anyhow::{ensure, Result};
+use anyhow::{bail, ensure, Context as _, Result};
 use itertools::Itertools as _;
-use scopeguard::ScopeGuard;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
 use srtlib::Timestamp;
 
+use crate::{sub::Subtitle, util};
+
 pub static VIDEO_EXTS: [&str; 7] = ["mp4", "mkv", "m4v", "wmv", "avi", "flv", "webm"];
 
+/// Subtitle files ffmpeg can read, see `--loose-subs`.
+pub static SUB_EXTS: [&str; 3] = ["srt", "ass", "vtt"];
+
+/// Niceness of the processes `command()` spawns, 0 to leave them be.
+static CHILD_NICENESS: AtomicI32 = AtomicI32::new(0);
+
+/// See `--nice`. Applies to processes spawned from now on.
+pub fn set_child_niceness(niceness: i32) {
+    if niceness != 0 && !cfg!(unix) {
+        warn!("Ignoring niceness {niceness}, `nice` only exists on unix");
+        return;
+    }
+    CHILD_NICENESS.store(niceness, Ordering::Relaxed);
+}
+
+/// How often `run()` retries transient failures.
+static RETRIES: AtomicU32 = AtomicU32::new(0);
+
+/// Wait before the first retry, doubling with each further one.
+const RETRY_BACKOFF: Duration = Duration::from_millis(250);
+
+/// See `--retries`.
+pub fn set_retries(retries: u32) {
+    RETRIES.store(retries, Ordering::Relaxed);
+}
+
+/// `Command::new(program)`, but run under `nice` if `set_child_niceness()` was
+/// called.
+fn command(program: &str) -> Command {
+    match CHILD_NICENESS.load(Ordering::Relaxed) {
+        0 => Command::new(program),
+        niceness => {
+            let mut command = Command::new("nice");
+            command.args(["-n", &niceness.to_string(), program]);
+            command
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, clap::ValueEnum, strum::Display)]
-#[allow(clippy::upper_case_acronyms)]
+#[allow(clippy::upper_case_acronyms, non_camel_case_types)]
 pub enum EncodingProfile {
     AV1,
+    AV1_NVENC,
+    H264_NVENC,
     FLAC,
+    /// Cut without re-encoding: near-instant and lossless, but clips start and
+    /// end on the nearest keyframes, so they're often a few seconds longer.
+    COPY,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct EncodingSettings {
     pub ext: &'static str,
+    /// go before `-i` (e.g. hardware decoding)
+    pub input_params: Vec<(&'static str, &'static str)>,
+    /// go after `-i` (encoding)
     pub params: Vec<(&'static str, &'static str)>,
 }
 
+/// Per-run settings for `clip()` on top of the encoding profile.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClipOptions {
+    pub profile: EncodingProfile,
+    /// overrides the profile's `ext`
+    pub container: Option<String>,
+    /// ms to start the clip before the subtitle
+    pub pad_start: u64,
+    /// ms to end the clip after the subtitle
+    pub pad_end: u64,
+    pub overwrite: Overwrite,
+    /// only keep this audio track (and the first video stream), instead of
+    /// whatever ffmpeg picks
+    pub audio: Option<AudioTrack>,
+    /// where clips get encoded before being moved into place (see
+    /// `util::move_file()`), instead of next to them
+    pub stage_dir: Option<PathBuf>,
+    /// normalize the audio's loudness, see `LOUDNORM_FILTER`
+    pub loudnorm: bool,
+    /// ms to fade video and audio in and out, 0 for none
+    pub fade: u64,
+    /// overrides the profile's CRF (CQ for NVENC), see `takes_quality()`
+    pub crf: Option<u8>,
+    /// overrides the profile's encoder preset (`p{n}` for NVENC), see
+    /// `takes_quality()`
+    pub preset: Option<u8>,
+    /// overrides the profile's subtitle codec, set by `clip()` where copying
+    /// the input's subtitles won't do, see `copied_sub_codec()`
+    pub sub_codec: Option<&'static str>,
+}
+
+/// EBU R128 loudness normalization to -16 LUFS, which suits speech.
+const LOUDNORM_FILTER: &str = "loudnorm=I=-16:TP=-1.5:LRA=11";
+
+/// An audio track of the input, see `ClipOptions::audio`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AudioTrack {
+    /// first audio stream tagged with this language, e.g. `jpn`
+    Language(String),
+    /// n-th audio stream (0-based, counting only audio streams)
+    Stream(usize),
+}
+
+/// What to do when a clip's output file already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Overwrite {
+    /// fail the clip
+    #[default]
+    Never,
+    /// leave the existing file alone and count the clip as done
+    Skip,
+    Always,
+}
+
+impl EncodingSettings {
+    fn has_video(&self) -> bool {
+        self.params
+            .iter()
+            .any(|(k, codec)| *k == "-c:v" && *codec != "none")
+    }
+
+    /// Whether streams get copied rather than re-encoded, see
+    /// `EncodingProfile::COPY`.
+    pub fn is_copy(&self) -> bool {
+        self.params
+            .iter()
+            .any(|(k, codec)| k.starts_with("-c:") && *codec == "copy")
+    }
+}
+
+impl EncodingProfile {
+    pub fn settings(self) -> &'static EncodingSettings {
+        ENCODING_PROFILES
+            .get(&self)
+            .expect("[ASSERT] not all encoding profiles covered")
+    }
+}
+
+impl ClipOptions {
+    pub fn settings(&self) -> &'static EncodingSettings {
+        self.profile.settings()
+    }
+
+    pub fn ext(&self) -> &str {
+        self.container.as_deref().unwrap_or(self.settings().ext)
+    }
+
+    /// `-map` args for `_clip()`, empty if ffmpeg should pick the streams.
+    /// `clip()` resolves `audio` to a `Stream` first, as mapping by language
+    /// would map every stream of it.
+    fn map_args(&self) -> Vec<String> {
+        let Some(audio) = &self.audio else {
+            return Vec::new();
+        };
+        let mut args = Vec::new();
+        if self.settings().has_video() {
+            args.extend(["-map".to_owned(), "0:v:0".to_owned()]);
+        }
+        args.push("-map".to_owned());
+        args.push(match audio {
+            AudioTrack::Stream(n) => format!("0:a:{n}"),
+            AudioTrack::Language(_) => panic!("[ASSERT] audio language resolved by clip()"),
+        });
+        args
+    }
+
+    /// Identifies the options making a difference to the clip's content
+    /// (padding aside, which changes its window), for the manifest.
+    pub fn output_hash(&self) -> u64 {
+        let content = format!(
+            "{:?}",
+            (
+                self.profile,
+                &self.container,
+                &self.audio,
+                self.loudnorm,
+                self.fade,
+                self.crf,
+                self.preset
+            )
+        );
+        util::fnv1a(content.as_bytes())
+    }
+
+    /// Whether `crf` and `preset` apply, i.e. the profile encodes video.
+    pub fn takes_quality(&self) -> bool {
+        self.settings().has_video() && !self.settings().is_copy()
+    }
+
+    /// The profile's `params` with `crf` and `preset` applied.
+    fn params(&self) -> Vec<(&'static str, Cow<'static, str>)> {
+        self.settings()
+            .params
+            .iter()
+            .map(|&(k, v)| {
+                let v = match (k, self.crf, self.preset) {
+                    ("-crf:v" | "-cq:v", Some(crf), _) => Cow::Owned(crf.to_string()),
+                    // NVENC presets are p1 (fastest) to p7
+                    ("-preset:v", _, Some(preset)) if v.starts_with('p') => {
+                        Cow::Owned(format!("p{preset}"))
+                    }
+                    ("-preset:v", _, Some(preset)) => Cow::Owned(preset.to_string()),
+                    ("-c:s", ..) => Cow::Borrowed(self.sub_codec.unwrap_or(v)),
+                    _ => Cow::Borrowed(v),
+                };
+                (k, v)
+            })
+            .collect()
+    }
+
+    /// Filter args for `_clip()` of a clip lasting `duration_ms`, to go before
+    /// the codec params. Each stream gets a single chain of all its filters.
+    fn filter_args(&self, duration_ms: u64) -> Vec<String> {
+        let mut video = Vec::new();
+        let mut audio = Vec::new();
+        if self.loudnorm {
+            audio.push(LOUDNORM_FILTER.to_owned());
+        }
+        if self.fade > 0 {
+            // the input is seeked, so the clip starts at 0. Fades mustn't overlap.
+            let fade = self.fade.min(duration_ms / 2);
+            let (fade, out) = (
+                millis_to_seconds(fade),
+                millis_to_seconds(duration_ms - fade),
+            );
+            video.push(format!(
+                "fade=t=in:st=0:d={fade},fade=t=out:st={out}:d={fade}"
+            ));
+            audio.push(format!(
+                "afade=t=in:st=0:d={fade},afade=t=out:st={out}:d={fade}"
+            ));
+        }
+
+        let mut args = Vec::new();
+        if !video.is_empty() && self.settings().has_video() {
+            args.extend(["-vf".to_owned(), video.join(",")]);
+        }
+        if !audio.is_empty() {
+            args.extend(["-af".to_owned(), audio.join(",")]);
+        }
+        if self.loudnorm {
+            // loudnorm upsamples to 192 kHz, which opus can't take
+            args.extend(["-ar".to_owned(), "48000".to_owned()]);
+        }
+        args
+    }
+
+    /// How far clips' lengths may be off, see `verify_clip()`.
+    fn duration_tolerance(&self) -> u64 {
+        if self.settings().is_copy() {
+            COPY_DURATION_TOLERANCE
+        } else {
+            CLIP_DURATION_TOLERANCE
+        }
+    }
+
+    /// The clip file for `basename`, i.e. with the extension added.
+    pub fn outfile(&self, basename: &Path) -> PathBuf {
+        let mut outfile = basename.to_owned().into_os_string();
+        outfile.push(".");
+        outfile.push(self.ext());
+        outfile.into()
+    }
+}
+
 static ENCODING_PROFILES: LazyLock<HashMap<EncodingProfile, EncodingSettings>> =
     LazyLock::new(|| {
         vec![
@@ -34,6 +294,7 @@ static ENCODING_PROFILES: LazyLock<HashMap<EncodingProfile, EncodingSettings>> =
                 EncodingProfile::AV1,
                 EncodingSettings {
                     ext: "mkv",
+                    input_params: vec![],
                     params: vec![
                         ("-c:v", "libsvtav1"),
                         ("-crf:v", "10"),
@@ -48,159 +309,1255 @@ static ENCODING_PROFILES: LazyLock<HashMap<EncodingProfile, EncodingSettings>> =
                     ],
                 },
             ),
+            (
+                EncodingProfile::AV1_NVENC,
+                EncodingSettings {
+                    ext: "mkv",
+                    input_params: vec![("-hwaccel", "cuda")],
+                    params: vec![
+                        ("-c:v", "av1_nvenc"),
+                        ("-preset:v", "p6"),
+                        ("-cq:v", "24"),
+                        ("-c:a", "libopus"),
+                        ("-b:a", "92k"),
+                        ("-ac", "2"),
+                    ],
+                },
+            ),
+            (
+                EncodingProfile::H264_NVENC,
+                EncodingSettings {
+                    ext: "mp4",
+                    input_params: vec![("-hwaccel", "cuda")],
+                    params: vec![
+                        ("-c:v", "h264_nvenc"),
+                        ("-preset:v", "p6"),
+                        ("-cq:v", "20"),
+                        ("-c:a", "aac"),
+                        ("-b:a", "160k"),
+                        ("-ac", "2"),
+                    ],
+                },
+            ),
             (
                 EncodingProfile::FLAC,
                 EncodingSettings {
                     ext: "flac",
+                    input_params: vec![],
                     params: vec![("-c:v", "none"), ("-c:a", "flac"), ("-ac", "2")],
                 },
             ),
+            (
+                // `_clip()` seeks in the input, which for copying lands on the
+                // keyframe before the start
+                EncodingProfile::COPY,
+                EncodingSettings {
+                    ext: "mkv",
+                    input_params: vec![],
+                    params: vec![
+                        ("-c:v", "copy"),
+                        ("-c:a", "copy"),
+                        ("-c:s", "copy"),
+                        // timestamps start at the keyframe, not before it
+                        ("-avoid_negative_ts", "make_zero"),
+                    ],
+                },
+            ),
         ]
         .into_iter()
         .collect()
     });
 
-pub fn _get_sub_files_in_dir(
-    p: impl AsRef<Path>,
-    output_dir_fn: impl Fn(&Path) -> PathBuf + Copy,
+/// Containers known to be able to hold `codec`. `None` for codecs we know
+/// nothing about.
+fn codec_containers(codec: &str) -> Option<&'static [&'static str]> {
+    match codec {
+        "libsvtav1" | "av1_nvenc" => Some(&["mkv", "webm", "mp4"]),
+        "h264_nvenc" => Some(&["mkv", "mp4", "mov"]),
+        "libopus" => Some(&["mkv", "webm", "mp4", "ogg", "opus"]),
+        "aac" => Some(&["mkv", "mp4", "mov", "m4a"]),
+        "flac" => Some(&["flac", "mkv", "mp4", "ogg"]),
+        _ => None,
+    }
+}
+
+/// Returns the codecs of `profile` which are known to not fit into `container`.
+pub fn incompatible_codecs(profile: EncodingProfile, container: &str) -> Vec<&'static str> {
+    profile
+        .settings()
+        .params
+        .iter()
+        .filter(|(k, _)| k.starts_with("-c:"))
+        .map(|(_, codec)| *codec)
+        .filter(|codec| {
+            codec_containers(codec).is_some_and(|containers| !containers.contains(&container))
+        })
+        .collect()
+}
+
+/// Extracts the sub streams of `video` into `output_dir` as
+/// `{video_stem}.{stream}.{lang}.srt`. Image-based streams are OCR'd if `ocr`,
+/// otherwise skipped, OCR scratch files going to `temp_dir` (or the system's).
+/// Streams failing to extract get logged and skipped, too.
+pub fn extract_sub_files(
+    video: &Path,
+    output_dir: &Path,
+    ocr: bool,
+    temp_dir: Option<&Path>,
 ) -> Result<Vec<PathBuf>> {
-    let output_dir = output_dir_fn(p.as_ref());
-    Ok(match p.as_ref() {
-        t if t.is_dir() => std::fs::read_dir(p)?
-            .flat_map(|entry| {
-                let entry = entry?;
-                _get_sub_files_in_dir(entry.path(), output_dir_fn)
+    let stem = video
+        .file_stem()
+        .with_context(|| format!("no file name in {video:?}"))?
+        .to_string_lossy();
+    let streams = probe_sub_streams(video)?;
+    std::fs::create_dir_all(output_dir).with_context(|| format!("creating dir {output_dir:?}"))?;
+    let temp_dir = util::temp_dir(temp_dir)?;
+
+    let mut files = Vec::new();
+    for (i, stream) in streams.iter().enumerate() {
+        let language = stream.tags.language.as_deref().unwrap_or("und");
+        let outfile = output_dir.join(format!("{stem}.{i}.{language}.srt"));
+        let extracted = if !stream.is_image_sub() {
+            extract_sub_stream_to(video, i, &outfile)
+        } else if ocr {
+            ocr_sub_stream(video, i, temp_dir.path()).and_then(|srt| {
+                std::fs::copy(&srt, &outfile)?;
+                Ok(())
             })
-            .flatten()
-            .collect_vec(),
-        t if t.is_symlink() => vec![],
-        t if t.is_file() => extract_sub_files(p, output_dir.clone().as_path())?,
-        _ => vec![],
-    })
+        } else {
+            warn!("Skipping sub stream {i} of {video:?}, it's image-based (pass --ocr)");
+            continue;
+        };
+        match extracted {
+            Ok(()) => files.push(outfile),
+            Err(e) => warn!("Extracting sub stream {i} of {video:?} failed: {e:#}"),
+        }
+    }
+    Ok(files)
+}
 
-    //Ok(Vec::<PathBuf>::new())
+/// Extracts the `i`-th sub stream of `path` into `output_dir` as SRT.
+pub fn extract_sub_stream(path: &Path, i: usize, output_dir: &Path) -> Result<PathBuf> {
+    let outfile = output_dir.join(format!("{i}.srt"));
+    extract_sub_stream_to(path, i, &outfile)?;
+    Ok(outfile)
+}
 
-    //Command::new("ffmpeg").args(["-i"])
+/// The `i`-th sub stream of `path` as SRT, piped from ffmpeg instead of going
+/// through a file.
+pub fn read_sub_stream(path: &Path, i: usize) -> Result<Vec<u8>> {
+    let out = run(command("ffmpeg")
+        .args(["-i", &path.to_string_lossy(), "-map"])
+        .arg(format!("0:s:{i}"))
+        .args(["-f", "srt", "pipe:1"]))?;
+    Ok(out.stdout)
 }
 
-pub fn extract_sub_files(
-    path: impl AsRef<Path>,
-    output_dir: impl AsRef<Path>,
-) -> Result<Vec<PathBuf>> {
-    Ok((0..(how_many_subs(path.as_ref())?))
-        .flat_map(|i| {
-            let outfile = output_dir.as_ref().to_path_buf().join(format!("{i}.srt"));
-            if outfile.exists().not() {
-                let out = Command::new("ffmpeg")
-                    .args(["-i", &path.as_ref().to_string_lossy(), "-map"])
-                    .arg(format!("0:s:{i}"))
-                    .args(["-f", "srt"])
-                    .arg(&outfile)
-                    .output()?;
-
-                ensure!(out.status.success());
-            }
+/// Extracts the `i`-th sub stream of `path` to `outfile` as SRT, unless that
+/// exists already.
+fn extract_sub_stream_to(path: &Path, i: usize, outfile: &Path) -> Result<()> {
+    if outfile.exists() {
+        return Ok(());
+    }
+    // `-y` for `run()`'s retries, which find what the failed attempt left
+    let result = run(command("ffmpeg")
+        .args(["-i", &path.to_string_lossy(), "-map"])
+        .arg(format!("0:s:{i}"))
+        .args(["-f", "srt", "-y"])
+        .arg(outfile));
+    if result.is_err() {
+        // don't let a partial file count as extracted next time
+        let _ = std::fs::remove_file(outfile);
+    }
+    result.map(|_| ())
+}
+
+/// Bitmap subtitle codecs, which only turn into text via OCR.
+const IMAGE_SUB_CODECS: [&str; 4] = ["dvd_subtitle", "hdmv_pgs_subtitle", "dvb_subtitle", "xsub"];
+
+static TESSERACT_AVAILABLE: LazyLock<bool> = LazyLock::new(|| {
+    Command::new("tesseract")
+        .arg("--version")
+        .output()
+        .is_ok_and(|out| out.status.success())
+});
+
+/// Players `preview_play()` tries, in this order.
+const PLAYERS: [&str; 2] = ["ffplay", "mpv"];
+
+static PLAYER_AVAILABLE: LazyLock<bool> = LazyLock::new(|| {
+    PLAYERS
+        .iter()
+        .zip(["-version", "--version"])
+        .any(|(player, version)| {
+            Command::new(player)
+                .arg(version)
+                .output()
+                .is_ok_and(|out| out.status.success())
+        })
+});
+
+/// Whether `preview_play()` has anything to play with.
+pub fn player_available() -> bool {
+    *PLAYER_AVAILABLE
+}
+
+/// Plays `start` to `end` (in ms) of `video` with ffplay (or mpv, if there's no
+/// ffplay), seeking right there, without writing anything.
+pub fn preview_play(video: &Path, start: u64, end: u64) -> Result<()> {
+    let (start, duration) = (
+        millis_to_string(start),
+        millis_to_string(end.saturating_sub(start)),
+    );
+    let ffplay = Command::new(PLAYERS[0])
+        .args([
+            "-autoexit",
+            "-loglevel",
+            "error",
+            "-ss",
+            &start,
+            "-t",
+            &duration,
+        ])
+        .arg(video)
+        .status();
+    let status = match ffplay {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Command::new(PLAYERS[1])
+            .args([format!("--start={start}"), format!("--length={duration}")])
+            .arg(video)
+            .status()
+            .with_context(|| format!("neither of {PLAYERS:?} could be run"))?,
+        status => status.with_context(|| format!("trying to run {}", PLAYERS[0]))?,
+    };
+    ensure!(status.success(), "playing {video:?} failed ({status})");
+    Ok(())
+}
+
+/// Longest an image sub is shown when its packet has no duration (`VobSub`).
+const OCR_MAX_EVENT_MILLIS: u64 = 10_000;
+
+/// Turns the image-based `i`-th sub stream of `path` into an SRT in
+/// `output_dir` by rendering each subtitle onto a black frame and running
+/// `tesseract` on it. Slow, as it runs ffmpeg and tesseract once per subtitle.
+pub fn ocr_sub_stream(path: &Path, i: usize, output_dir: &Path) -> Result<PathBuf> {
+    ensure!(
+        *TESSERACT_AVAILABLE,
+        "tesseract isn't available, skipping OCR of sub stream {i}"
+    );
+    let outfile = output_dir.join(format!("{i}.srt"));
+    let frame = output_dir.join(format!("{i}.png"));
+
+    let out = run(command("ffprobe")
+        .args(["-v", "error", "-select_streams"])
+        .arg(format!("s:{i}"))
+        .args([
+            "-show_entries",
+            "packet=pts_time,duration_time",
+            "-of",
+            "json",
+        ])
+        .arg(path.as_os_str()))?;
+    let packets = serde_json::from_slice::<ProbePackets>(&out.stdout)
+        .context("parsing ffprobe packets")?
+        .packets;
+
+    let mut subs = Vec::new();
+    for (start, end) in sub_events(&packets) {
+        let at = start + (end - start) / 2;
+        // fast input seeking to a bit before, then exact output seeking, so the
+        // subtitle packet (which started earlier) is still decoded
+        let seek = at.saturating_sub(2000);
+        run(command("ffmpeg")
+            .args(["-v", "error", "-ss", &millis_to_string(seek), "-i"])
+            .arg(path.as_os_str())
+            .arg("-filter_complex")
+            .arg(format!(
+                "[0:v:0]drawbox=c=black:t=fill[bg];[bg][0:s:{i}]overlay[out]"
+            ))
+            .args(["-map", "[out]", "-ss", &millis_to_string(at - seek)])
+            .args(["-frames:v", "1", "-y"])
+            .arg(&frame))?;
+
+        let out = run(command("tesseract").arg(&frame).arg("stdout"))?;
+        let text = String::from_utf8_lossy(&out.stdout)
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .join("\n");
+        // the blank packets clearing the screen
+        if text.is_empty() {
+            continue;
+        }
+        subs.push(srtlib::Subtitle::new(
+            subs.len() + 1,
+            millis_to_timestamp(start)?,
+            millis_to_timestamp(end)?,
+            text,
+        ));
+    }
+
+    srtlib::Subtitles::new_from_vec(subs)
+        .write_to_file(&outfile, None)
+        .with_context(|| format!("writing {outfile:?}"))?;
+    Ok(outfile)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+struct ProbePackets {
+    #[serde(default)]
+    packets: Vec<ProbePacket>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+struct ProbePacket {
+    pts_time: Option<String>,
+    duration_time: Option<String>,
+}
+
+/// Start and end (in ms) of each subtitle shown by `packets`. Packets without
+/// a duration last until the next one.
+fn sub_events(packets: &[ProbePacket]) -> Vec<(u64, u64)> {
+    let starts = packets
+        .iter()
+        .map(|packet| packet.pts_time.as_deref().and_then(seconds_to_millis))
+        .collect_vec();
+    packets
+        .iter()
+        .zip(&starts)
+        .enumerate()
+        .filter_map(|(i, (packet, start))| {
+            let start = (*start)?;
+            let duration = packet
+                .duration_time
+                .as_deref()
+                .and_then(seconds_to_millis)
+                .filter(|duration| *duration > 0);
+            let next = starts[i + 1..].iter().flatten().next().copied();
+            let end = match (duration, next) {
+                (Some(duration), _) => start + duration,
+                (None, Some(next)) => next.min(start + OCR_MAX_EVENT_MILLIS),
+                (None, None) => start + OCR_MAX_EVENT_MILLIS,
+            };
+            (end > start).then_some((start, end))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+struct ProbeChapters {
+    #[serde(default)]
+    chapters: Vec<ProbeChapter>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+struct ProbeChapter {
+    start_time: String,
+    end_time: String,
+    #[serde(default)]
+    tags: ProbeChapterTags,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+struct ProbeChapterTags {
+    title: Option<String>,
+}
+
+/// A chapter of a video (e.g. Matroska's), see `probe_chapters()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chapter {
+    /// 1-based, in the order of the file
+    pub number: usize,
+    pub title: Option<String>,
+    /// in ms
+    pub start: u64,
+    /// in ms
+    pub end: u64,
+}
+
+impl Chapter {
+    /// The title, or `Chapter N` for untitled ones.
+    pub fn name(&self) -> String {
+        self.title
+            .clone()
+            .unwrap_or_else(|| format!("Chapter {n}", n = self.number))
+    }
+}
 
-            Ok(outfile)
+/// Probes the chapters of `path`, empty if it has none.
+pub fn probe_chapters(path: impl AsRef<Path>) -> Result<Vec<Chapter>> {
+    let out = run(command("ffprobe")
+        .args(["-v", "error", "-show_chapters", "-of", "json"])
+        .arg(path.as_ref().as_os_str()))?;
+    parse_chapters(&out.stdout)
+}
+
+fn parse_chapters(json: &[u8]) -> Result<Vec<Chapter>> {
+    let probed: ProbeChapters = serde_json::from_slice(json).context("parsing ffprobe chapters")?;
+    probed
+        .chapters
+        .into_iter()
+        .enumerate()
+        .map(|(i, chapter)| {
+            let time = |time: &str| {
+                seconds_to_millis(time).with_context(|| format!("chapter time {time:?}"))
+            };
+            Ok(Chapter {
+                number: i + 1,
+                title: chapter.tags.title.filter(|title| !title.trim().is_empty()),
+                start: time(&chapter.start_time)?,
+                end: time(&chapter.end_time)?,
+            })
         })
-        .collect_vec())
+        .collect()
+}
+
+/// Parses a user-given `[[HH:]MM:]SS[.fff]` (or `,fff`) into ms.
+pub fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let timestamp = timestamp.trim().replace(',', ".");
+    let parts = timestamp.rsplit(':').collect_vec();
+    if parts.len() > 3 {
+        return None;
+    }
+    let mut millis = seconds_to_millis(parts[0])?;
+    for (part, factor) in parts[1..].iter().zip([60_000, 3_600_000]) {
+        millis += part.parse::<u64>().ok()? * factor;
+    }
+    Some(millis)
+}
+
+/// Parses ffprobe's `12.345000` (seconds) into ms.
+fn seconds_to_millis(seconds: &str) -> Option<u64> {
+    let (whole, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+    let millis = format!("{fraction:0<3}");
+    Some(whole.parse::<u64>().ok()? * 1000 + millis.get(..3)?.parse::<u64>().ok()?)
 }
 
-fn how_many_subs(path: impl AsRef<Path>) -> Result<usize> {
-    let out = Command::new("ffprobe")
-        .args("-v error -show_streams -select_streams s".split(' '))
-        .arg(path.as_ref().as_os_str())
-        .output()?;
+/// The parts of `ffprobe -show_streams [-show_format] -of json` we care about.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+pub struct Probe {
+    #[serde(default)]
+    pub streams: Vec<ProbeStream>,
+    #[serde(default)]
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ProbeStream {
+    /// index among _all_ streams of the file, not just the selected ones
+    pub index: usize,
+    pub codec_name: Option<String>,
+    #[serde(default)]
+    pub tags: ProbeTags,
+    #[serde(default)]
+    pub disposition: ProbeDisposition,
+    /// e.g. `24000/1001`, only meaningful for video streams
+    pub r_frame_rate: Option<String>,
+    /// `video`, `audio`, `subtitle`, …
+    #[serde(default)]
+    pub codec_type: Option<String>,
+}
 
-    ensure!(out.status.success());
+impl ProbeStream {
+    /// Whether the stream is bitmaps instead of text, see `ocr_sub_stream()`.
+    pub fn is_image_sub(&self) -> bool {
+        self.codec_name
+            .as_deref()
+            .is_some_and(|codec| IMAGE_SUB_CODECS.contains(&codec))
+    }
+}
 
-    Ok(out
-        .stdout
-        .lines()
-        .filter(|line| line.as_ref().map_or(false, |s| s.trim() == "[STREAM]"))
-        .count())
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+pub struct ProbeDisposition {
+    #[serde(default)]
+    pub forced: u8,
+    #[serde(default)]
+    pub default: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+pub struct ProbeTags {
+    pub language: Option<String>,
+}
+
+impl Probe {
+    fn parse(json: &[u8]) -> Result<Self> {
+        serde_json::from_slice(json).context("parsing ffprobe output")
+    }
+}
+
+/// What `probe_video()` finds out about a video.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct VideoProbe {
+    /// in the order ffmpeg's `0:s:N` uses
+    pub sub_streams: Vec<ProbeStream>,
+    /// in ms
+    pub duration: Option<u64>,
+    /// of the first video stream
+    pub fps: Option<FrameRate>,
+}
+
+impl From<Probe> for VideoProbe {
+    fn from(probe: Probe) -> Self {
+        let stream_type =
+            |stream: &ProbeStream, wanted: &str| stream.codec_type.as_deref() == Some(wanted);
+        Self {
+            fps: probe
+                .streams
+                .iter()
+                .find(|stream| stream_type(stream, "video"))
+                .and_then(|stream| stream.r_frame_rate.as_deref())
+                .and_then(FrameRate::parse),
+            duration: probe
+                .format
+                .and_then(|format| format.duration)
+                .as_deref()
+                .and_then(seconds_to_millis),
+            sub_streams: probe
+                .streams
+                .into_iter()
+                .filter(|stream| stream_type(stream, "subtitle"))
+                .collect(),
+        }
+    }
+}
+
+/// Probes the subtitle streams, length and frame rate of `path` in a single
+/// ffprobe call, for indexing.
+pub fn probe_video(path: impl AsRef<Path>) -> Result<VideoProbe> {
+    let out = run(command("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_streams",
+            "-show_format",
+            "-of",
+            "json",
+        ])
+        .arg(path.as_ref().as_os_str()))?;
+    Ok(Probe::parse(&out.stdout)?.into())
+}
+
+/// Probes the subtitle streams of `path`, in the order ffmpeg's `0:s:N` uses.
+pub fn probe_sub_streams(path: impl AsRef<Path>) -> Result<Vec<ProbeStream>> {
+    probe_streams(path.as_ref(), "s")
+}
+
+/// Probes the streams of `path` of the given type (`s`, `a`, `v`, …).
+fn probe_streams(path: &Path, stream_type: &str) -> Result<Vec<ProbeStream>> {
+    let out = run(command("ffprobe")
+        .args(["-v", "error", "-select_streams", stream_type])
+        .args(["-show_streams", "-of", "json"])
+        .arg(path.as_os_str()))?;
+
+    Ok(Probe::parse(&out.stdout)?.streams)
+}
+
+/// Frames per second as ffprobe reports them, e.g. `24000/1001`. Stored that
+/// way in the DB too, exact unlike a float.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct FrameRate {
+    num: u64,
+    den: u64,
+}
+
+impl FrameRate {
+    pub fn parse(rate: &str) -> Option<Self> {
+        let (num, den) = rate.split_once('/').unwrap_or((rate, "1"));
+        let (num, den) = (num.trim().parse().ok()?, den.trim().parse().ok()?);
+        (num != 0 && den != 0).then_some(Self { num, den })
+    }
+
+    /// Number of the frame shown at `millis`.
+    pub fn frame_at(self, millis: u64) -> u64 {
+        millis * self.num / (self.den * 1000)
+    }
+}
+
+impl std::fmt::Display for FrameRate {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{num}/{den}", num = self.num, den = self.den)
+    }
+}
+
+impl TryFrom<String> for FrameRate {
+    type Error = String;
+
+    fn try_from(rate: String) -> Result<Self, Self::Error> {
+        Self::parse(&rate).ok_or_else(|| format!("invalid frame rate {rate:?}"))
+    }
+}
+
+impl From<FrameRate> for String {
+    fn from(rate: FrameRate) -> Self {
+        rate.to_string()
+    }
+}
+
+/// Probes the length of `path` in ms.
+pub fn probe_duration(path: impl AsRef<Path>) -> Result<u64> {
+    let path = path.as_ref();
+    let out = run(command("ffprobe")
+        .args(["-v", "error", "-show_entries", "format=duration"])
+        .args(["-of", "default=noprint_wrappers=1:nokey=1"])
+        .arg(path.as_os_str()))?;
+    seconds_to_millis(String::from_utf8_lossy(&out.stdout).trim())
+        .with_context(|| format!("no duration for {path:?}"))
+}
+
+/// Probes the frame rate of the first video stream of `path`.
+pub fn probe_frame_rate(path: impl AsRef<Path>) -> Result<FrameRate> {
+    let path = path.as_ref();
+    let streams = probe_streams(path, "v:0")?;
+    streams
+        .first()
+        .and_then(|stream| stream.r_frame_rate.as_deref())
+        .and_then(FrameRate::parse)
+        .with_context(|| format!("no video frame rate in {path:?}"))
+}
+
+/// How far a clip's length may be off from what was asked for, in ms, as
+/// cutting snaps to frames.
+const CLIP_DURATION_TOLERANCE: u64 = 500;
+
+/// Like `CLIP_DURATION_TOLERANCE`, for copied clips snapping to keyframes,
+/// which can be seconds apart.
+const COPY_DURATION_TOLERANCE: u64 = 10_000;
+
+/// Checks that `clip`, made with `opts`, is playable: ffprobe can read it, it
+/// has the streams `opts` asks for and is `expected_ms` long (give or take
+/// `CLIP_DURATION_TOLERANCE`, or `COPY_DURATION_TOLERANCE`). `duration_ms` is
+/// its length if already probed (see `Clipped`).
+pub fn verify_clip(
+    clip: &Path,
+    duration_ms: Option<u64>,
+    expected_ms: u64,
+    opts: &ClipOptions,
+) -> Result<()> {
+    let duration = duration_ms
+        .map_or_else(|| probe_duration(clip), Ok)
+        .context("unreadable")?;
+    let has_video = !probe_streams(clip, "v")?.is_empty();
+    let has_audio = !probe_streams(clip, "a")?.is_empty();
+    check_clip(
+        duration,
+        has_video || !opts.settings().has_video(),
+        has_audio,
+        expected_ms,
+        opts.duration_tolerance(),
+    )
+}
+
+fn check_clip(
+    duration: u64,
+    has_video: bool,
+    has_audio: bool,
+    expected_ms: u64,
+    tolerance: u64,
+) -> Result<()> {
+    ensure!(duration > 0, "zero-length");
+    ensure!(has_video, "no video stream");
+    ensure!(has_audio, "no audio stream");
+    ensure!(
+        duration.abs_diff(expected_ms) <= tolerance,
+        "{duration}ms long instead of {expected_ms}ms"
+    );
+    Ok(())
+}
+
+/// The index of `track` among `audio_streams` (counting only audio streams),
+/// erroring if there's no such track.
+fn check_audio_track(audio_streams: &[ProbeStream], track: &AudioTrack) -> Result<usize> {
+    let found = match track {
+        AudioTrack::Language(language) => audio_streams
+            .iter()
+            .position(|stream| stream.tags.language.as_ref() == Some(language)),
+        AudioTrack::Stream(n) => Some(*n).filter(|n| *n < audio_streams.len()),
+    };
+    let languages = audio_streams
+        .iter()
+        .map(|stream| stream.tags.language.as_deref().unwrap_or("unknown"))
+        .join(", ");
+    found.with_context(|| {
+        format!(
+            "no audio track {track:?}, there are {count} ({languages})",
+            count = audio_streams.len()
+        )
+    })
 }
 
 // TODO encoding settings
-/// Clips `sub` belonging to `file`
+// Clips `sub` belonging to `file`
 /*pub fn clip_one(sub: &Subtitle, file: &Path) {
     sub.start_time
 }*/
 
 // TODO check conversion between sub formats
-// TODO find out why the timestamps are so weird (clips being e.g. x+5 secs long
-// but starting at 5 secs)
-// TODO add offsets to buffer against badly synced subs
+/// `video_ms` is the length of `infile` if known, it gets probed where needed
+/// otherwise. `progress` gets called with the fraction (0 to 1) encoded so
+/// far, every now and then while ffmpeg runs.
 pub fn clip(
     infile: impl AsRef<Path>,
     outfile: impl AsRef<Path>,
-    start: Timestamp,
-    end: Timestamp,
-    profile: EncodingProfile,
-) -> Result<()> {
-    ensure!(end > start);
+    sub: &Subtitle,
+    opts: &ClipOptions,
+    video_ms: Option<u64>,
+    progress: Option<&mut dyn FnMut(f64)>,
+) -> Result<Clipped> {
     let (infile, outfile) = (infile.as_ref(), outfile.as_ref());
+    let mut resolved = Cow::Borrowed(opts);
+    if let Some(track) = &opts.audio {
+        let n = check_audio_track(&probe_streams(infile, "a")?, track)
+            .with_context(|| format!("selecting audio of {infile:?}"))?;
+        resolved.to_mut().audio = Some(AudioTrack::Stream(n));
+    }
+    // with `audio`, only the video and audio streams get mapped
+    if opts.settings().is_copy() && opts.audio.is_none() {
+        let subs = probe_sub_streams(infile)
+            .with_context(|| format!("probing the subtitles of {infile:?}"))?;
+        let codecs = subs
+            .iter()
+            .filter_map(|stream| stream.codec_name.as_deref())
+            .collect_vec();
+        resolved.to_mut().sub_codec = copied_sub_codec(&codecs, opts.ext());
+    }
+    let opts = resolved.as_ref();
+    let window = clip_window(sub, opts)?;
+    _clip(infile, outfile, window, video_ms, opts, progress)
+}
+
+/// What became of a `clip()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clipped {
+    /// it already existed (`Overwrite::Skip`)
+    Skipped,
+    /// `duration_ms` long, if ffprobe could tell, to spare `verify_clip()`
+    /// probing it again
+    Encoded { duration_ms: Option<u64> },
+}
 
-    let mut duration = end;
-    duration.sub(&start);
+/// Containers holding `mov_text` subtitles, which in turn are the only text
+/// subtitles they hold.
+const MOV_TEXT_CONTAINERS: [&str; 4] = ["mp4", "m4v", "mov", "3gp"];
 
-    #[allow(unused)]
-    let end = ();
+/// What to convert subtitle streams of `codecs` to when copying them into an
+/// `ext` file, `None` if they can be copied as they are.
+fn copied_sub_codec(codecs: &[&str], ext: &str) -> Option<&'static str> {
+    let mut text_codecs = codecs
+        .iter()
+        .filter(|codec| !IMAGE_SUB_CODECS.contains(codec));
+    if MOV_TEXT_CONTAINERS.contains(&ext) {
+        text_codecs
+            .any(|codec| *codec != "mov_text")
+            .then_some("mov_text")
+    } else if text_codecs.any(|codec| *codec == "mov_text") {
+        Some(if ext == "webm" { "webvtt" } else { "srt" })
+    } else {
+        None
+    }
+}
 
-    let (start, duration) = (timestamp_to_string(start), timestamp_to_string(duration));
-    _clip(infile, outfile, &start, &duration, profile)
+/// How long a clip of `window` (start and duration in ms) of a video
+/// `video_ms` long comes out, i.e. shorter if it runs past the end.
+pub fn expected_clip_len((start_ms, duration_ms): (u64, u64), video_ms: u64) -> u64 {
+    duration_ms.min(video_ms.saturating_sub(start_ms))
+}
+
+/// Start and duration (both in ms) of the clip for `sub`, including padding.
+/// Padding before 0 gets clamped.
+pub fn clip_window(sub: &Subtitle, opts: &ClipOptions) -> Result<(u64, u64)> {
+    let start = duration_to_millis(sub.start()).saturating_sub(opts.pad_start);
+    let end = duration_to_millis(sub.end()).saturating_add(opts.pad_end);
+    ensure!(
+        end > start,
+        "clip would end ({end}ms) before it starts ({start}ms)"
+    );
+    Ok((start, end - start))
+}
+
+/// Saturates at `u64::MAX` ms, which is way beyond any video length.
+pub fn duration_to_millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+pub fn timestamp_to_millis(t: Timestamp) -> u64 {
+    let (h, m, s, ms) = t.get();
+    ((u64::from(h) * 60 + u64::from(m)) * 60 + u64::from(s)) * 1000 + u64::from(ms)
+}
+
+#[allow(clippy::cast_possible_truncation)] // all but hours are in range by construction
+pub fn millis_to_timestamp(millis: u64) -> Result<Timestamp> {
+    let hours = u8::try_from(millis / 3_600_000).context("timestamp exceeds 255 hours")?;
+    Ok(Timestamp::new(
+        hours,
+        (millis / 60_000 % 60) as u8,
+        (millis / 1000 % 60) as u8,
+        (millis % 1000) as u16,
+    ))
+}
+
+/// Reads ffmpeg's `-progress` output: blocks of `key=value` lines, each ending
+/// with `progress=continue` (or `progress=end`).
+#[derive(Debug)]
+struct ProgressParser {
+    /// of the whole clip
+    total_ms: u64,
+    out_time_ms: Option<u64>,
+}
+
+impl ProgressParser {
+    fn new(total_ms: u64) -> Self {
+        Self {
+            total_ms,
+            out_time_ms: None,
+        }
+    }
+
+    /// The fraction done (0 to 1) whenever `line` completes a block.
+    #[allow(clippy::cast_precision_loss)] // ms don't get anywhere near 2^52
+    fn feed(&mut self, line: &str) -> Option<f64> {
+        let (key, value) = line.trim().split_once('=')?;
+        match key {
+            // both are µs, `out_time_ms` is misnamed (and gone in newer versions)
+            "out_time_us" | "out_time_ms" => {
+                // `N/A` before the first frame
+                if let Ok(us) = value.parse::<u64>() {
+                    self.out_time_ms = Some(us / 1000);
+                }
+                None
+            }
+            "progress" if value == "end" => Some(1.0),
+            "progress" => {
+                let done = self.out_time_ms? as f64 / self.total_ms.max(1) as f64;
+                Some(done.clamp(0.0, 1.0))
+            }
+            _ => None,
+        }
+    }
 }
 
 fn _clip(
     infile: &Path,
     outfile_basename: &Path,
-    start: &str,
-    duration: &str,
-    profile: EncodingProfile,
-) -> Result<()> {
-    let settings = ENCODING_PROFILES
-        .get(&profile)
-        .expect("[ASSERT] not all encoding profiles covered");
-    let outfile = format!("{}.{}", outfile_basename.to_string_lossy(), settings.ext);
+    window: (u64, u64),
+    video_ms: Option<u64>,
+    opts: &ClipOptions,
+    mut progress: Option<&mut dyn FnMut(f64)>,
+) -> Result<Clipped> {
+    let outfile = opts.outfile(outfile_basename);
+    if let Some(video_ms) = video_ms {
+        check_start(window, video_ms)?;
+    }
 
-    // delete temp file on failure
-    let rm_temp = scopeguard::guard(Path::new(&outfile), |outfile| {
-        let _ = std::fs::remove_file(outfile);
+    if outfile.exists() {
+        match opts.overwrite {
+            Overwrite::Never => {
+                bail!("{outfile:?} already exists (pass --overwrite or --skip-existing)")
+            }
+            Overwrite::Skip => {
+                info!("Skipping existing {outfile:?}");
+                return Ok(Clipped::Skipped);
+            }
+            Overwrite::Always => {}
+        }
+    }
+
+    // encode into a hidden file, so a crash never leaves a partial clip under
+    // the final name. It gets deleted on failure.
+    let stage_dir = opts
+        .stage_dir
+        .as_deref()
+        .or_else(|| outfile.parent())
+        .unwrap_or(Path::new("."));
+    // ffmpeg picks the muxer by extension
+    let staged = util::staging_file(stage_dir, &format!(".{}", opts.ext()))?.into_temp_path();
+
+    let mut progress = |done| {
+        if let Some(progress) = progress.as_mut() {
+            progress(done);
+        }
+    };
+    encode(infile, &staged, window, opts, Seek::Input, &mut progress)?;
+    let duration_ms = match check_seek(&staged, infile, window, video_ms, opts) {
+        Ok(duration_ms) => duration_ms,
+        Err(e) => {
+            warn!(
+                "Clipping {infile:?} with fast seeking went wrong ({e:#}), retrying with slow seeking"
+            );
+            encode(infile, &staged, window, opts, Seek::Decode, &mut progress)?;
+            check_seek(&staged, infile, window, video_ms, opts).with_context(|| {
+                format!(
+                    "clipping {infile:?} even with slow seeking, its index is probably broken. \
+                     Remux it (e.g. `ffmpeg -i <video> -c copy <new video>`) and rescan."
+                )
+            })?
+        }
+    };
+
+    // the staged file stays deleted on failure, as `staged` gets dropped
+    util::move_file(&staged, &outfile)
+        .with_context(|| format!("moving the finished clip into place at {outfile:?}"))?;
+    Ok(Clipped::Encoded { duration_ms })
+}
+
+/// Where `_clip()` puts `-ss`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Seek {
+    /// before `-i`, jumping there via the video's index. Fast, but broken
+    /// indexes (damaged downloads, recorded streams) can land anywhere.
+    Input,
+    /// after `-i`, decoding everything up to the start. Slow, but needs no index.
+    Decode,
+}
+
+/// The input args of a clip starting `start_ms` into `infile`.
+fn seek_args(infile: &Path, start_ms: u64, seek: Seek) -> Vec<String> {
+    let ss = ["-ss".to_owned(), millis_to_string(start_ms)];
+    let input = ["-i".to_owned(), infile.to_string_lossy().into_owned()];
+    match seek {
+        Seek::Input => [ss, input].concat(),
+        Seek::Decode => [input, ss].concat(),
+    }
+}
+
+/// Errors if the clip at `staged` of `window` (start and duration in ms) of
+/// `infile` isn't about as long as `expected_clip_len()`, the telltale of
+/// `Seek::Input` going wrong. `infile` only gets probed for its length if the
+/// clip is off and `video_ms` isn't known. Clips ffprobe can't read pass,
+/// that's up to `verify_clip()`. Returns the clip's length, if probed.
+fn check_seek(
+    staged: &Path,
+    infile: &Path,
+    window: (u64, u64),
+    video_ms: Option<u64>,
+    opts: &ClipOptions,
+) -> Result<Option<u64>> {
+    let duration = match probe_duration(staged) {
+        Ok(duration) => duration,
+        Err(e) => {
+            debug!("Not checking the length of {staged:?}: {e:#}");
+            return Ok(None);
+        }
+    };
+    let tolerance = opts.duration_tolerance();
+    if duration.abs_diff(window.1) <= tolerance {
+        return Ok(Some(duration));
+    }
+    // clips running past the end of the video come out short
+    let video_ms = video_ms.or_else(|| {
+        probe_duration(infile)
+            .inspect_err(|e| debug!("Couldn't probe the length of {infile:?}: {e:#}"))
+            .ok()
     });
+    check_len(duration, window, video_ms, tolerance)?;
+    Ok(Some(duration))
+}
+
+/// Errors if `window` (start and duration in ms) starts after the end of the
+/// video, `video_ms` long.
+fn check_start((start_ms, _): (u64, u64), video_ms: u64) -> Result<()> {
+    ensure!(
+        start_ms < video_ms,
+        "starts at {start_ms}ms, after the video ends ({video_ms}ms)"
+    );
+    Ok(())
+}
+
+/// Errors unless a clip `duration` ms long fits `window` of a video `video_ms`
+/// long (if known), give or take `tolerance` ms.
+fn check_len(
+    duration: u64,
+    window: (u64, u64),
+    video_ms: Option<u64>,
+    tolerance: u64,
+) -> Result<()> {
+    let expected = match video_ms {
+        Some(video_ms) => {
+            check_start(window, video_ms)?;
+            expected_clip_len(window, video_ms)
+        }
+        None => window.1,
+    };
+    ensure!(
+        duration.abs_diff(expected) <= tolerance,
+        "{duration}ms long instead of {expected}ms"
+    );
+    Ok(())
+}
 
-    let out = dbg!(Command::new("ffmpeg")
+/// Runs ffmpeg for `_clip()`, encoding `window` (start and duration in ms) of
+/// `infile` into `staged`.
+fn encode(
+    infile: &Path,
+    staged: &Path,
+    (start_ms, duration_ms): (u64, u64),
+    opts: &ClipOptions,
+    seek: Seek,
+    progress: &mut dyn FnMut(f64),
+) -> Result<()> {
+    let mut command = command("ffmpeg");
+    command
+        .args(settings_to_args(&opts.settings().input_params))
+        .args(seek_args(infile, start_ms, seek))
         .args([
-            // seek in input to sub start
-            "-ss",
-            start,
-            "-i",
-            infile.to_string_lossy().as_ref(),
             // stop encoding after sub duration
             "-t",
-            duration,
+            &millis_to_string(duration_ms),
+            // machine readable progress on stdout instead of the status line
+            "-progress",
+            "pipe:1",
+            "-nostats",
         ])
-        .args(settings_to_args(settings))
-        .arg(&outfile))
-    .output()?;
+        // have to come before the codec params
+        .args(opts.map_args())
+        .args(opts.filter_args(duration_ms))
+        .args(settings_to_args(&opts.params()))
+        // the staging file exists already, `opts.overwrite` was handled above
+        .arg("-y")
+        .arg(staged);
+    let retries = RETRIES.load(Ordering::Relaxed);
+    let mut attempt = 0;
+    loop {
+        debug!("Running {command:?}");
+        let result = encode_once(&mut command, duration_ms, progress);
+        let transient = match &result {
+            Ok((status, stderr)) => {
+                log_stderr(&command, stderr);
+                !status.success() && is_transient_stderr(stderr)
+            }
+            Err(e) => is_transient(e),
+        };
+        if !transient || attempt == retries {
+            let (status, stderr) = result.with_context(|| format!("trying to run {command:?}"))?;
+            ensure!(
+                status.success(),
+                "{command:?} failed ({status}): {stderr}",
+                stderr = String::from_utf8_lossy(&stderr).trim_end()
+            );
+            return Ok(());
+        }
+        let wait = RETRY_BACKOFF * 2u32.saturating_pow(attempt);
+        warn!("ffmpeg failed to encode {staged:?}, retrying in {wait:?}…");
+        std::thread::sleep(wait);
+        attempt += 1;
+    }
+}
+
+/// One run of `encode`'s `command`, feeding its progress to `progress` as it
+/// goes. Returns how it exited and what it wrote to stderr.
+fn encode_once(
+    command: &mut Command,
+    duration_ms: u64,
+    progress: &mut dyn FnMut(f64),
+) -> std::io::Result<(ExitStatus, Vec<u8>)> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // drained alongside stdout, so ffmpeg never blocks on a full pipe
+    let mut stderr = child.stderr.take().expect("[ASSERT] stderr is piped");
+    let stderr_thread = std::thread::spawn(move || {
+        let mut out = Vec::new();
+        let _ = stderr.read_to_end(&mut out);
+        out
+    });
+    let stdout = child.stdout.take().expect("[ASSERT] stdout is piped");
+    let mut parser = ProgressParser::new(duration_ms);
+    for line in BufReader::new(stdout).lines() {
+        if let Some(done) = parser.feed(&line?) {
+            progress(done);
+        }
+    }
+    let status = child.wait()?;
+    let stderr = stderr_thread
+        .join()
+        .map_err(|e| std::io::Error::other(format!("reading ffmpeg's stderr: {e:?}")))?;
+    Ok((status, stderr))
+}
+
+/// Joins `clips` (in order) into `out` without re-encoding, via ffmpeg's concat
+/// demuxer. They all need the same container as `out`, and the same codecs.
+pub fn concat(clips: &[PathBuf], out: &Path) -> Result<()> {
+    ensure!(!clips.is_empty(), "nothing to concatenate into {out:?}");
+    let exts = clips
+        .iter()
+        .map(PathBuf::as_path)
+        .chain([out])
+        .map(|clip| clip.extension().unwrap_or_default())
+        .unique()
+        .collect_vec();
     ensure!(
-        out.status.success(),
-        "{}",
-        String::from_utf8_lossy(&out.stderr)
+        exts.len() == 1,
+        "can't concatenate clips of different containers ({exts:?}) into {out:?}"
     );
 
-    // defuse ScopeGuard for deleting temp
-    let _ = ScopeGuard::into_inner(rm_temp);
+    let list_dir = tempfile::tempdir()?;
+    let list_file = list_dir.path().join("clips.txt");
+    let list = clips
+        .iter()
+        .map(|clip| {
+            let clip = std::path::absolute(clip)?;
+            // quoted for the concat demuxer, `'` becomes `'\''`
+            anyhow::Ok(format!(
+                "file '{}'\n",
+                clip.to_string_lossy().replace('\'', r"'\''")
+            ))
+        })
+        .collect::<Result<String>>()?;
+    std::fs::write(&list_file, list).with_context(|| format!("writing {list_file:?}"))?;
+
+    run(command("ffmpeg")
+        .args(["-f", "concat", "-safe", "0", "-i"])
+        .arg(&list_file)
+        .args(["-c", "copy", "-y"])
+        .arg(out))?;
+    Ok(())
+}
+
+/// Extracts the frame at `at` from `infile` as a JPEG.
+pub fn poster_frame(
+    infile: impl AsRef<Path>,
+    outfile: impl AsRef<Path>,
+    at: Timestamp,
+) -> Result<()> {
+    let at = millis_to_string(timestamp_to_millis(at));
+    run(command("ffmpeg")
+        .args([
+            // same input seeking as `_clip()`
+            "-ss",
+            &at,
+            "-i",
+            infile.as_ref().to_string_lossy().as_ref(),
+            "-frames:v",
+            "1",
+            "-q:v",
+            "2",
+            "-y",
+        ])
+        .arg(outfile.as_ref()))?;
     Ok(())
 }
 
-fn settings_to_args(settings: &EncodingSettings) -> Vec<&str> {
+/// Thumbnails per side of `contact_sheet()`s.
+const CONTACT_SHEET_TILES: u64 = 4;
+
+/// Writes a JPEG grid of thumbnails spread evenly over `video` to `out`,
+/// unless that exists already. Probes the `duration` (in ms) if not given.
+pub fn contact_sheet(video: &Path, duration: Option<u64>, out: &Path) -> Result<()> {
+    if out.exists() {
+        return Ok(());
+    }
+    let duration = match duration {
+        Some(duration) => duration,
+        None => probe_duration(video)?,
+    };
+    let filter = contact_sheet_filter(duration);
+    let result = run(command("ffmpeg")
+        .args(["-i", &video.to_string_lossy(), "-vf", &filter])
+        .args(["-frames:v", "1", "-q:v", "3", "-y"])
+        .arg(out));
+    if result.is_err() {
+        // don't let a broken sheet count as done next time
+        let _ = std::fs::remove_file(out);
+    }
+    result.map(|_| ())
+}
+
+/// One frame every `1/CONTACT_SHEET_TILES²` of `duration` (in ms), scaled
+/// down and tiled.
+fn contact_sheet_filter(duration: u64) -> String {
+    let tiles = CONTACT_SHEET_TILES;
+    let interval = (duration / (tiles * tiles)).max(1000);
+    format!("fps=1000/{interval},scale=320:-1,tile={tiles}x{tiles}")
+}
+
+/// Runs `command` to completion, logging its stderr. Fails with the whole
+/// command line and its stderr unless it succeeds. Transient failures (see
+/// `is_transient()`) get retried `set_retries()` times.
+fn run(command: &mut Command) -> Result<Output> {
+    let retries = RETRIES.load(Ordering::Relaxed);
+    let mut attempt = 0;
+    loop {
+        debug!("Running {command:?}");
+        let result = command.output();
+        let transient = match &result {
+            Ok(out) => {
+                log_stderr(command, &out.stderr);
+                !out.status.success() && is_transient_stderr(&out.stderr)
+            }
+            Err(e) => is_transient(e),
+        };
+        if !transient || attempt == retries {
+            let out = result.with_context(|| format!("trying to run {command:?}"))?;
+            ensure!(
+                out.status.success(),
+                "{command:?} failed ({status}): {stderr}",
+                status = out.status,
+                stderr = String::from_utf8_lossy(&out.stderr).trim_end()
+            );
+            return Ok(out);
+        }
+        let wait = RETRY_BACKOFF * 2u32.saturating_pow(attempt);
+        warn!(
+            "{program:?} failed, retrying in {wait:?}…",
+            program = command.get_program()
+        );
+        std::thread::sleep(wait);
+        attempt += 1;
+    }
+}
+
+/// Whether spawning failed for passing reasons, like too many processes.
+fn is_transient(e: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+
+    matches!(
+        e.kind(),
+        ErrorKind::WouldBlock
+            | ErrorKind::Interrupted
+            | ErrorKind::ResourceBusy
+            | ErrorKind::TimedOut
+            | ErrorKind::OutOfMemory
+    )
+}
+
+/// Whether a failed run's `stderr` blames passing I/O trouble rather than the
+/// file, e.g. a stalling network mount.
+fn is_transient_stderr(stderr: &[u8]) -> bool {
+    const TRANSIENT: [&str; 3] = [
+        "Resource temporarily unavailable",
+        "Input/output error",
+        "Stale file handle",
+    ];
+    let stderr = String::from_utf8_lossy(stderr);
+    TRANSIENT.iter().any(|reason| stderr.contains(reason))
+}
+
+/// Even successful runs can have warnings explaining odd results.
+fn log_stderr(command: &Command, stderr: &[u8]) {
+    if !stderr.is_empty() {
+        debug!(
+            "{program:?} said: {stderr}",
+            program = command.get_program(),
+            stderr = String::from_utf8_lossy(stderr).trim_end()
+        );
+    }
+}
+
+fn settings_to_args<'a>(params: &'a [(&'a str, impl AsRef<str>)]) -> Vec<&'a str> {
     let mut result = Vec::new();
-    settings.params.iter().for_each(|(k, v)| {
+    for (k, v) in params {
         result.push(*k);
-        if v.is_empty().not() {
-            result.push(*v);
+        if v.as_ref().is_empty().not() {
+            result.push(v.as_ref());
         }
-    });
+    }
     result
 }
 
@@ -250,15 +1607,479 @@ fn settings_to_args(settings: &EncodingSettings) -> Vec<&str> {
 /// 23.189
 /// 23.189 seconds
 /// ```
-fn timestamp_to_string(t: Timestamp) -> String {
-    let (h, m, s, ms) = t.get();
+pub fn timestamp_to_string(t: Timestamp) -> String {
+    millis_to_string(timestamp_to_millis(t))
+}
+
+/// `72.340` for filter options taking seconds.
+fn millis_to_seconds(millis: u64) -> String {
+    format!("{}.{:03}", millis / 1000, millis % 1000)
+}
+
+/// Like `timestamp_to_string()`, but from total milliseconds, so hours can't
+/// overflow.
+pub fn millis_to_string(millis: u64) -> String {
+    let (h, m, s, ms) = (
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000,
+    );
     format!("{h:02}:{m:02}:{s:02}.{ms:03}")
 }
 
 #[cfg(test)]
 mod test {
+    #![allow(non_snake_case)]
+
     use srtlib::Timestamp;
 
+    #[test]
+    fn incompatible_codecs() {
+        use super::{incompatible_codecs, EncodingProfile};
+
+        assert!(incompatible_codecs(EncodingProfile::AV1, "webm").is_empty());
+        assert_eq!(
+            incompatible_codecs(EncodingProfile::AV1, "mov"),
+            ["libsvtav1", "libopus"]
+        );
+        assert_eq!(incompatible_codecs(EncodingProfile::FLAC, "webm"), ["flac"]);
+    }
+
+    fn opts(pad_start: u64, pad_end: u64) -> super::ClipOptions {
+        super::ClipOptions {
+            profile: super::EncodingProfile::AV1,
+            container: None,
+            pad_start,
+            pad_end,
+            overwrite: super::Overwrite::default(),
+            audio: None,
+            stage_dir: None,
+            loudnorm: false,
+            fade: 0,
+            crf: None,
+            preset: None,
+            sub_codec: None,
+        }
+    }
+
+    #[test]
+    fn map_args() {
+        use super::{AudioTrack, EncodingProfile};
+
+        assert!(opts(0, 0).map_args().is_empty());
+
+        let mut opts = opts(0, 0);
+        opts.audio = Some(AudioTrack::Stream(2));
+        assert_eq!(opts.map_args(), ["-map", "0:v:0", "-map", "0:a:2"]);
+
+        opts.profile = EncodingProfile::FLAC;
+        opts.audio = Some(AudioTrack::Stream(1));
+        assert_eq!(opts.map_args(), ["-map", "0:a:1"]);
+    }
+
+    #[test]
+    fn params() {
+        use super::EncodingProfile;
+
+        let value = |opts: &super::ClipOptions, key: &str| {
+            opts.params()
+                .into_iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.into_owned())
+        };
+        let mut opts = opts(0, 0);
+        assert_eq!(value(&opts, "-crf:v").as_deref(), Some("10"));
+        opts.crf = Some(30);
+        opts.preset = Some(8);
+        assert_eq!(value(&opts, "-crf:v").as_deref(), Some("30"));
+        assert_eq!(value(&opts, "-preset:v").as_deref(), Some("8"));
+        assert_eq!(value(&opts, "-c:a").as_deref(), Some("libopus"));
+
+        opts.profile = EncodingProfile::H264_NVENC;
+        opts.preset = Some(4);
+        assert_eq!(value(&opts, "-cq:v").as_deref(), Some("30"));
+        assert_eq!(value(&opts, "-preset:v").as_deref(), Some("p4"));
+        assert!(opts.takes_quality());
+
+        opts.profile = EncodingProfile::FLAC;
+        assert!(!opts.takes_quality());
+        assert_eq!(opts.params().len(), opts.settings().params.len());
+        opts.profile = EncodingProfile::COPY;
+        assert!(!opts.takes_quality());
+        assert_eq!(value(&opts, "-c:s").as_deref(), Some("copy"));
+        opts.sub_codec = Some("srt");
+        assert_eq!(value(&opts, "-c:s").as_deref(), Some("srt"));
+    }
+
+    #[test]
+    fn copied_sub_codec() {
+        use super::copied_sub_codec;
+
+        assert_eq!(copied_sub_codec(&["mov_text"], "mkv"), Some("srt"));
+        assert_eq!(copied_sub_codec(&["mov_text"], "webm"), Some("webvtt"));
+        assert_eq!(copied_sub_codec(&["mov_text"], "mp4"), None);
+        assert_eq!(copied_sub_codec(&["subrip", "ass"], "mkv"), None);
+        assert_eq!(copied_sub_codec(&["ass"], "mp4"), Some("mov_text"));
+        assert_eq!(copied_sub_codec(&["hdmv_pgs_subtitle"], "mkv"), None);
+        assert_eq!(copied_sub_codec(&[], "mov"), None);
+    }
+
+    #[test]
+    fn seek_args() {
+        use super::Seek;
+
+        let video = std::path::Path::new("/v.mkv");
+        assert_eq!(
+            super::seek_args(video, 61_500, Seek::Input),
+            ["-ss", "00:01:01.500", "-i", "/v.mkv"]
+        );
+        assert_eq!(
+            super::seek_args(video, 61_500, Seek::Decode),
+            ["-i", "/v.mkv", "-ss", "00:01:01.500"]
+        );
+    }
+
+    #[test]
+    fn filter_args() {
+        assert!(opts(0, 0).filter_args(5000).is_empty());
+        let mut opts = super::ClipOptions {
+            loudnorm: true,
+            ..opts(0, 0)
+        };
+        assert_eq!(
+            opts.filter_args(5000),
+            ["-af", super::LOUDNORM_FILTER, "-ar", "48000"]
+        );
+
+        opts.fade = 500;
+        assert_eq!(
+            opts.filter_args(5000),
+            [
+                "-vf",
+                "fade=t=in:st=0:d=0.500,fade=t=out:st=4.500:d=0.500",
+                "-af",
+                &format!(
+                    "{},afade=t=in:st=0:d=0.500,afade=t=out:st=4.500:d=0.500",
+                    super::LOUDNORM_FILTER
+                ),
+                "-ar",
+                "48000",
+            ]
+        );
+        // fades take half of short clips at most
+        opts.loudnorm = false;
+        opts.profile = super::EncodingProfile::FLAC;
+        assert_eq!(
+            opts.filter_args(600),
+            [
+                "-af",
+                "afade=t=in:st=0:d=0.300,afade=t=out:st=0.300:d=0.300"
+            ]
+        );
+    }
+
+    #[test]
+    fn check_audio_track() {
+        use super::AudioTrack;
+
+        let streams = super::Probe::parse(
+            br#"{"streams": [{"index": 1, "tags": {"language": "eng"}}, {"index": 2, "tags": {"language": "jpn"}}, {"index": 3, "tags": {"language": "jpn"}}]}"#,
+        )
+        .unwrap()
+        .streams;
+        let check = |track| super::check_audio_track(&streams, &track);
+
+        // only the first of the language
+        assert_eq!(check(AudioTrack::Language("jpn".to_owned())).unwrap(), 1);
+        assert_eq!(check(AudioTrack::Stream(1)).unwrap(), 1);
+        assert!(check(AudioTrack::Stream(3)).is_err());
+        let err = check(AudioTrack::Language("ger".to_owned())).unwrap_err();
+        assert!(err.to_string().contains("eng, jpn, jpn"), "{err}");
+    }
+
+    #[test]
+    fn check_len() {
+        use super::{check_len, expected_clip_len};
+
+        assert_eq!(expected_clip_len((1_000, 5_000), 60_000), 5_000);
+        assert_eq!(expected_clip_len((58_000, 5_000), 60_000), 2_000);
+        assert_eq!(expected_clip_len((61_000, 5_000), 60_000), 0);
+
+        assert!(check_len(5_100, (1_000, 5_000), None, 500).is_ok());
+        assert!(check_len(9_000, (1_000, 5_000), None, 500).is_err());
+        // runs past the end of the video
+        assert!(check_len(2_000, (58_000, 5_000), Some(60_000), 500).is_ok());
+        assert!(check_len(2_000, (58_000, 5_000), None, 500).is_err());
+        assert!(check_len(5_000, (58_000, 5_000), Some(60_000), 500).is_err());
+        let err = check_len(0, (61_000, 5_000), Some(60_000), 500).unwrap_err();
+        assert!(err.to_string().contains("after the video ends"), "{err}");
+    }
+
+    #[test]
+    fn contact_sheet_filter() {
+        assert_eq!(
+            super::contact_sheet_filter(24 * 60 * 1000),
+            "fps=1000/90000,scale=320:-1,tile=4x4"
+        );
+        assert_eq!(
+            super::contact_sheet_filter(5000),
+            "fps=1000/1000,scale=320:-1,tile=4x4"
+        );
+    }
+
+    #[test]
+    fn is_transient() {
+        use std::io::{Error, ErrorKind};
+
+        assert!(super::is_transient(&Error::from(ErrorKind::WouldBlock)));
+        assert!(!super::is_transient(&Error::from(ErrorKind::NotFound)));
+        assert!(super::is_transient_stderr(
+            b"/mnt/nas/a.mkv: Input/output error\n"
+        ));
+        assert!(!super::is_transient_stderr(
+            b"Stream map '0:s:0' matches no streams.\n"
+        ));
+    }
+
+    #[test]
+    fn check_clip() {
+        let check = |duration, has_video, has_audio| {
+            super::check_clip(duration, has_video, has_audio, 3_000, 500)
+        };
+        assert!(check(3_250, true, true).is_ok());
+        assert!(check(2_600, true, true).is_ok());
+        assert!(check(0, true, true).is_err());
+        assert!(check(3_000, false, true).is_err());
+        assert!(check(3_000, true, false).is_err());
+        let err = check(8_000, true, true).unwrap_err();
+        assert_eq!(err.to_string(), "8000ms long instead of 3000ms");
+        assert!(super::check_clip(8_000, true, true, 3_000, 10_000).is_ok());
+    }
+
+    #[test]
+    fn parse_chapters() {
+        let chapters = super::parse_chapters(
+            br#"{"chapters": [
+                {"id": 1, "time_base": "1/1000000000", "start": 0, "start_time": "0.000000", "end": 90500000000, "end_time": "90.500000", "tags": {"title": "Opening"}},
+                {"id": 2, "time_base": "1/1000000000", "start": 90500000000, "start_time": "90.500000", "end": 1300000000000, "end_time": "1300.000000", "tags": {"title": " "}}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[0].name(), "Opening");
+        assert_eq!((chapters[0].start, chapters[0].end), (0, 90_500));
+        assert_eq!(chapters[1].name(), "Chapter 2");
+        assert_eq!((chapters[1].start, chapters[1].end), (90_500, 1_300_000));
+        assert!(super::parse_chapters(b"{}").unwrap().is_empty());
+    }
+
+    fn sub(start: Timestamp, end: Timestamp) -> crate::sub::Subtitle {
+        srtlib::Subtitle::new(1, start, end, String::new()).into()
+    }
+
+    #[test]
+    fn clip_window__across_hour_boundary() {
+        let (start, duration) = super::clip_window(
+            &sub(Timestamp::new(0, 59, 59, 900), Timestamp::new(1, 0, 1, 100)),
+            &opts(0, 0),
+        )
+        .unwrap();
+        assert_eq!(super::millis_to_string(start), "00:59:59.900");
+        assert_eq!(super::millis_to_string(duration), "00:00:01.200");
+    }
+
+    #[test]
+    fn clip_window__padding() {
+        let (start, duration) = super::clip_window(
+            &sub(Timestamp::new(0, 0, 0, 300), Timestamp::new(0, 0, 59, 800)),
+            &opts(500, 400),
+        )
+        .unwrap();
+        // start padding gets clamped at 0
+        assert_eq!(start, 0);
+        assert_eq!(super::millis_to_string(duration), "00:01:00.200");
+    }
+
+    #[test]
+    fn clip_window__end_before_start() {
+        let ts = Timestamp::new(0, 1, 0, 0);
+        assert!(super::clip_window(&sub(ts, ts), &opts(0, 0)).is_err());
+        assert!(super::clip_window(&sub(Timestamp::new(0, 1, 0, 1), ts), &opts(0, 0)).is_err());
+    }
+
+    #[test]
+    fn progress_parser() {
+        // captured from `ffmpeg -progress pipe:1` for a 2s clip
+        let output = "\
+frame=0
+fps=0.00
+out_time_us=N/A
+out_time_ms=N/A
+progress=continue
+frame=24
+fps=23.10
+bitrate= 102.4kbits/s
+out_time_us=1000000
+out_time_ms=1000000
+out_time=00:00:01.000000
+speed=0.962x
+progress=continue
+frame=48
+out_time_us=2040000
+out_time_ms=2040000
+progress=end
+";
+        let mut parser = super::ProgressParser::new(2000);
+        let progress = output
+            .lines()
+            .filter_map(|l| parser.feed(l))
+            .collect::<Vec<_>>();
+        assert_eq!(progress, [0.5, 1.0]);
+
+        let mut parser = super::ProgressParser::new(2000);
+        let progress = ["out_time_us=3000000", "progress=continue"]
+            .into_iter()
+            .filter_map(|l| parser.feed(l))
+            .collect::<Vec<_>>();
+        assert_eq!(progress, [1.0], "clamped");
+    }
+
+    #[test]
+    fn millis_to_string__many_hours() {
+        assert_eq!(
+            super::millis_to_string(300 * 3_600_000 + 1),
+            "300:00:00.001"
+        );
+    }
+
+    #[test]
+    fn probe_parse() {
+        let json = std::fs::read(
+            [env!("CARGO_MANIFEST_DIR"), "test", "ffprobe_subs.json"]
+                .iter()
+                .collect::<std::path::PathBuf>(),
+        )
+        .unwrap();
+        let probe = super::Probe::parse(&json).unwrap();
+
+        assert_eq!(probe.streams.len(), 2);
+        assert_eq!(probe.streams[0].codec_name.as_deref(), Some("subrip"));
+        assert_eq!(probe.streams[0].tags.language.as_deref(), Some("eng"));
+        assert_eq!(probe.streams[1].tags.language, None);
+        assert_eq!(probe.streams[0].disposition.forced, 0);
+        assert_eq!(probe.streams[1].disposition.forced, 1);
+        assert_eq!(probe.streams[0].disposition.default, 1);
+        assert_eq!(probe.streams[1].disposition.default, 0);
+        assert!(!probe.streams[0].is_image_sub());
+    }
+
+    #[test]
+    fn frame_rate() {
+        let ntsc = super::FrameRate::parse("24000/1001").unwrap();
+        assert_eq!(ntsc.frame_at(1001), 24);
+        assert_eq!(super::FrameRate::parse("25").unwrap().frame_at(2000), 50);
+        assert_eq!(super::FrameRate::parse("0/0"), None);
+        assert_eq!(super::FrameRate::parse("n/a"), None);
+        assert_eq!(serde_json::to_string(&ntsc).unwrap(), r#""24000/1001""#);
+        assert_eq!(
+            serde_json::from_str::<super::FrameRate>(r#""24000/1001""#).unwrap(),
+            ntsc
+        );
+        assert!(serde_json::from_str::<super::FrameRate>(r#""0/0""#).is_err());
+    }
+
+    #[test]
+    fn video_probe() {
+        let probe = super::Probe::parse(
+            br#"{
+                "streams": [
+                    {"index": 0, "codec_type": "video", "r_frame_rate": "24000/1001"},
+                    {"index": 1, "codec_type": "audio", "r_frame_rate": "0/0"},
+                    {"index": 2, "codec_type": "subtitle", "codec_name": "ass"},
+                    {"index": 3, "codec_type": "video", "r_frame_rate": "90000/1"},
+                    {"index": 4, "codec_type": "subtitle", "codec_name": "subrip"}
+                ],
+                "format": {"duration": "1420.512000"}
+            }"#,
+        )
+        .unwrap();
+        let probe = super::VideoProbe::from(probe);
+        assert_eq!(
+            probe
+                .sub_streams
+                .iter()
+                .map(|s| s.index)
+                .collect::<Vec<_>>(),
+            [2, 4]
+        );
+        assert_eq!(probe.duration, Some(1_420_512));
+        assert_eq!(probe.fps, super::FrameRate::parse("24000/1001"));
+
+        assert_eq!(
+            super::VideoProbe::from(super::Probe::default()),
+            super::VideoProbe::default()
+        );
+    }
+
+    #[test]
+    fn probe_parse__no_streams() {
+        assert!(super::Probe::parse(b"{}").unwrap().streams.is_empty());
+    }
+
+    #[test]
+    fn concat__mixed_containers() {
+        let clips = ["a.mkv", "b.mp4"].map(std::path::PathBuf::from);
+        let err = super::concat(&clips, "out.mkv".as_ref()).unwrap_err();
+        assert!(err.to_string().contains("different containers"));
+
+        let clips = ["a.mkv", "b.mkv"].map(std::path::PathBuf::from);
+        assert!(super::concat(&clips, "out.webm".as_ref()).is_err());
+    }
+
+    #[test]
+    fn sub_events() {
+        let packets = serde_json::from_str::<super::ProbePackets>(
+            r#"{"packets": [
+                {"pts_time": "1.500000", "duration_time": "2.000000"},
+                {"pts_time": "10.000000", "duration_time": "0.000000"},
+                {"pts_time": "12.25"},
+                {"pts_time": "N/A"},
+                {"pts_time": "30"}
+            ]}"#,
+        )
+        .unwrap()
+        .packets;
+        assert_eq!(
+            super::sub_events(&packets),
+            [
+                (1500, 3500),
+                (10_000, 12_250),
+                (12_250, 22_250),
+                (30_000, 40_000)
+            ]
+        );
+    }
+
+    #[test]
+    fn seconds_to_millis() {
+        assert_eq!(super::seconds_to_millis("12.345678"), Some(12_345));
+        assert_eq!(super::seconds_to_millis("3.5"), Some(3_500));
+        assert_eq!(super::seconds_to_millis("7"), Some(7_000));
+        assert_eq!(super::seconds_to_millis("N/A"), None);
+    }
+
+    #[test]
+    fn parse_timestamp() {
+        assert_eq!(super::parse_timestamp("00:12:03.5"), Some(723_500));
+        assert_eq!(super::parse_timestamp("1:00:00"), Some(3_600_000));
+        assert_eq!(super::parse_timestamp("12:08,25"), Some(728_250));
+        assert_eq!(super::parse_timestamp("8"), Some(8_000));
+        assert_eq!(super::parse_timestamp("1:2:3:4"), None);
+        assert_eq!(super::parse_timestamp("1::4"), None);
+        assert_eq!(super::parse_timestamp(""), None);
+    }
+
     #[test]
     fn ffmpeg_duration() {
         assert_eq!(
@@ -267,7 +2088,3 @@ mod test {
         );
     }
 }
-
-//pub fn get_sub_files_in_dir(p: impl AsRef<Path>) -> Result<Vec<impl AsRef<Path>>> {
-//    _get_sub_files_in_dir(p, || Ok(tempfile::tempfile()?))
-//}
@@ -1,25 +1,159 @@
 use std::{
-    io::{BufRead, Write as _},
+    io::Write as _,
+    path::PathBuf,
     process::{Command, Stdio},
 };
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use itertools::Itertools as _;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
 
-pub fn select(strings: &[impl AsRef<str>]) -> Result<Vec<String>> {
-    let mut fzf = Command::new("fzf")
-        .arg("-m") // multi select
+use crate::ffmpeg;
+
+/// Keys (one per string) for `select()` to match queries against.
+/// Either way, queries match case-insensitively.
+#[derive(Debug, Clone, Copy)]
+pub enum SearchKeys<'a> {
+    /// match the strings themselves
+    None,
+    /// appended to their string as an extra tab-separated field
+    Appended(&'a [String]),
+    /// shown and matched instead of the strings, which get recovered via a
+    /// hidden index field
+    Only(&'a [String]),
+}
+
+/// Segment of a video `select()` plays on `PLAY_KEY`, one per string.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayTarget {
+    pub video: PathBuf,
+    /// in ms
+    pub start: u64,
+    pub end: u64,
+}
+
+/// fzf key playing the current line's `PlayTarget`, see `--preview-clip`.
+pub const PLAY_KEY: &str = "ctrl-p";
+
+/// fzf's exit codes for "nothing matched" and "aborted with Esc/Ctrl-C"
+const NO_SELECTION_CODES: [i32; 2] = [1, 130];
+
+/// fzf options changing what it reads or prints, which `select()` depends on
+const RESERVED_ARGS: [&str; 9] = [
+    "+m",
+    "--no-multi",
+    "--read0",
+    "--print0",
+    "--print-query",
+    "--expect",
+    "--delimiter",
+    "-d",
+    "--with-nth",
+];
+
+/// Extra fzf options, from `parse_args()`. An alias, so clap takes it as one
+/// value rather than one per `Vec` item.
+pub type FzfArgs = Vec<String>;
+
+/// Splits `--fzf-args` into words like a shell would (`'…'`, `"…"` and `\`
+/// quote), refusing options that would break `select()`.
+pub fn parse_args(args: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = None::<String>;
+    let mut chars = args.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\\' => word
+                .get_or_insert_default()
+                .push(chars.next().context("trailing `\\`")?),
+            '\'' | '"' => {
+                let word = word.get_or_insert_default();
+                loop {
+                    match chars.next().with_context(|| format!("unterminated {c}"))? {
+                        end if end == c => break,
+                        '\\' if c == '"' => word.push(chars.next().context("trailing `\\`")?),
+                        c => word.push(c),
+                    }
+                }
+            }
+            c => word.get_or_insert_default().push(c),
+        }
+    }
+    words.extend(word);
+    for word in &words {
+        let name = word.split_once('=').map_or(word.as_str(), |(name, _)| name);
+        let short_d = word.starts_with("-d") && !word.starts_with("--");
+        if short_d || RESERVED_ARGS.contains(&name) {
+            bail!("{word:?} can't be used, magiclip needs to control it");
+        }
+    }
+    Ok(words)
+}
+
+/// Lets the user pick some of `strings`, which get returned as they are (never
+/// the `search_keys`). Cancelling fzf selects nothing rather than failing.
+/// `extra_args` (see `parse_args()`) go to fzf, too, as does
+/// `$FZF_DEFAULT_OPTS`, which fzf reads itself. With `play_targets`,
+/// `PLAY_KEY` plays the current line (if there's a player).
+pub fn select(
+    strings: &[impl AsRef<str>],
+    search_keys: SearchKeys,
+    extra_args: &[String],
+    play_targets: Option<&[PlayTarget]>,
+) -> Result<Vec<String>> {
+    let mut fzf = Command::new("fzf");
+    fzf.args(extra_args);
+    // kept until fzf is done
+    let _targets_file = match play_targets {
+        Some(targets) => {
+            assert_eq!(strings.len(), targets.len(), "one play target per string");
+            bind_play(&mut fzf, targets)?
+        }
+        None => None,
+    };
+    // multi select
+    fzf.arg("-m");
+    // strings may span lines
+    fzf.args(["--read0", "--print0"]);
+    match search_keys {
+        SearchKeys::None => {}
+        SearchKeys::Appended(_) => {
+            fzf.arg("-i");
+        }
+        SearchKeys::Only(_) => {
+            fzf.args(["-i", "--delimiter", "\t", "--with-nth", "2.."]);
+        }
+    }
+    let mut fzf = fzf
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .spawn()
         .context("trying to spawn fzf")?;
 
     let stdin = fzf.stdin.take().context("trying to open fzf.stdin");
-    let thread_strings = strings.iter().map(|s| s.as_ref().to_owned()).collect_vec();
+    let clean_key = |key: &str| key.replace(['\t', '\n'], " ");
+    let thread_strings = match search_keys {
+        SearchKeys::None => strings.iter().map(|s| s.as_ref().to_owned()).collect_vec(),
+        SearchKeys::Appended(keys) => strings
+            .iter()
+            .zip_eq(keys)
+            .map(|(s, key)| format!("{}\t{}", s.as_ref(), clean_key(key)))
+            .collect_vec(),
+        SearchKeys::Only(keys) => {
+            assert_eq!(strings.len(), keys.len(), "one search key per string");
+            keys.iter()
+                .enumerate()
+                .map(|(i, key)| format!("{i}\t{}", clean_key(key)))
+                .collect_vec()
+        }
+    };
     let input_thread = std::thread::spawn(move || {
         stdin
             .expect("Could not open stdin")
-            .write_all(thread_strings.join("\n").as_bytes())
+            .write_all(thread_strings.join("\0").as_bytes())
             .expect("Failed to write to stdin");
     });
 
@@ -29,6 +163,13 @@ pub fn select(strings: &[impl AsRef<str>]) -> Result<Vec<String>> {
         .context("trying to write to fzf.stdin")?;
     let output = fzf.wait_with_output().context("trying to wait for fzf")?;
 
+    if output
+        .status
+        .code()
+        .is_some_and(|code| NO_SELECTION_CODES.contains(&code))
+    {
+        return Ok(Vec::new());
+    }
     if !output.status.success() {
         return Err(anyhow!(
             "fzf failed (status {})",
@@ -41,10 +182,119 @@ pub fn select(strings: &[impl AsRef<str>]) -> Result<Vec<String>> {
 
     output
         .stdout
-        .lines()
-        .map(|r| match r {
-            Ok(s) => Ok(s),
-            Err(e) => Err(anyhow::Error::from(e)),
+        .split(|byte| *byte == b'\0')
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            let s = String::from_utf8(s.to_vec()).context("fzf returned invalid UTF-8")?;
+            Ok(match search_keys {
+                SearchKeys::None => s,
+                SearchKeys::Appended(_) => s
+                    .rsplit_once('\t')
+                    .map_or(s.as_str(), |(s, _)| s)
+                    .to_owned(),
+                SearchKeys::Only(_) => {
+                    let index = s
+                        .split_once('\t')
+                        .and_then(|(i, _)| i.parse::<usize>().ok())
+                        .filter(|i| *i < strings.len())
+                        .with_context(|| format!("fzf returned a line without index: {s}"))?;
+                    strings[index].as_ref().to_owned()
+                }
+            })
         })
         .collect()
 }
+
+/// Makes `PLAY_KEY` play the current line's target, unless there's no player.
+/// The returned file lists the targets for fzf's lifetime.
+fn bind_play(fzf: &mut Command, targets: &[PlayTarget]) -> Result<Option<NamedTempFile>> {
+    if !ffmpeg::player_available() {
+        warn!("Can't preview clips, neither ffplay nor mpv were found");
+        return Ok(None);
+    }
+    let file = write_play_targets(targets)?;
+    fzf.arg("--bind").arg(format!(
+        "{PLAY_KEY}:execute-silent({exe} play-line {file} {{n}})",
+        exe = shell_quote(&std::env::current_exe()?.to_string_lossy()),
+        file = shell_quote(&file.path().to_string_lossy()),
+    ));
+    Ok(Some(file))
+}
+
+/// `targets` as JSON lines, for `read_play_target()`.
+fn write_play_targets(targets: &[PlayTarget]) -> Result<NamedTempFile> {
+    let mut file = NamedTempFile::new()?;
+    let mut writer = std::io::BufWriter::new(file.as_file_mut());
+    for target in targets {
+        serde_json::to_writer(&mut writer, target)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    drop(writer);
+    Ok(file)
+}
+
+/// The `index`-th target in a file from `write_play_targets()`.
+pub fn read_play_target(file: &std::path::Path, index: usize) -> Result<PlayTarget> {
+    let content = std::fs::read_to_string(file).with_context(|| format!("reading {file:?}"))?;
+    let line = content
+        .lines()
+        .nth(index)
+        .with_context(|| format!("no line {index} in {file:?}"))?;
+    Ok(serde_json::from_str(line)?)
+}
+
+/// `s` in single quotes for `sh`, which runs fzf's `execute` commands.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+
+    #[test]
+    fn parse_args() -> Result<()> {
+        assert_eq!(
+            super::parse_args(
+                r#" --height 40% --prompt='Line> ' --bind "ctrl-a:select-all" a\ b"#
+            )?,
+            [
+                "--height",
+                "40%",
+                "--prompt=Line> ",
+                "--bind",
+                "ctrl-a:select-all",
+                "a b",
+            ]
+        );
+        assert_eq!(super::parse_args("''")?, [""]);
+        assert!(super::parse_args("")?.is_empty());
+        for invalid in [
+            "--prompt='x",
+            "x\\",
+            "+m",
+            "--expect=ctrl-x",
+            "-d,",
+            "--with-nth 1",
+        ] {
+            assert!(super::parse_args(invalid).is_err(), "{invalid}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn play_targets() -> Result<()> {
+        let target = |video: &str, start| super::PlayTarget {
+            video: video.into(),
+            start,
+            end: start + 1000,
+        };
+        let targets = [target("/a.mkv", 0), target("/it's b.mkv", 5000)];
+        let file = super::write_play_targets(&targets)?;
+        assert_eq!(super::read_play_target(file.path(), 1)?, targets[1]);
+        assert!(super::read_play_target(file.path(), 2).is_err());
+        assert_eq!(super::shell_quote("it's"), r"'it'\''s'");
+        Ok(())
+    }
+}
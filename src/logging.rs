@@ -0,0 +1,100 @@
+use std::io::Write as _;
+
+use chrono::{SecondsFormat, Utc};
+use log::{
+    kv::{self, VisitSource},
+    LevelFilter,
+};
+use serde_json::{Map, Value};
+
+use crate::cli::LogFormat;
+
+/// Sets up `env_logger` at `level` (`RUST_LOG` taking precedence), writing
+/// records in `format`.
+pub fn init(level: LevelFilter, format: LogFormat) {
+    let mut builder = env_logger::builder();
+    builder
+        .default_format()
+        .filter_level(level)
+        .parse_default_env();
+    if format == LogFormat::Json {
+        builder.format(|buf, record| writeln!(buf, "{}", to_json(record)));
+    }
+    builder.init();
+}
+
+/// `record` as a JSON object with `time`, `level`, `target` and `message`,
+/// plus its key-values (e.g. `file`, `clip`) as further fields.
+fn to_json(record: &log::Record) -> Value {
+    let mut object = Map::new();
+    object.insert(
+        "time".to_owned(),
+        Utc::now()
+            .to_rfc3339_opts(SecondsFormat::Millis, true)
+            .into(),
+    );
+    object.insert("level".to_owned(), record.level().as_str().into());
+    object.insert("target".to_owned(), record.target().into());
+    object.insert("message".to_owned(), record.args().to_string().into());
+    // visiting only fails if the visitor does
+    let _ = record.key_values().visit(&mut Fields(&mut object));
+    Value::Object(object)
+}
+
+struct Fields<'a>(&'a mut Map<String, Value>);
+
+impl<'kvs> VisitSource<'kvs> for Fields<'_> {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        let value = if let Some(b) = value.to_bool() {
+            b.into()
+        } else if let Some(n) = value.to_u64() {
+            n.into()
+        } else if let Some(n) = value.to_i64() {
+            n.into()
+        } else {
+            value.to_string().into()
+        };
+        // the fixed fields win over clashing keys
+        self.0.entry(key.as_str()).or_insert(value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::Level;
+    use serde_json::json;
+
+    #[test]
+    fn to_json() {
+        let fields: &[(&str, &dyn log::kv::ToValue)] = &[
+            ("file", &"/videos/a.mkv"),
+            ("clips", &3u64),
+            ("ok", &true),
+            ("message", &"not the message"),
+        ];
+        let json = super::to_json(
+            &log::Record::builder()
+                .level(Level::Warn)
+                .target("magiclip")
+                .args(format_args!("Clip {n} failed", n = 2))
+                .key_values(&fields)
+                .build(),
+        );
+
+        assert!(json["time"].is_string());
+        let mut json = json;
+        json.as_object_mut().unwrap().remove("time");
+        assert_eq!(
+            json,
+            json!({
+                "level": "WARN",
+                "target": "magiclip",
+                "message": "Clip 2 failed",
+                "file": "/videos/a.mkv",
+                "clips": 3,
+                "ok": true,
+            })
+        );
+    }
+}
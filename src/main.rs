@@ -1,65 +1,292 @@
-#![feature(lazy_cell)]
-#![feature(exit_status_error)]
-#![feature(path_file_prefix)]
 #![deny(clippy::suspicious)]
 #![deny(clippy::perf)]
 #![warn(clippy::style)]
 #![warn(clippy::pedantic)]
 #![allow(clippy::default_trait_access)]
+#![allow(clippy::used_underscore_items)]
+#![allow(clippy::unnecessary_debug_formatting)]
 
-use anyhow::{anyhow, bail, Result};
-use clap::Parser;
+use anyhow::{anyhow, bail, ensure, Context as _, Result};
+use cli::{ClipArgs, Command, ExtractArgs, ScanArgs};
 use itertools::Itertools;
-use log::{error, info, warn, LevelFilter};
+use log::{debug, error, info, warn};
 use rayon::{
-    iter::{IntoParallelIterator as _, IntoParallelRefIterator as _, ParallelIterator as _},
+    iter::{
+        IndexedParallelIterator as _, IntoParallelIterator as _, IntoParallelRefIterator as _,
+        ParallelIterator as _,
+    },
     slice::ParallelSliceMut,
 };
-use std::path::{Path, PathBuf};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
 use sub::db::{self, SubDB};
 use walkdir::{DirEntry, WalkDir};
 
 mod cli;
 mod clip;
+mod config;
+mod extract_cache;
 mod ffmpeg;
 mod fzf;
+mod logging;
+mod manifest;
+mod overrides;
+mod rank;
 mod sub;
+mod template;
+mod tui;
 mod util;
+mod wanted;
 
-/// I think 255 is upper limit for filenames on unix. Besides the components
-/// mentioned below, there is a timestamp taking up approx. <15, and separation
-/// chars ~5, totalling to <20 chars extra. So keep the sum of these values
-/// below 235 (I guess). _Better yet_, leave a buffer, as the escaping taking place
-/// could push the length up a few chars (see `crate::util::escape_for_unix_filename()`)
+/// Default of `--filename-text-len`, in bytes. Filesystems with shorter names
+/// get less, see `util::FilenameLens`.
 pub const CLIP_FILENAME_TEXT_LEN: usize = 64;
 pub const CLIP_FILENAME_PATH_LEN: usize = 128;
+/// What the above leave of 255 for the timestamp, separators, extension,
+/// `--hash-suffix` and escaping.
+pub const CLIP_FILENAME_OVERHEAD: usize = 255 - CLIP_FILENAME_TEXT_LEN - CLIP_FILENAME_PATH_LEN;
 
-static _REGEX_SUBFILE: &str = r"(.\w{2})?.srt";
+/// Save the DB after every this many newly indexed videos during a scan, so a
+/// crash doesn't lose all progress.
+const SCAN_CHECKPOINT_EVERY: usize = 100;
+
+/// Suffix of subtitle files accompanying a video, after the video's file stem.
+/// Capture group 1 is the language suffix, if any (`movie.en.srt`).
+pub static REGEX_SUBFILE: &str = r"(?:\.(\w{2,3}))?\.srt";
 
-#[allow(clippy::too_many_lines)]
 fn main() -> anyhow::Result<()> {
-    env_logger::builder()
-        .default_format()
-        .filter_level(LevelFilter::Info)
-        .init();
+    let cli = cli::Cli::parse_with_config()?;
+    logging::init(cli.log_level(), cli.log_format);
+    ffmpeg::set_retries(cli.retries);
 
-    let args = cli::Args::parse();
+    let db_file = util::resolve_path(&cli.db_file)?;
+    let mut command = cli.command();
+    command.resolve_paths()?;
+    // runs from within fzf, the DB stays untouched
+    if let Command::PlayLine(args) = &command {
+        let target = fzf::read_play_target(&args.targets, args.index)?;
+        return ffmpeg::preview_play(&target.video, target.start, target.end);
+    }
+    // for DBs `SubDB::load()` fails on
+    if let Command::CheckDb(args) = &command {
+        return check_db(&db_file, args.repair);
+    }
 
-    info!("Loading or creating DB…");
-    let mut db = SubDB::load(args.db_file)?;
+    let legacy_db_file = cli::legacy_db_file();
+    if !db_file.exists() && legacy_db_file.is_file() {
+        warn!("{db_file:?} doesn't exist yet, but {legacy_db_file:?} (the old default) does. Move it over or pass it as --db-file to keep your index.");
+    }
+    info!("Loading or creating DB {db_file:?}…");
+    util::create_parent_dirs([db_file.as_path()])?;
+    let mut db = SubDB::load(db_file)?;
     info!("DB loaded with {n} entries", n = db.len());
+    util::handle_interrupts();
 
-    info!("Starting scan of {n} video folders…", n = args.paths.len());
-    let (_, errors): (Vec<()>, Vec<_>) = populate_db(args.paths.into_iter(), &mut db)
-        .into_iter()
-        .partition_result();
-    for err in errors {
-        warn!("Error trying to populate db: {err}");
+    match command {
+        Command::Scan(args) => {
+            let stats = scan(&mut db, &args)?;
+            info!("{stats}");
+            Ok(())
+        }
+        Command::Clip(args) => clip(&mut db, &args),
+        Command::List(args) => {
+            if args.errors {
+                print_errors(&db);
+            } else {
+                print_cached(&db, args.query.as_deref(), args.limit, args.print0);
+            }
+            Ok(())
+        }
+        Command::Prune => {
+            let n = db.prune();
+            info!("Pruned {n} entries whose videos are gone");
+            Ok(())
+        }
+        Command::Stats => {
+            db.set_read_only();
+            println!("{}", db.stats());
+            Ok(())
+        }
+        Command::ExtractSubs(args) => extract_subs(&args),
+        Command::PlayLine(_) | Command::CheckDb(_) => {
+            unreachable!("handled before loading the DB")
+        }
     }
-    info!(
-        "Scan finished. DB now consists of {n} entries",
-        n = db.len()
-    );
+}
+
+#[allow(clippy::too_many_lines)]
+fn clip(db: &mut SubDB, args: &ClipArgs) -> Result<()> {
+    let clip_dir = args
+        .clip_dir
+        .clone()
+        .expect("[ASSERT] clap requires --clip-dir for clipping");
+    info!("Clipping into {clip_dir:?}");
+    let filename_lens = args.filename_lens();
+    debug!("Clip filenames keep {filename_lens:?} bytes of the subtitle text and path");
+
+    // one per `--profile`
+    let clip_opts = args
+        .profile
+        .iter()
+        .unique()
+        .map(|&profile| ffmpeg::ClipOptions {
+            profile,
+            container: args.container.clone(),
+            pad_start: args.pad_start,
+            pad_end: args.pad_end,
+            overwrite: if args.skip_existing {
+                ffmpeg::Overwrite::Skip
+            } else if args.overwrite {
+                ffmpeg::Overwrite::Always
+            } else {
+                ffmpeg::Overwrite::Never
+            },
+            audio: match (&args.audio_lang, args.audio_stream) {
+                (Some(language), _) => Some(ffmpeg::AudioTrack::Language(language.clone())),
+                (None, Some(n)) => Some(ffmpeg::AudioTrack::Stream(n)),
+                (None, None) => None,
+            },
+            stage_dir: args.stage_dir.clone(),
+            loudnorm: args.loudnorm,
+            fade: args.fade,
+            crf: args.crf,
+            preset: args.preset,
+            sub_codec: None,
+        })
+        .collect_vec();
+    ensure!(!clip_opts.is_empty(), "no --profile given");
+    if let Some(stage_dir) = &args.stage_dir {
+        std::fs::create_dir_all(stage_dir)
+            .with_context(|| format!("creating stage dir {stage_dir:?}"))?;
+    }
+    for opts in &clip_opts {
+        ensure!(
+            !opts.loudnorm || !opts.settings().is_copy(),
+            "--loudnorm needs re-encoding, it doesn't work with --profile {profile}",
+            profile = opts.profile,
+        );
+        ensure!(
+            opts.fade == 0 || !opts.settings().is_copy(),
+            "--fade needs re-encoding, it doesn't work with --profile {profile}",
+            profile = opts.profile,
+        );
+        if (opts.crf.is_some() || opts.preset.is_some()) && !opts.takes_quality() {
+            warn!(
+                "--crf and --preset only apply to video encoding, ignoring them for --profile {profile}",
+                profile = opts.profile,
+            );
+        }
+        let Some(container) = &opts.container else {
+            continue;
+        };
+        let incompatible = ffmpeg::incompatible_codecs(opts.profile, container);
+        if !incompatible.is_empty() {
+            warn!(
+                "Container {container:?} probably can't hold {codecs} (profile {profile}), clipping will likely fail",
+                codecs = incompatible.join(", "),
+                profile = opts.profile,
+            );
+        }
+    }
+    if args.finder == cli::Finder::Tui {
+        if args.preview_clip {
+            warn!("--preview-clip only works with --finder fzf, not playing lines");
+        }
+        if !args.fzf_args.is_empty() {
+            warn!("Ignoring --fzf-args, they're for --finder fzf");
+        }
+    }
+    let output_template = args.output_template();
+    if !output_template.has_profile() {
+        if let Some((a, b)) = clip_opts
+            .iter()
+            .tuple_combinations()
+            .find(|(a, b)| a.ext() == b.ext())
+        {
+            bail!(
+                "Profiles {a} and {b} both make {ext} files, which would overwrite each other. Pass --subdir-per-profile or use {{profile}} in --output-template.",
+                a = a.profile,
+                b = b.profile,
+                ext = a.ext()
+            );
+        }
+    }
+    let profile_dir = |opts: &ffmpeg::ClipOptions| {
+        if args.subdir_per_profile {
+            clip_dir.join(opts.profile.to_string())
+        } else {
+            clip_dir.clone()
+        }
+    };
+
+    if let (Some(range), Some(source)) = (args.range, &args.source) {
+        ensure!(
+            args.output_template.is_none(),
+            "--output-template is for clipping subtitles, it doesn't work with --range"
+        );
+        for opts in &clip_opts {
+            clip_range(source, range, &profile_dir(opts), opts)?;
+        }
+        return Ok(());
+    }
+    if args.by == cli::ClipBy::Chapters {
+        ensure!(
+            args.output_template.is_none(),
+            "--output-template is for clipping subtitles, it doesn't work with --by chapters"
+        );
+        return clip_chapters(args, &clip_opts, profile_dir);
+    }
+
+    let mut timings = util::Timings::default();
+
+    // the fast path for clipping from a single episode: no walk, and only its
+    // lines get searched
+    let single_video = match (&args.scan.paths[..], &args.scan.paths_from) {
+        ([path], None) if path.is_file() && !path.is_symlink() => {
+            let exclude = args
+                .scan
+                .exclude
+                .iter()
+                .map(|glob| util::Glob::new(glob))
+                .collect::<Result<Vec<_>>>()?;
+            // skipped the same as a scan would
+            let video_path = find_videos(
+                std::iter::once(path.clone()),
+                Vec::new(),
+                &exclude,
+                args.scan.loose_subs,
+            )
+            // it's a file, so there are no errors
+            .find_map(Result::ok)
+            .with_context(|| {
+                format!("{path:?} isn't a video, or excluded by --exclude, nothing to clip")
+            })?;
+            Some(db::Key { video_path })
+        }
+        _ => None,
+    };
+    // the fast path for a stable library: no paths, no (accidental) rescan
+    let scan_stats = if let Some(key) = &single_video {
+        let span = util::Timings::start("scan");
+        let (_, found) = db.lookup_or_update(key, &index_options(&args.scan, db)?)?;
+        let how = if found == db::EntryFoundDiscriminants::Yes {
+            "cached"
+        } else {
+            "indexed"
+        };
+        info!("Single video {key:?}, {how}");
+        timings.finish(span);
+        None
+    } else if args.scan.is_empty() {
+        info!("Search-only mode (no scan), using the DB as is");
+        None
+    } else {
+        Some(timings.time("scan", || scan(db, &args.scan))?)
+    };
 
     // FIXME here may be a dividing point ("fork") between optimizing for cpu
     // or memory. Either keep all (Vec<(key, str)>, Vec<str>) in memory, or
@@ -74,17 +301,116 @@ fn main() -> anyhow::Result<()> {
     //
     // For starters, go with 1).
     info!("Formatting search strings…");
-    let search_map = db.as_identifying_strings().collect::<Vec<_>>();
+    let span = util::Timings::start("format");
+    let search_opts = sub::SearchOptions {
+        fold: args.fold_search,
+        text_only: args.text_only,
+        show_episode: args.show_episode,
+        clean: args.strip.iter().copied().collect(),
+        skip_intro: args.skip_intro.saturating_mul(1000),
+        skip_outro: args.skip_outro.saturating_mul(1000),
+        merge_overlapping: args.merge_overlapping,
+        fps_correction: args.fps_correction,
+        sub_offset: args.sub_offset,
+        pair_langs: args.pair_langs,
+        prefer_stream: args.prefer_stream.filter(|_| !args.all_streams),
+        keep_text: args.query.is_some(),
+    };
+    let (search_map, (search_keys, (spans, texts))): (Vec<_>, (Vec<_>, (Vec<_>, Vec<_>))) = db
+        .as_identifying_strings(search_opts, single_video.as_ref())
+        .map(|(key, line)| {
+            (
+                (key, line.display),
+                (line.search_key, ((line.start, line.end), line.text)),
+            )
+        })
+        .unzip();
+    // ranked here rather than by the finder, which (like fzf) keeps the input
+    // order until something's typed
+    let (search_map, search_keys, spans) = match &args.query {
+        Some(query) => {
+            let texts = texts.into_iter().flatten().collect_vec();
+            let ranked = rank::rank(query, &texts, args.limit);
+            (
+                rank::pick(search_map, &ranked),
+                rank::pick(search_keys, &ranked),
+                rank::pick(spans, &ranked),
+            )
+        }
+        None => (search_map, search_keys, spans),
+    };
+    let play_targets = (args.preview_clip || args.finder == cli::Finder::Tui).then(|| {
+        search_map
+            .iter()
+            .zip_eq(spans)
+            .map(|((key, _), (start, end))| fzf::PlayTarget {
+                // loose subtitle files without video just don't play
+                video: db
+                    .get(key)
+                    .and_then(|entry| entry.meta().clip_source().ok())
+                    .unwrap_or(&key.video_path)
+                    .to_owned(),
+                start,
+                end,
+            })
+            .collect_vec()
+    });
     let search_strings = search_map.iter().map(|(_, str)| str).collect_vec();
+    let search_keys = search_keys.into_iter().flatten().collect_vec();
+    let search_keys = if args.text_only {
+        fzf::SearchKeys::Only(&search_keys)
+    } else if args.fold_search {
+        fzf::SearchKeys::Appended(&search_keys)
+    } else {
+        fzf::SearchKeys::None
+    };
+    timings.finish(span);
+    // how many videos the scan went through, if it did
+    let videos = match (&single_video, &scan_stats) {
+        (Some(_), _) => Some(1),
+        (None, Some(scan_stats)) => Some(scan_stats.videos),
+        (None, None) => None,
+    };
+    if args.no_clip {
+        report_phases(
+            &timings,
+            scan_stats.as_ref(),
+            videos,
+            search_map.len(),
+            args.time_phases,
+        );
+        return Ok(());
+    }
 
-    let mut search_results = fzf::select(&search_strings)?;
+    let span = util::Timings::start("select");
+    let search_results = match (&args.replay_selection, &args.from_list) {
+        (Some(file), _) => replay_selection(file, &search_strings)?,
+        (None, Some(list)) => from_list(db, list, search_opts, &search_strings)?,
+        (None, None) => select(args, &search_strings, search_keys, play_targets.as_deref())?,
+    };
+    timings.finish(span);
+    if search_results.is_empty() {
+        info!("No clips selected, nothing to do");
+        return Ok(());
+    }
+    if let Some(file) = &args.save_selection {
+        let mut content = search_results.join("\n");
+        content.push('\n');
+        std::fs::write(file, content).with_context(|| format!("saving selection to {file:?}"))?;
+        info!("Saved the selection to {file:?}");
+    }
 
     info!(
-        "Sorting {count} results (and detecting duplicates)…",
+        "Detecting duplicates in {count} results…",
         count = search_results.len()
     );
-    search_results.as_mut_slice().par_sort();
-    let search_results = search_results.iter().dedup_with_count().collect_vec();
+    // keeps the selection order, for `--concat`
+    let counts = search_results.iter().counts();
+    let search_results = search_results
+        .iter()
+        .unique()
+        .map(|result| (counts[result], result))
+        .collect_vec();
 
     for (count, too_much) in search_results.iter().filter(|(count, _)| *count > 1) {
         error!("Search string appeared more than once ({count}x) in the result: {too_much}. This is a hard error, because it would lead to files beìng written to multiple times.");
@@ -104,31 +430,1285 @@ fn main() -> anyhow::Result<()> {
         })
         .collect::<Vec<_>>();
 
-    info!("Launching parallel clip creation");
-    search_results.par_iter().map(|(key, line)| {
-        info!("Preparing \"{line}\"");
-        let target_entry = match db.lookup(key)? {
-            db::EntryFound::Yes(entry) => entry,
-            db::EntryFound::YesButGone |            db::EntryFound::YesButChanged => bail!("While clipping, file changed right under our a$$es ({key:?})"),
-            db::EntryFound::No => panic!("LOGIC ERROR: took key directly from map, but map doesn't know about it anymore ({key:?})"),
+    info!("Resolving subtitles…");
+    let selections = search_results
+        .into_par_iter()
+        .map(|(key, line)| {
+            let Some(entry) = db.get(key) else {
+                panic!("LOGIC ERROR: took key directly from map, but map doesn't know about it anymore ({key:?})");
+            };
+            // TODO PERF maybe I don't have to recalc every sub string but instead can
+            // keep the sub around. OR parallelize.
+            let (sub_path, sub) = entry.subs(search_opts).find(|(sub_path, sub)| entry.display_string(sub_path, sub, search_opts) == *line).expect("LOGIC ERROR: The entry under $key doesn't have a corresponding sub line ({entry:?})");
+
+            let video = entry.meta().clip_source()?;
+            // needs the live video, unlike everything else up to clipping
+            let fps = if args.timestamp_format == sub::TimestampFormat::Frames {
+                Some(entry.meta().frame_rate().with_context(|| format!("--timestamp-format frames for {key:?}"))?)
+            } else {
+                None
+            };
+            let (text, start) = sub.filename_parts(args.timestamp_format, fps, filename_lens.text, search_opts.clean);
+            let vars = template::Vars {
+                video,
+                stream: sub_path,
+                sub_num: sub.num,
+                text,
+                start,
+                path_len: filename_lens.path,
+                hash: hash_suffix(video, &sub),
+            };
+            let outfiles = clip_opts.iter().map(|opts| output_template.render(&vars, opts)).collect();
+
+            Ok(Selection { key, line, entry, sub_path, sub, context: None, outfiles })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let selections = if args.span {
+        vec![span_selection(selections, search_opts)?]
+    } else {
+        selections
+    };
+
+    let selections = selections
+        .into_iter()
+        .filter(|Selection { line, sub, .. }| {
+            let duration = ffmpeg::duration_to_millis(sub.duration());
+            let in_range = args.min_duration.is_none_or(|min| duration >= min)
+                && args.max_duration.is_none_or(|max| duration <= max);
+            if !in_range {
+                warn!("Skipping \"{line}\": its duration of {duration}ms is outside of --min-duration/--max-duration");
+            }
+            in_range
+        })
+        .collect_vec();
+
+    // the same line in several streams (e.g. full and forced subs) is a single clip
+    let mut outfiles = HashSet::new();
+    let selections = selections
+        .into_iter()
+        .filter(
+            |Selection {
+                 line,
+                 outfiles: selection_outfiles,
+                 ..
+             }| {
+                let first = outfiles.insert(selection_outfiles.clone());
+                if !first {
+                    warn!(
+                        "\"{line}\" was selected from several subtitle streams, clipping it once"
+                    );
+                }
+                first
+            },
+        )
+        .collect_vec();
+
+    let selections = if args.context > 0 {
+        union_overlapping(
+            selections
+                .into_iter()
+                .map(|selection| selection.with_context(args.context, search_opts))
+                .collect(),
+            search_opts,
+        )
+    } else {
+        selections
+    };
+
+    let selections = match args.sort {
+        Some(order) => number_selections(selections, order),
+        None => selections,
+    };
+
+    let several_profiles = clip_opts.len() > 1;
+    let clip_dir = &clip_dir;
+    if args.clip_format == cli::ClipFormat::SrtWindow {
+        return export_srt_windows(&selections, clip_dir, clip_opts[0].overwrite);
+    }
+
+    let jobs = selections
+        .iter()
+        .flat_map(|selection| {
+            clip_opts.iter().enumerate().map(move |(i, opts)| Job {
+                selection,
+                opts,
+                outfile: clip_dir.join(&selection.outfiles[i]),
+                label: if several_profiles {
+                    format!(
+                        "\"{line}\" ({profile})",
+                        line = selection.line,
+                        profile = opts.profile
+                    )
+                } else {
+                    format!("\"{line}\"", line = selection.line)
+                },
+                sidecars: i == 0 || selection.outfiles[i] != selection.outfiles[0],
+            })
+        })
+        .collect_vec();
+
+    let manifest = manifest::Manifest::open(clip_dir)?;
+    let manifest_clip = |Job {
+                             selection: Selection { sub, .. },
+                             opts,
+                             outfile,
+                             ..
+                         }: &Job| {
+        let (start_ms, duration_ms) = ffmpeg::clip_window(sub, opts).unwrap_or_default();
+        manifest::Clip {
+            outfile: opts.outfile(outfile),
+            start_ms,
+            duration_ms,
+            options: opts.output_hash(),
+        }
+    };
+    // `--overwrite` means clipping again whatever's recorded
+    let (done, to_clip): (Vec<_>, Vec<_>) = jobs.iter().partition(|job| {
+        job.opts.overwrite != ffmpeg::Overwrite::Always && manifest.is_done(&manifest_clip(job))
+    });
+    if !done.is_empty() {
+        info!(
+            "Skipping {n} clips which are already done according to {file:?}",
+            n = done.len(),
+            file = manifest::MANIFEST_FILENAME
+        );
+    }
+    let record = |job: &Job, error: Option<&anyhow::Error>| {
+        let Job {
+            selection: Selection { key, sub, .. },
+            outfile,
+            ..
+        } = job;
+        let record = manifest::Record {
+            video_path: key.video_path.clone(),
+            sub_num: sub.num,
+            clip: manifest_clip(job),
+            status: if error.is_some() {
+                manifest::Status::Failed
+            } else {
+                manifest::Status::Done
+            },
+            error: error.map(|e| format!("{e:#}")),
+        };
+        // losing track of a clip only means redoing it next time
+        if let Err(e) = manifest.record(&record) {
+            warn!("Couldn't record {outfile:?} in the manifest: {e:#}");
+        }
+    };
+
+    // once up front, instead of every clip racing to create its dir
+    util::create_parent_dirs(to_clip.iter().map(|job| job.outfile.as_path()))?;
+
+    let clip_one = |Job {
+                        selection:
+                            Selection {
+                                key, entry, sub, ..
+                            },
+                        opts,
+                        outfile,
+                        label,
+                        sidecars,
+                    }: &Job|
+     -> Result<()> {
+        info!("Preparing {label}");
+
+        // everything up to here is served from the DB cache, only ffmpeg needs the live video
+        match entry.has_changed()? {
+            db::EntryChanged::No => {}
+            db::EntryChanged::Yes | db::EntryChanged::Gone | db::EntryChanged::Unreachable => {
+                bail!("While clipping, file changed right under our a$$es ({key:?})")
+            }
+        }
+
+        info!("Clipping {label}");
+        let mut reported = 0.0;
+        let mut log_progress = |done: f64| {
+            let quarter = (done * 4.0).floor() / 4.0;
+            if quarter > reported && quarter < 1.0 {
+                reported = quarter;
+                info!("{label} {percent:.0}%…", percent = quarter * 100.0);
+            }
         };
-        // TODO PERF maybe I don't have to recalc every sub string but instead can
-        // keep the sub around. OR parallelize.
-        let target_sub = target_entry.sub_files().par_iter().flat_map(|(_, subs)|subs).find_any(|sub| &sub.as_identifying_string(&key.video_path, Default::default()) == line).expect("LOGIC ERROR: The entry under $key doesn't have a corresponding sub line ({entry:?})");
-        let outfile = target_sub.as_identifying_string(target_entry.meta().video_path(), sub::SubtitleStringFormatOptions::Filename);
-        let profile_string = args.profile.to_string();
-        let outfile = args.clip_dir.join(if args.subdir_per_profile {&profile_string} else {""}).join(outfile);
+        let video_ms = *entry.meta().duration_ms();
+        let clipped = ffmpeg::clip(
+            entry.meta().clip_source()?,
+            outfile,
+            sub,
+            opts,
+            video_ms,
+            Some(&mut log_progress),
+        )?;
 
-        info!("Clipping \"{line}\"");
-        ffmpeg::clip(target_entry.meta().video_path(), outfile, target_sub.start_time, target_sub.end_time, args.profile)?;
+        let window @ (clip_start, clip_duration) = ffmpeg::clip_window(sub, opts)?;
+        match clipped {
+            ffmpeg::Clipped::Encoded { duration_ms } if args.verify_clips => {
+                let clip = opts.outfile(outfile);
+                let expected = video_ms.map_or(clip_duration, |video_ms| {
+                    ffmpeg::expected_clip_len(window, video_ms)
+                });
+                if let Err(e) = ffmpeg::verify_clip(&clip, duration_ms, expected, opts) {
+                    // so neither the retry nor the next run trips over it
+                    if let Err(e) = std::fs::remove_file(&clip) {
+                        warn!("Couldn't remove broken clip {clip:?}: {e}");
+                    }
+                    return Err(e.context(format!("verifying {clip:?}")));
+                }
+            }
+            // clips skipped as existing aren't this run's to judge, let alone delete
+            _ => {}
+        }
+        if args.poster && *sidecars {
+            let mut poster_file = outfile.clone().into_os_string();
+            poster_file.push(".jpg");
+            let poster =
+                ffmpeg::millis_to_timestamp(clip_start + clip_duration / 2).and_then(|midpoint| {
+                    ffmpeg::poster_frame(entry.meta().clip_source()?, poster_file, midpoint)
+                });
+            // the clip itself is fine, so don't fail it
+            if let Err(e) = poster {
+                warn!("Couldn't extract poster frame for {label}: {e:#}");
+            }
+        }
+        if args.export_srt && *sidecars {
+            let mut srt_file = outfile.clone().into_os_string();
+            srt_file.push(".srt");
+            sub.write_shifted_srt(clip_start, srt_file)?;
+        }
 
-        info!("\"{line}\" done!");
+        info!("{label} done!");
         Ok(())
-    }).for_each(|result| if let Err(e) = result { error!("One of the clips failed: {e}") });
+    };
+
+    info!("Launching parallel clip creation");
+    let outcomes = timings.time("clip", || clip_all(&to_clip, clip_one, record));
+    if util::interrupted() {
+        bail!("Interrupted, run the same command again to resume with the remaining clips");
+    }
+
+    let total = to_clip.len();
+    report_phases(
+        &timings,
+        scan_stats.as_ref(),
+        videos,
+        search_map.len(),
+        args.time_phases,
+    );
+    for outcome in &outcomes {
+        if let Some(output) = &outcome.output {
+            debug!(
+                clip = outcome.line.as_str(), file:% = output.display();
+                "Clipped {line} into {output:?}", line = outcome.line
+            );
+        }
+    }
+    let failed = log_outcomes(&outcomes);
+    if failed == 0 {
+        if let Some(concat_file) = &args.concat {
+            // the first profile's, as there's only one file
+            let clips = jobs
+                .iter()
+                .filter(|job| job.opts == &clip_opts[0])
+                .map(|job| job.opts.outfile(&job.outfile))
+                .collect_vec();
+            info!(
+                "Concatenating {n} clips into {concat_file:?}…",
+                n = clips.len()
+            );
+            ffmpeg::concat(&clips, concat_file)
+                .with_context(|| format!("concatenating clips into {concat_file:?}"))?;
+        }
+        return Ok(());
+    }
+    if args.concat.is_some() {
+        error!("Not concatenating, since some clips are missing");
+    }
+    bail!("{failed}/{total} clips failed")
+}
+
+/// Writes an SRT per source video of `selections` into `clip_dir`, with the
+/// selected lines at their original times.
+fn export_srt_windows(
+    selections: &[Selection],
+    clip_dir: &Path,
+    overwrite: ffmpeg::Overwrite,
+) -> Result<()> {
+    std::fs::create_dir_all(clip_dir).with_context(|| format!("creating dir {clip_dir:?}"))?;
+    let by_video = selections
+        .iter()
+        .into_group_map_by(|selection| &selection.key.video_path);
+    let mut names = HashSet::new();
+    for (video, mut selections) in by_video.into_iter().sorted_by_key(|(video, _)| *video) {
+        selections.sort_by_key(|selection| selection.sub.start());
+        let stem = util::escape_for_filename(
+            &video.file_stem().unwrap_or_default().to_string_lossy(),
+            util::FilenameTarget::native(),
+        );
+        // videos of the same name in different dirs
+        let mut name = format!("{stem}.srt");
+        for n in 2.. {
+            if names.insert(name.clone()) {
+                break;
+            }
+            name = format!("{stem} ({n}).srt");
+        }
+        let file = clip_dir.join(name);
+        if file.exists() {
+            match overwrite {
+                ffmpeg::Overwrite::Never => {
+                    bail!("{file:?} already exists (pass --overwrite or --skip-existing)")
+                }
+                ffmpeg::Overwrite::Skip => {
+                    info!("Skipping existing {file:?}");
+                    continue;
+                }
+                ffmpeg::Overwrite::Always => {}
+            }
+        }
+        sub::Subtitle::write_srt(selections.iter().map(|selection| &*selection.sub), &file)?;
+        info!(
+            "Wrote {n} lines of {video:?} to {file:?}",
+            n = selections.len()
+        );
+    }
+    Ok(())
+}
+
+/// What became of a `Job`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ClipOutcome {
+    /// `Job::label`
+    line: String,
+    /// the clip, if it was made
+    output: Option<PathBuf>,
+    error: Option<String>,
+}
+
+/// Logs how many of `outcomes` succeeded and why the others failed, returning
+/// how many did.
+fn log_outcomes(outcomes: &[ClipOutcome]) -> usize {
+    let failures = outcomes
+        .iter()
+        .filter_map(|outcome| Some((&outcome.line, outcome.error.as_ref()?)))
+        .collect_vec();
+    let total = outcomes.len();
+    let ok = total - failures.len();
+    info!(ok, total; "{ok}/{total} clips succeeded");
+    for (line, e) in &failures {
+        error!(clip = line.as_str(), error = e.as_str(); "Failed to clip {line}: {e}");
+    }
+    failures.len()
+}
+
+/// What `clip_all()` needs to know of a job.
+trait ClipJob {
+    /// for logging, see `ClipOutcome::line`
+    fn label(&self) -> &str;
+    /// the clip it makes
+    fn output(&self) -> PathBuf;
+}
+
+impl ClipJob for Job<'_> {
+    fn label(&self) -> &str {
+        &self.label
+    }
+
+    fn output(&self) -> PathBuf {
+        self.opts.outfile(&self.outfile)
+    }
+}
+
+/// Runs `clip_one` on all `jobs` in parallel, giving failures a second chance,
+/// and `record`s how it went. Jobs that didn't start before Ctrl-C fail.
+fn clip_all<J: ClipJob + Sync>(
+    jobs: &[&J],
+    clip_one: impl Fn(&J) -> Result<()> + Sync,
+    record: impl Fn(&J, Option<&anyhow::Error>) + Sync,
+) -> Vec<ClipOutcome> {
+    let results = jobs
+        .par_iter()
+        .map(|job| {
+            // what's running gets to finish (unless ffmpeg got the Ctrl-C, too)
+            if util::interrupted() {
+                return Err(anyhow!("interrupted"));
+            }
+            let result = clip_one(job);
+            if result.is_ok() {
+                record(job, None);
+            }
+            result
+        })
+        .collect::<Vec<_>>();
+
+    let failed = results.iter().filter(|result| result.is_err()).count();
+    // failures now are most likely ffmpeg getting the Ctrl-C, not worth retrying
+    if failed > 0 && !util::interrupted() {
+        info!("Retrying {failed} failed clips once…");
+    }
+    // ffmpeg hiccups on busy systems are common, so give each failure a second chance
+    jobs.par_iter()
+        .zip(results.into_par_iter())
+        .map(|(job, result)| {
+            let result = match result {
+                Err(e) if !util::interrupted() => {
+                    warn!(
+                        clip = job.label(), error:% = e;
+                        "Clip {label} failed, retrying: {e}", label = job.label()
+                    );
+                    let result = clip_one(job);
+                    record(job, result.as_ref().err());
+                    result
+                }
+                result => result,
+            };
+            ClipOutcome {
+                line: job.label().to_owned(),
+                output: result.is_ok().then(|| job.output()),
+                error: result.err().map(|e| format!("{e:#}")),
+            }
+        })
+        .collect()
+}
+
+/// 8 hex digits identifying `sub` of `video` for `--hash-suffix`, the same
+/// every run.
+fn hash_suffix(video: &Path, sub: &sub::Subtitle) -> String {
+    let mut bytes = video.as_os_str().as_encoded_bytes().to_vec();
+    bytes.extend((sub.num as u64).to_le_bytes());
+    bytes.extend(ffmpeg::duration_to_millis(sub.start()).to_le_bytes());
+    format!("{:08x}", util::fnv1a(&bytes) >> 32)
+}
+
+/// A line picked in the finder, resolved to its DB entry and subtitle.
+struct Selection<'a> {
+    key: &'a db::Key,
+    line: &'a str,
+    entry: &'a db::Entry,
+    sub_path: &'a db::SubPath,
+    /// owned once widened by `--context`
+    sub: Cow<'a, sub::Subtitle>,
+    /// the lines `sub` spans once widened by `--context`, as indices into
+    /// `stream_subs()`
+    context: Option<RangeInclusive<usize>>,
+    /// per `--profile`, rendered from `--output-template` relative to the clip
+    /// dir, without extension
+    outfiles: Vec<PathBuf>,
+}
+
+/// A selection to be clipped with one of the `--profile`s.
+struct Job<'a> {
+    selection: &'a Selection<'a>,
+    opts: &'a ffmpeg::ClipOptions,
+    /// its `selection.outfiles` in the clip dir
+    outfile: PathBuf,
+    /// for logging, the selected line (and profile, if there are several)
+    label: String,
+    /// whether to write `--poster` and `--export-srt` files, which happens only
+    /// once per dir
+    sidecars: bool,
+}
+
+impl Selection<'_> {
+    /// Widens the clip to the `n` subtitles before and after it in its stream,
+    /// as searched with `search_opts`.
+    fn with_context(mut self, n: usize, search_opts: sub::SearchOptions) -> Self {
+        let subs = self.stream_subs(search_opts);
+        let i = subs
+            .iter()
+            .position(|sub| *sub == self.sub)
+            .expect("[ASSERT] selected from this stream");
+        let context = i.saturating_sub(n)..=(i + n).min(subs.len() - 1);
+        self.sub = Cow::Owned(
+            sub::Subtitle::spanning(self.sub.num, subs[context.clone()].iter().map(|sub| &**sub))
+                .expect("[ASSERT] context contains the selected sub"),
+        );
+        self.context = Some(context);
+        self
+    }
+
+    /// The subtitles of the selected one's stream, as searched with
+    /// `search_opts`.
+    fn stream_subs(&self, search_opts: sub::SearchOptions) -> Vec<Cow<'_, sub::Subtitle>> {
+        self.entry
+            .subs(search_opts)
+            .filter(|(sub_path, _)| *sub_path == self.sub_path)
+            .map(|(_, sub)| sub)
+            .collect_vec()
+    }
+}
+
+/// The selection of `--span`: from the earlier of exactly two `selections` to
+/// the end of the later one, with all lines in between in its subtitle (as
+/// searched with `search_opts`). Its filenames get the end, so it can't
+/// collide with a clip of just the first line.
+fn span_selection(
+    selections: Vec<Selection>,
+    search_opts: sub::SearchOptions,
+) -> Result<Selection> {
+    let n = selections.len();
+    let [a, b] = <[Selection; 2]>::try_from(selections).map_err(|_| {
+        anyhow!("--span needs exactly two lines selected, the first and the last one, not {n}")
+    })?;
+    ensure!(
+        a.key == b.key,
+        "--span needs both lines from the same video, not from {a:?} and {b:?}",
+        a = a.key.video_path,
+        b = b.key.video_path
+    );
+    let (mut first, last) = if a.sub.start() <= b.sub.start() {
+        (a, b)
+    } else {
+        (b, a)
+    };
+    let between = first
+        .entry
+        .subs(search_opts)
+        .filter(|(sub_path, _)| *sub_path == first.sub_path)
+        .map(|(_, sub)| sub)
+        .filter(|sub| sub.start() >= first.sub.start() && sub.end() <= last.sub.end())
+        .collect_vec();
+    let subs = [&*first.sub, &*last.sub]
+        .into_iter()
+        .chain(between.iter().map(|sub| &**sub))
+        .sorted_by_key(|sub| sub.start())
+        .dedup()
+        .collect_vec();
+    let span = sub::Subtitle::spanning(first.sub.num, subs.iter().copied())
+        .expect("[ASSERT] two subs given");
+    info!(
+        "Clipping from \"{first}\" to \"{last}\" ({n} lines) as one clip",
+        first = first.line,
+        last = last.line,
+        n = subs.len(),
+    );
+
+    let suffix = util::escape_for_filename(
+        &format!(
+            " (to {end})",
+            end = ffmpeg::millis_to_string(ffmpeg::duration_to_millis(span.end()))
+        ),
+        util::FilenameTarget::native(),
+    );
+    for outfile in &mut first.outfiles {
+        let mut name = outfile.file_name().unwrap_or_default().to_owned();
+        name.push(&suffix);
+        outfile.set_file_name(name);
+    }
+    first.sub = Cow::Owned(span);
+    Ok(first)
+}
+
+/// Unions selections of the same video whose clips overlap (e.g. due to
+/// `--context`) into the earliest one, so nothing gets clipped twice. Keeps
+/// the order otherwise. Context lines of the same stream (as searched with
+/// `search_opts`) are joined once, even if both selections have them.
+fn union_overlapping(
+    selections: Vec<Selection>,
+    search_opts: sub::SearchOptions,
+) -> Vec<Selection> {
+    let mut by_time = selections.into_iter().enumerate().collect_vec();
+    by_time.sort_by(|(_, a), (_, b)| {
+        (&a.key.video_path, a.sub.start()).cmp(&(&b.key.video_path, b.sub.start()))
+    });
+
+    let mut unioned: Vec<(usize, Selection)> = Vec::new();
+    for (i, selection) in by_time {
+        match unioned.last_mut() {
+            Some((first_i, last))
+                if last.key == selection.key && selection.sub.start() <= last.sub.end() =>
+            {
+                info!(
+                    "Clipping \"{line}\" together with \"{last_line}\", their context overlaps",
+                    line = selection.line,
+                    last_line = last.line
+                );
+                *first_i = (*first_i).min(i);
+                let same_stream = last.sub_path == selection.sub_path;
+                let span = match (&last.context, &selection.context) {
+                    (Some(a), Some(b)) if same_stream => {
+                        let context = *a.start().min(b.start())..=*a.end().max(b.end());
+                        let subs = last.stream_subs(search_opts);
+                        let span = sub::Subtitle::spanning(
+                            last.sub.num,
+                            subs[context.clone()].iter().map(|sub| &**sub),
+                        );
+                        last.context = Some(context);
+                        span
+                    }
+                    _ => sub::Subtitle::spanning(last.sub.num, [&*last.sub, &*selection.sub]),
+                };
+                last.sub = Cow::Owned(span.expect("[ASSERT] two subs given"));
+            }
+            _ => unioned.push((i, selection)),
+        }
+    }
+    unioned.sort_by_key(|(i, _)| *i);
+    unioned
+        .into_iter()
+        .map(|(_, selection)| selection)
+        .collect()
+}
+
+/// Sorts `selections` by `order` and prefixes their filenames with their
+/// position, like `001_`.
+fn number_selections(mut selections: Vec<Selection>, order: cli::ClipOrder) -> Vec<Selection> {
+    match order {
+        cli::ClipOrder::Selection => {}
+        cli::ClipOrder::Chrono => selections.sort_by(|a, b| {
+            util::natural_cmp(
+                &a.key.video_path.to_string_lossy(),
+                &b.key.video_path.to_string_lossy(),
+            )
+            .then_with(|| a.sub.start().cmp(&b.sub.start()))
+        }),
+        cli::ClipOrder::Alpha => {
+            selections.sort_by_cached_key(|selection| selection.sub.plain_text().to_lowercase());
+        }
+    }
+    let width = selections.len().to_string().len().max(3);
+    for (i, selection) in selections.iter_mut().enumerate() {
+        for outfile in &mut selection.outfiles {
+            let mut name = std::ffi::OsString::from(format!("{n:0width$}_", n = i + 1));
+            name.push(outfile.file_name().unwrap_or_default());
+            outfile.set_file_name(name);
+        }
+    }
+    selections
+}
+
+/// Prints the subtitle lines cached in the DB, optionally only those containing
+/// `query` (case- and accent-insensitive), ranked by `rank::rank_all()`. Never
+/// touches the videos themselves.
+fn print_cached(db: &SubDB, query: Option<&str>, limit: Option<usize>, print0: bool) {
+    let search_opts = sub::SearchOptions {
+        keep_text: query.is_some(),
+        ..Default::default()
+    };
+    let mut lines = db
+        .as_identifying_strings(search_opts, None)
+        .map(|(_, line)| (line.display, line.text))
+        .collect::<Vec<_>>();
+    lines.par_sort();
+    if let Some(query) = query {
+        // anywhere in the line, the path included, as ranking only looks at the text
+        let folded = util::fold_for_search(query);
+        lines.retain(|(line, _)| util::fold_for_search(line).contains(&folded));
+    }
+    let (mut lines, texts): (Vec<_>, Vec<_>) = lines.into_iter().unzip();
+    if let Some(query) = query {
+        let texts = texts.into_iter().flatten().collect_vec();
+        lines = rank::pick(lines, &rank::rank_all(query, &texts, limit));
+    }
+    let end = if print0 { '\0' } else { '\n' };
+    for line in lines {
+        print!("{line}{end}");
+    }
+}
+
+/// Prints the sub errors stored with each video, see `list --errors`.
+fn print_errors(db: &SubDB) {
+    let failed = db
+        .iter()
+        .filter(|(_, entry)| !entry.meta().errors().is_empty())
+        .sorted_by_key(|(key, _)| &key.video_path);
+    for (key, entry) in failed {
+        println!("{}", key.video_path.display());
+        for error in entry.meta().errors() {
+            println!("  {}", error.replace('\n', "\n  "));
+        }
+    }
+}
+
+/// Reports what's readable of the DB and, with `repair`, rewrites it with only
+/// that, see `check-db`.
+fn check_db(db_file: &Path, repair: bool) -> Result<()> {
+    let sub::db::Salvaged {
+        db,
+        lost,
+        truncated,
+    } = SubDB::salvage(db_file)?;
+    for reason in &lost {
+        warn!("Lost {reason}");
+    }
+    if truncated {
+        warn!("The DB ends within an entry, it and all entries after it are lost");
+    }
+    println!(
+        "Recovered {recovered} entries, lost {lost}{more}",
+        recovered = db.len(),
+        lost = lost.len() + usize::from(truncated),
+        more = if truncated { " or more" } else { "" },
+    );
+    if lost.is_empty() && !truncated {
+        println!("The DB is fine");
+    } else if repair {
+        let backup = db.repair()?;
+        println!("Rewrote {db_file:?} with the recovered entries, the original is at {backup:?}");
+    } else {
+        println!("Pass --repair to rewrite it with only the recovered entries");
+    }
+    Ok(())
+}
+
+/// Clips `range` of `source` into `clip_dir`, as if there was a subtitle.
+fn clip_range(
+    source: &Path,
+    range: cli::TimeRange,
+    clip_dir: &Path,
+    clip_opts: &ffmpeg::ClipOptions,
+) -> Result<()> {
+    let start = ffmpeg::millis_to_timestamp(range.start)?;
+    let end = ffmpeg::millis_to_timestamp(range.end)?;
+    let sub = sub::Subtitle::from(srtlib::Subtitle::new(0, start, end, String::new()));
+
+    let stem = source
+        .file_stem()
+        .with_context(|| format!("no file name in {source:?}"))?
+        .to_string_lossy();
+    let basename = util::escape_for_filename(
+        &format!(
+            "{stem} [{start}-{end}]",
+            start = ffmpeg::timestamp_to_string(start),
+            end = ffmpeg::timestamp_to_string(end),
+        ),
+        util::FilenameTarget::native(),
+    );
+    let outfile = clip_dir.join(basename);
+
+    std::fs::create_dir_all(clip_dir).with_context(|| format!("creating dir {clip_dir:?}"))?;
+    info!(
+        "Clipping {source:?} into {outfile:?}",
+        outfile = clip_opts.outfile(&outfile)
+    );
+    ffmpeg::clip(source, &outfile, &sub, clip_opts, None, None)?;
     Ok(())
 }
 
-fn populate_db(paths: impl Iterator<Item = PathBuf>, db: &mut sub::db::SubDB) -> Vec<Result<()>> {
+/// Lets the user pick chapters of the videos in `args.scan` and clips them like
+/// subtitle lines, named `{video stem} - {chapter}`.
+fn clip_chapters(
+    args: &ClipArgs,
+    clip_opts: &[ffmpeg::ClipOptions],
+    profile_dir: impl Fn(&ffmpeg::ClipOptions) -> PathBuf,
+) -> Result<()> {
+    ensure!(
+        !args.scan.is_empty(),
+        "--by chapters needs videos to scan, chapters aren't kept in the DB"
+    );
+    let chapters = probe_chapters(&args.scan)?;
+    // chapters don't have text, so the title stands in for a subtitle's
+    let choices = chapters
+        .iter()
+        .map(|(video, chapter)| {
+            let start = ffmpeg::millis_to_timestamp(chapter.start)?;
+            let end = ffmpeg::millis_to_timestamp(chapter.end)?;
+            let sub = sub::Subtitle::from(srtlib::Subtitle::new(
+                chapter.number,
+                start,
+                end,
+                chapter.name(),
+            ));
+            let display = format!(
+                "{video} | {name} [{start}-{end}]",
+                video = video.display(),
+                name = chapter.name(),
+                start = ffmpeg::timestamp_to_string(start),
+                end = ffmpeg::timestamp_to_string(end),
+            );
+            Ok((display, video, chapter, sub))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if choices.is_empty() {
+        info!("No chapters found, nothing to do");
+        return Ok(());
+    }
+
+    let play_targets = (args.preview_clip || args.finder == cli::Finder::Tui).then(|| {
+        choices
+            .iter()
+            .map(|(_, video, chapter, _)| fzf::PlayTarget {
+                video: (*video).clone(),
+                start: chapter.start,
+                end: chapter.end,
+            })
+            .collect_vec()
+    });
+    let selected = select(
+        args,
+        &choices.iter().map(|(display, ..)| display).collect_vec(),
+        fzf::SearchKeys::None,
+        play_targets.as_deref(),
+    )?;
+    if selected.is_empty() {
+        info!("No chapters selected, nothing to do");
+        return Ok(());
+    }
+    let selected = selected.iter().map(String::as_str).collect::<HashSet<_>>();
+    let text_len = args.filename_lens().text;
+    let jobs = choices
+        .iter()
+        .filter(|(display, ..)| selected.contains(display.as_str()))
+        .cartesian_product(clip_opts)
+        .map(|((display, video, chapter, sub), opts)| {
+            let stem = video.file_stem().unwrap_or_default().to_string_lossy();
+            let basename = util::escape_for_filename(
+                &format!(
+                    "{stem} - {number:02} {name}",
+                    number = chapter.number,
+                    name = util::truncate_at_word(&chapter.name(), text_len),
+                ),
+                util::FilenameTarget::native(),
+            );
+            ChapterJob {
+                label: display,
+                video,
+                sub,
+                opts,
+                outfile: profile_dir(opts).join(basename),
+            }
+        })
+        .collect_vec();
+    util::create_parent_dirs(jobs.iter().map(|job| job.outfile.as_path()))?;
+
+    info!("Launching parallel clip creation");
+    let outcomes = clip_all(
+        &jobs.iter().collect_vec(),
+        |job| {
+            info!("Clipping {label}", label = job.label);
+            ffmpeg::clip(job.video, &job.outfile, job.sub, job.opts, None, None)?;
+            Ok(())
+        },
+        // chapters don't go into the manifest
+        |_, _| {},
+    );
+    let failed = log_outcomes(&outcomes);
+    ensure!(
+        failed == 0,
+        "{failed}/{total} clips failed",
+        total = outcomes.len()
+    );
+    Ok(())
+}
+
+/// A chapter to be clipped with one of the `--profile`s, see `clip_chapters()`.
+struct ChapterJob<'a> {
+    label: &'a str,
+    video: &'a Path,
+    /// the chapter as a subtitle, titled with its name
+    sub: &'a sub::Subtitle,
+    opts: &'a ffmpeg::ClipOptions,
+    outfile: PathBuf,
+}
+
+impl ClipJob for ChapterJob<'_> {
+    fn label(&self) -> &str {
+        self.label
+    }
+
+    fn output(&self) -> PathBuf {
+        self.opts.outfile(&self.outfile)
+    }
+}
+
+/// The chapters of all videos in `scan`, skipping videos that fail to probe.
+fn probe_chapters(scan: &ScanArgs) -> Result<Vec<(PathBuf, ffmpeg::Chapter)>> {
+    let listed = match &scan.paths_from {
+        Some(list) => read_path_list(list)?,
+        None => Vec::new(),
+    };
+    let exclude = scan
+        .exclude
+        .iter()
+        .map(|glob| util::Glob::new(glob))
+        .collect::<Result<Vec<_>>>()?;
+    let mut videos = Vec::new();
+    for video in find_videos(scan.paths.iter().cloned(), listed, &exclude, false) {
+        match video {
+            Ok(video) => videos.push(video),
+            Err(e) => warn!("Skipping: {e:#}", e = e.skippable()?),
+        }
+    }
+
+    info!("Probing chapters of {n} videos…", n = videos.len());
+    let probed = videos
+        .par_iter()
+        .map(|video| (video, ffmpeg::probe_chapters(video)))
+        .collect::<Vec<_>>();
+    let mut chapters = Vec::new();
+    for (video, probed) in probed {
+        match probed {
+            Ok(probed) => {
+                chapters.extend(probed.into_iter().map(|chapter| (video.clone(), chapter)));
+            }
+            Err(e) => warn!("Couldn't read chapters of {video:?}: {e:#}"),
+        }
+    }
+    Ok(chapters)
+}
+
+/// How `args` want videos indexed into `db`.
+fn index_options(args: &ScanArgs, db: &SubDB) -> Result<sub::IndexOptions> {
+    Ok(sub::IndexOptions {
+        parsing: if args.lenient_subs {
+            sub::SubParsing::Lenient
+        } else {
+            sub::SubParsing::Strict
+        },
+        ocr: args.ocr,
+        keep_missing: args.keep_missing,
+        dedup: args.dedup,
+        temp_dir: args.temp_dir.clone(),
+        extract_cache: args.extract_cache.then(|| db.extract_cache_dir()),
+        thumbnails: args.thumbnails.clone(),
+        loose_subs: args.loose_subs,
+        video_dir: args.video_dir.clone(),
+        rescan: if args.rescan {
+            sub::Rescan::All
+        } else if args.rescan_path.is_empty() {
+            sub::Rescan::None
+        } else {
+            sub::Rescan::Matching(
+                args.rescan_path
+                    .iter()
+                    .map(|glob| util::Glob::new(glob))
+                    .collect::<Result<_>>()?,
+            )
+        },
+    })
+}
+
+fn scan(db: &mut SubDB, args: &ScanArgs) -> Result<ScanStats> {
+    let listed = match &args.paths_from {
+        Some(list) => read_path_list(list)?,
+        None => Vec::new(),
+    };
+    info!(
+        "Starting scan of {n} video folders and {m} listed files…",
+        n = args.paths.len(),
+        m = listed.len()
+    );
+    let exclude = args
+        .exclude
+        .iter()
+        .map(|glob| util::Glob::new(glob))
+        .collect::<Result<Vec<_>>>()?;
+    let index_opts = index_options(args, db)?;
+    db.check_writable()?;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.scan_jobs.unwrap_or(0))
+        .build()?;
+    ffmpeg::set_child_niceness(args.nice);
+    let _reset_niceness = scopeguard::guard((), |()| ffmpeg::set_child_niceness(0));
+    let stats = populate_db(
+        args.paths.iter().cloned(),
+        listed,
+        &exclude,
+        &index_opts,
+        db,
+        &pool,
+    )?;
+    for (video, err) in &stats.errors {
+        if let Some(video) = video {
+            warn!(file:% = video.display(); "Error trying to populate db with {video:?}: {err}");
+        } else {
+            warn!("Error trying to populate db: {err}");
+        }
+    }
+    if let Some(dir) = &index_opts.extract_cache {
+        let max_bytes = args.extract_cache_mib.saturating_mul(1024 * 1024);
+        match extract_cache::ExtractCache::new(dir).prune(max_bytes) {
+            Ok(0) => {}
+            Ok(n) => info!(
+                "Removed {n} videos' subs from the extract cache to stay below {mib} MiB",
+                mib = args.extract_cache_mib
+            ),
+            Err(e) => warn!("Couldn't prune the extract cache {dir:?}: {e:#}"),
+        }
+    }
+    info!(
+        "Scan finished. DB now consists of {n} entries",
+        n = db.len()
+    );
+    Ok(stats)
+}
+
+/// Extracts the subs of all videos to scan into `args.out_dir`, each into the
+/// folder mirroring where it lives below its scanned path.
+fn extract_subs(args: &ExtractArgs) -> Result<()> {
+    let listed = match &args.scan.paths_from {
+        Some(list) => read_path_list(list)?,
+        None => Vec::new(),
+    };
+    let exclude = args
+        .scan
+        .exclude
+        .iter()
+        .map(|glob| util::Glob::new(glob))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut extracted = 0;
+    for video in find_videos(args.scan.paths.iter().cloned(), listed, &exclude, false) {
+        if util::interrupted() {
+            bail!("Interrupted after extracting {extracted} subtitle files");
+        }
+        let video = match video {
+            Ok(video) => Ok(video),
+            Err(e) => Err(e.skippable()?),
+        };
+        let files = video.and_then(|video| {
+            let relative_dir = args
+                .scan
+                .paths
+                .iter()
+                .find_map(|root| video.strip_prefix(root).ok())
+                .and_then(Path::parent)
+                .unwrap_or(Path::new(""));
+            info!("Extracting subs of {video:?}");
+            ffmpeg::extract_sub_files(
+                &video,
+                &args.out_dir.join(relative_dir),
+                args.scan.ocr,
+                args.scan.temp_dir.as_deref(),
+            )
+        });
+        match files {
+            Ok(files) => extracted += files.len(),
+            Err(e) => warn!("Error extracting subs: {e:#}"),
+        }
+    }
+    info!(
+        "Extracted {extracted} subtitle files into {out_dir:?}",
+        out_dir = args.out_dir
+    );
+    Ok(())
+}
+
+/// Reads a selection saved by `--save-selection`, keeping only the lines which
+/// are still among `search_strings`.
+fn replay_selection(file: &Path, search_strings: &[&String]) -> Result<Vec<String>> {
+    let known = search_strings.iter().copied().collect::<HashSet<_>>();
+    let content =
+        std::fs::read_to_string(file).with_context(|| format!("reading selection {file:?}"))?;
+    let (found, missing): (Vec<_>, Vec<_>) = content
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(ToOwned::to_owned)
+        .partition(|line| known.contains(line));
+    for line in &missing {
+        warn!("Skipping \"{line}\" from {file:?}, it's not in the DB (anymore)");
+    }
+    info!(
+        "Replaying {n} selected lines from {file:?}",
+        n = found.len()
+    );
+    Ok(found)
+}
+
+/// Looks up the lines of `list` (see `wanted::read()`) in the DB, returning
+/// them as in `search_strings`. Lines not found, or found in several places,
+/// get skipped with a warning.
+fn from_list(
+    db: &SubDB,
+    list: &Path,
+    search_opts: sub::SearchOptions,
+    search_strings: &[&String],
+) -> Result<Vec<String>> {
+    let wanted = wanted::read(list)?;
+    let known = search_strings.iter().copied().collect::<HashSet<_>>();
+    // by raw and plain text, so lines match with and without markup
+    let mut by_text: HashMap<String, Vec<_>> = HashMap::new();
+    for (key, entry) in db.iter() {
+        for (sub_path, sub) in entry.subs(search_opts) {
+            let texts = [
+                wanted::match_key(&sub.text),
+                wanted::match_key(&sub.plain_text()),
+            ];
+            for text in texts.into_iter().unique() {
+                by_text
+                    .entry(text)
+                    .or_default()
+                    .push((key, entry, sub_path, sub.clone()));
+            }
+        }
+    }
+
+    let mut found = Vec::new();
+    for line in &wanted {
+        let matches = by_text
+            .get(&line.match_key())
+            .into_iter()
+            .flatten()
+            .filter(|(key, ..)| line.wants_video(&key.video_path))
+            .map(|(key, entry, sub_path, sub)| {
+                let display = entry.display_string(sub_path, sub, search_opts);
+                ((&key.video_path, sub.start()), display)
+            })
+            // e.g. skipped as intro
+            .filter(|(_, display)| known.contains(display))
+            .collect_vec();
+        // the same line in several streams still is a single clip
+        let clips = matches.iter().map(|(clip, _)| clip).unique().count();
+        match clips {
+            0 => warn!("Skipping \"{text}\" from {list:?}, it's not in the DB", text = line.text),
+            1 => found.push(matches[0].1.clone()),
+            n => warn!(
+                "Skipping \"{text}\" from {list:?}, it's ambiguous with {n} matches (give a video_path): {matches}",
+                text = line.text,
+                matches = matches.iter().map(|(_, display)| display).join(" | ")
+            ),
+        }
+    }
+    info!(
+        "Found {n}/{total} lines from {list:?}",
+        n = found.len(),
+        total = wanted.len()
+    );
+    Ok(found)
+}
+
+/// Reads newline-delimited paths from `list`, or from stdin if it's `-`.
+fn read_path_list(list: &Path) -> Result<Vec<PathBuf>> {
+    let lines = if list == Path::new("-") {
+        std::io::stdin().lines().collect::<Result<Vec<_>, _>>()?
+    } else {
+        std::fs::read_to_string(list)
+            .with_context(|| format!("reading path list {list:?}"))?
+            .lines()
+            .map(ToOwned::to_owned)
+            .collect()
+    };
+    Ok(lines
+        .into_iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Problems finding or indexing videos, by whether the scan can go on.
+#[derive(Debug)]
+enum ScanError {
+    /// only affects a single video (or dir), e.g. a corrupt file
+    SkippableFile(anyhow::Error),
+    /// affects everything to come, e.g. a scanned folder can't be read
+    FatalIo(anyhow::Error),
+}
+
+impl ScanError {
+    /// Returns skippable errors, so fatal ones can be propagated with `?`.
+    fn skippable(self) -> Result<anyhow::Error> {
+        match self {
+            ScanError::SkippableFile(e) => Ok(e),
+            ScanError::FatalIo(e) => Err(e),
+        }
+    }
+}
+
+impl From<walkdir::Error> for ScanError {
+    fn from(e: walkdir::Error) -> Self {
+        // depth 0 is the scanned folder itself
+        if e.depth() == 0 {
+            ScanError::FatalIo(anyhow!("can't read scanned folder: {e}"))
+        } else {
+            ScanError::SkippableFile(anyhow!("{e}"))
+        }
+    }
+}
+
+/// Lets the user pick some of `strings` in `args.finder`, see `fzf::select()`.
+fn select(
+    args: &ClipArgs,
+    strings: &[impl AsRef<str>],
+    search_keys: fzf::SearchKeys,
+    play_targets: Option<&[fzf::PlayTarget]>,
+) -> Result<Vec<String>> {
+    match args.finder {
+        cli::Finder::Fzf => fzf::select(strings, search_keys, &args.fzf_args, play_targets),
+        cli::Finder::Tui => {
+            let action = match args.clip_format {
+                cli::ClipFormat::Video => "clip",
+                cli::ClipFormat::SrtWindow => "export",
+            };
+            tui::select(strings, search_keys, play_targets, action)
+        }
+    }
+}
+
+/// Logs what got scanned and the `timings`, or prints them with throughput for
+/// `--time-phases` (`time_phases`). `videos` went through the scan, if any,
+/// and `lines` got formatted.
+fn report_phases(
+    timings: &util::Timings,
+    scan_stats: Option<&ScanStats>,
+    videos: Option<usize>,
+    lines: usize,
+    time_phases: bool,
+) {
+    if let Some(scan_stats) = scan_stats {
+        info!("{scan_stats}, {lines} subtitle lines");
+    } else {
+        info!("No scan, {lines} subtitle lines from the DB");
+    }
+    if !time_phases {
+        info!("Time per phase: {timings}");
+        return;
+    }
+    let throughput = |phase: &str| {
+        let (n, what) = match phase {
+            "scan" => (videos?, "videos"),
+            "format" => (lines, "lines"),
+            _ => return None,
+        };
+        let rate = util::per_second(n, timings.of(phase))?;
+        Some(format!("{rate:.1} {what}/s"))
+    };
+    for (phase, duration) in timings.iter() {
+        let throughput = throughput(phase).unwrap_or_default();
+        println!("{phase:<8}{duration:>12.3?}  {throughput}");
+    }
+    println!("{:<8}{:>12.3?}", "total", timings.total());
+}
+
+/// What `populate_db()` did.
+#[derive(Debug, Default)]
+struct ScanStats {
+    videos: usize,
+    /// up-to-date in the DB already
+    cached: usize,
+    /// new or changed
+    indexed: usize,
+    /// gone from disk
+    removed: usize,
+    /// gone from disk or unreachable, but kept as stale
+    missing: usize,
+    /// of those indexed, copies of other videos (see `--dedup`)
+    duplicates: usize,
+    /// with the video, if known
+    errors: Vec<(Option<PathBuf>, anyhow::Error)>,
+}
+
+impl std::fmt::Display for ScanStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Scanned {videos} videos ({indexed} newly indexed, {cached} cached, {removed} removed, {missing} missing, {duplicates} duplicates, {errors} errors)",
+            videos = self.videos,
+            indexed = self.indexed,
+            cached = self.cached,
+            removed = self.removed,
+            missing = self.missing,
+            duplicates = self.duplicates,
+            errors = self.errors.len(),
+        )
+    }
+}
+
+/// Walks `paths` for videos (skipping `exclude`d ones) and appends the
+/// `listed` files, taken as they are.
+fn find_videos<'a>(
+    paths: impl Iterator<Item = PathBuf> + 'a,
+    listed: Vec<PathBuf>,
+    exclude: &'a [util::Glob],
+    loose_subs: bool,
+) -> impl Iterator<Item = Result<PathBuf, ScanError>> + 'a {
     fn only_files(path: PathBuf) -> Option<Result<PathBuf>> {
         (move || {
             if path.metadata()?.is_file() {
@@ -139,14 +1719,23 @@ fn populate_db(paths: impl Iterator<Item = PathBuf>, db: &mut sub::db::SubDB) ->
         })()
         .transpose()
     }
-    fn walk(path: impl AsRef<Path>) -> impl Iterator<Item = Result<PathBuf>> {
-        WalkDir::new(path.as_ref())
+    fn walk<'a>(
+        root: &'a Path,
+        exclude: &'a [util::Glob],
+    ) -> impl Iterator<Item = Result<PathBuf, ScanError>> + 'a {
+        // pruning in `filter_entry()` keeps excluded dirs from being descended into
+        let is_excluded = move |entry: &DirEntry| {
+            let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+            exclude.iter().any(|glob| glob.is_match(relative))
+        };
+        WalkDir::new(root)
             .min_depth(1)
             .into_iter()
+            .filter_entry(move |entry| !is_excluded(entry))
             .map_ok(DirEntry::into_path)
             .filter_map_ok(only_files)
             .flatten_ok()
-            .map(to_anyhow)
+            .map(|entry| entry.map_err(ScanError::from))
     }
 
     #[allow(clippy::ptr_arg)] // otherwise fn sig doesn't match when passing to filter()
@@ -160,28 +1749,134 @@ fn populate_db(paths: impl Iterator<Item = PathBuf>, db: &mut sub::db::SubDB) ->
     let possible_files = paths
         .map(|entry| {
             if entry.is_symlink() {
-                bail!("No symlinks! ({entry:?})");
+                return Err(ScanError::SkippableFile(anyhow!(
+                    "No symlinks! ({entry:?})"
+                )));
             }
             if entry.is_file() {
-                return Ok(vec![Ok(entry)]);
+                // matched by name, as when walking its dir
+                let excluded = entry
+                    .file_name()
+                    .is_some_and(|name| exclude.iter().any(|glob| glob.is_match(Path::new(name))));
+                return Ok(if excluded {
+                    Vec::new()
+                } else {
+                    vec![Ok(entry)]
+                });
             }
             if entry.is_dir() {
-                return Ok(walk(entry).collect_vec());
+                return Ok(walk(&entry, exclude).collect_vec());
+            }
+            // most likely a typo or an unmounted drive, not worth scanning on
+            if !entry.exists() {
+                return Err(ScanError::FatalIo(anyhow!(
+                    "scan path {entry:?} doesn't exist"
+                )));
             }
-            bail!("{entry:?} is neither symlink, file nor dir.")
+            Err(ScanError::SkippableFile(anyhow!(
+                "{entry:?} is neither symlink, file nor dir."
+            )))
         })
         .flatten_ok() // Iter<Result<Vec<Result<Path>>>> => Iter<Result<    Result<Path>>>
         .flatten_ok(); // Iter<Result<    Result<Path>>>  => Iter<Result<           Path>>
 
-    let movie_files = possible_files.filter_ok(has_movie_ext);
+    // subtitle files next to their video get indexed along with it
+    let is_loose_sub = move |path: &PathBuf| {
+        loose_subs && sub::has_sub_ext(path) && sub::loose_sub_video(path, path.parent()).is_none()
+    };
+    let listed = listed.into_iter().filter(move |path| {
+        let is_movie = has_movie_ext(path) || is_loose_sub(path);
+        if !is_movie {
+            warn!("Skipping listed {path:?}, it doesn't have a video extension");
+        }
+        is_movie
+    });
 
-    movie_files
-        .map_ok(|path| {
-            db.lookup_or_update(&db::Key { video_path: path })
-                .map(|_| ())
-        })
-        .flatten_ok()
-        .collect_vec()
+    possible_files
+        .filter_ok(move |path| has_movie_ext(path) || is_loose_sub(path))
+        .chain(listed.map(Ok))
+}
+
+/// Walks `paths` for videos, takes `listed` files as they are, and adds them
+/// all to the DB.
+fn populate_db(
+    paths: impl Iterator<Item = PathBuf>,
+    listed: Vec<PathBuf>,
+    exclude: &[util::Glob],
+    index_opts: &sub::IndexOptions,
+    db: &mut sub::db::SubDB,
+    pool: &rayon::ThreadPool,
+) -> Result<ScanStats> {
+    let movie_files = find_videos(paths, listed, exclude, index_opts.loose_subs);
+    let mut stats = ScanStats::default();
+    let mut checkpointed = 0;
+    // indexing is the slow part, so it runs in parallel a chunk at a time,
+    // while updating the DB stays sequential
+    for chunk in &movie_files.chunks(SCAN_CHECKPOINT_EVERY) {
+        let chunk = chunk.collect_vec();
+        let db_ = &*db;
+        let prepared = pool.install(|| {
+            chunk
+                .into_par_iter()
+                // whatever didn't start yet gets picked up by the next scan
+                .filter(|_| !util::interrupted())
+                .map(|path| {
+                    let key = db::Key { video_path: path? };
+                    let prepared = db_.prepare_update(&key, index_opts);
+                    Ok((key, prepared))
+                })
+                .collect::<Vec<Result<_, ScanError>>>()
+        });
+        for result in prepared {
+            let mut video = None;
+            let found = result.and_then(|(key, prepared)| {
+                stats.videos += 1;
+                let prepared = prepared.map_err(|e| {
+                    video = Some(key.video_path.clone());
+                    ScanError::SkippableFile(e)
+                })?;
+                Ok(db.apply_update(&key, prepared, index_opts))
+            });
+            match found {
+                Ok((_, db::EntryFoundDiscriminants::Yes)) => stats.cached += 1,
+                Ok((_, db::EntryFoundDiscriminants::YesButGone)) if !index_opts.keep_missing => {
+                    stats.removed += 1;
+                }
+                Ok((
+                    _,
+                    db::EntryFoundDiscriminants::YesButGone
+                    | db::EntryFoundDiscriminants::YesButUnreachable,
+                )) => stats.missing += 1,
+                Ok((
+                    val,
+                    db::EntryFoundDiscriminants::YesButChanged | db::EntryFoundDiscriminants::No,
+                )) => {
+                    stats.indexed += 1;
+                    if val.is_some_and(|entry| entry.meta().alias_of().is_some()) {
+                        stats.duplicates += 1;
+                    }
+                }
+                Err(e) => stats.errors.push((video, e.skippable()?)),
+            }
+        }
+        if stats.indexed - checkpointed >= SCAN_CHECKPOINT_EVERY {
+            info!(
+                "Saving checkpoint after {n} indexed videos…",
+                n = stats.indexed
+            );
+            db.save()
+                .context("Saving checkpoint failed, aborting the scan")?;
+            checkpointed = stats.indexed;
+        }
+        if util::interrupted() {
+            db.save().context("Saving the DB after Ctrl-C")?;
+            bail!(
+                "Interrupted, the DB keeps the {n} videos scanned so far",
+                n = stats.videos
+            );
+        }
+    }
+    Ok(stats)
 }
 
 /// Converts arbitrary errors to anyhow.
@@ -195,3 +1890,119 @@ where
         Err(e) => Err(anyhow!("{e}")),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        borrow::Cow,
+        collections::HashMap,
+        path::{Path, PathBuf},
+        sync::Mutex,
+    };
+
+    use anyhow::anyhow;
+
+    use super::{clip_all, db, ffmpeg, sub, ClipOutcome, Job, Selection};
+
+    #[test]
+    fn clip_all_retries_once() {
+        let key = db::Key {
+            video_path: PathBuf::from("/shows/movie.mkv"),
+        };
+        let entry = db::Entry::empty(key.video_path.clone());
+        let sub_path = db::SubPath::External {
+            path: PathBuf::from("/shows/movie.srt"),
+            language: None,
+        };
+        let sub = sub::Subtitle::from(srtlib::Subtitle::new(
+            1,
+            srtlib::Timestamp::new(0, 0, 1, 0),
+            srtlib::Timestamp::new(0, 0, 2, 0),
+            "Hi".to_owned(),
+        ));
+        let selection = Selection {
+            key: &key,
+            line: "Hi",
+            entry: &entry,
+            sub_path: &sub_path,
+            sub: Cow::Borrowed(&sub),
+            context: None,
+            outfiles: Vec::new(),
+        };
+        let opts = ffmpeg::ClipOptions {
+            profile: ffmpeg::EncodingProfile::AV1,
+            container: None,
+            pad_start: 0,
+            pad_end: 0,
+            overwrite: ffmpeg::Overwrite::default(),
+            audio: None,
+            stage_dir: None,
+            loudnorm: false,
+            fade: 0,
+            crf: None,
+            preset: None,
+            sub_codec: None,
+        };
+        let job = |label: &str| Job {
+            selection: &selection,
+            opts: &opts,
+            outfile: PathBuf::from("/clips").join(label),
+            label: label.to_owned(),
+            sidecars: false,
+        };
+        let jobs = [job("fine"), job("flaky"), job("broken")];
+
+        let attempts = Mutex::new(HashMap::<String, usize>::new());
+        let records = Mutex::new(Vec::new());
+        let outcomes = clip_all(
+            &jobs.iter().collect::<Vec<_>>(),
+            |job| {
+                let mut attempts = attempts.lock().unwrap();
+                let attempt = attempts.entry(job.label.clone()).or_default();
+                *attempt += 1;
+                match (job.label.as_str(), *attempt) {
+                    ("fine", _) | ("flaky", 2) => Ok(()),
+                    _ => Err(anyhow!("attempt {attempt} failed")),
+                }
+            },
+            |job, error| {
+                records
+                    .lock()
+                    .unwrap()
+                    .push((job.label.clone(), error.map(ToString::to_string)));
+            },
+        );
+
+        let outcome = |line: &str, error: Option<&str>| ClipOutcome {
+            line: line.to_owned(),
+            output: error
+                .is_none()
+                .then(|| opts.outfile(&Path::new("/clips").join(line))),
+            error: error.map(str::to_owned),
+        };
+        assert_eq!(
+            outcomes,
+            [
+                outcome("fine", None),
+                outcome("flaky", None),
+                outcome("broken", Some("attempt 2 failed")),
+            ]
+        );
+        let attempts = attempts.into_inner().unwrap();
+        assert_eq!(
+            [attempts["fine"], attempts["flaky"], attempts["broken"]],
+            [1, 2, 2]
+        );
+        // once per job, after its last attempt
+        let mut records = records.into_inner().unwrap();
+        records.sort();
+        assert_eq!(
+            records,
+            [
+                ("broken".to_owned(), Some("attempt 2 failed".to_owned())),
+                ("fine".to_owned(), None),
+                ("flaky".to_owned(), None),
+            ]
+        );
+    }
+}
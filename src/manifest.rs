@@ -0,0 +1,210 @@
+use std::{
+    collections::HashSet,
+    fs::{File, OpenOptions},
+    io::Write as _,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{Context as _, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+pub const MANIFEST_FILENAME: &str = "magiclip-manifest.jsonl";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Done,
+    Failed,
+}
+
+/// A clip as recorded: where it went and what it was made of, so clipping the
+/// same line differently (e.g. with other padding) doesn't count as done.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Clip {
+    /// including extension
+    pub outfile: PathBuf,
+    /// of the clip in the video, padding included
+    #[serde(default)]
+    pub start_ms: u64,
+    #[serde(default)]
+    pub duration_ms: u64,
+    /// `ClipOptions::output_hash()` of what it was clipped with
+    #[serde(default)]
+    pub options: u64,
+}
+
+/// One line of the manifest, written whenever a clip is finished for good.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    pub video_path: PathBuf,
+    /// `num` of the subtitle in its file
+    pub sub_num: usize,
+    #[serde(flatten)]
+    pub clip: Clip,
+    pub status: Status,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Log of finished clips in the clip dir (one JSON record per line), so an
+/// interrupted batch can be resumed without re-clipping.
+///
+/// Later records for the same clip win, so a clip failing after it once
+/// succeeded counts as failed.
+#[derive(Debug)]
+pub struct Manifest {
+    file: Mutex<File>,
+    done: HashSet<Clip>,
+}
+
+impl Manifest {
+    /// Opens (or creates) the manifest in `clip_dir` and reads what's done.
+    pub fn open(clip_dir: &Path) -> Result<Self> {
+        let path = clip_dir.join(MANIFEST_FILENAME);
+        std::fs::create_dir_all(clip_dir)
+            .with_context(|| format!("creating clip dir {clip_dir:?}"))?;
+
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("reading manifest {path:?}")),
+        };
+        let mut done = HashSet::new();
+        for (i, line) in content.lines().enumerate().filter(|(_, l)| !l.is_empty()) {
+            // a crash while writing leaves a partial last line, which is no biggie
+            match serde_json::from_str::<Record>(line) {
+                Ok(record) if record.status == Status::Done => {
+                    done.insert(record.clip);
+                }
+                Ok(record) => {
+                    done.remove(&record.clip);
+                }
+                Err(e) => warn!("Ignoring line {n} of manifest {path:?}: {e}", n = i + 1),
+            }
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("opening manifest {path:?}"))?;
+        // or the next record would be glued onto the partial line, and lost too
+        if !content.is_empty() && !content.ends_with('\n') {
+            file.write_all(b"\n")
+                .with_context(|| format!("writing manifest {path:?}"))?;
+        }
+        Ok(Self {
+            file: Mutex::new(file),
+            done,
+        })
+    }
+
+    /// Whether `clip` was clipped successfully (the same way) and still exists.
+    pub fn is_done(&self, clip: &Clip) -> bool {
+        self.done.contains(clip) && clip.outfile.is_file()
+    }
+
+    /// Appends `record` in a single write, so concurrent clips don't interleave.
+    pub fn record(&self, record: &Record) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| anyhow::anyhow!("manifest lock poisoned"))?;
+        file.write_all(&line)?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+    use tempfile::TempDir;
+
+    use super::{Clip, Manifest, Record, Status};
+
+    #[test]
+    fn resume() -> Result<()> {
+        let clip_dir = TempDir::new()?;
+        let path = |name: &str| clip_dir.path().join(name);
+        let clip = |name: &str| Clip {
+            outfile: path(name),
+            start_ms: 1000,
+            duration_ms: 2000,
+            options: 42,
+        };
+        let record = |name: &str, status| Record {
+            video_path: "video.mkv".into(),
+            sub_num: 1,
+            clip: clip(name),
+            status,
+            error: (status == Status::Failed).then(|| "boom".to_owned()),
+        };
+        for name in ["done.mkv", "redone.mkv", "gone.mkv"] {
+            std::fs::write(path(name), "")?;
+        }
+
+        {
+            let manifest = Manifest::open(clip_dir.path())?;
+            assert!(!manifest.is_done(&clip("done.mkv")));
+            for (name, status) in [
+                ("done.mkv", Status::Done),
+                ("failed.mkv", Status::Failed),
+                ("redone.mkv", Status::Failed),
+                ("redone.mkv", Status::Done),
+                ("gone.mkv", Status::Done),
+            ] {
+                manifest.record(&record(name, status))?;
+            }
+        }
+        std::fs::remove_file(path("gone.mkv"))?;
+        // as if crashed mid-write
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(clip_dir.path().join(super::MANIFEST_FILENAME))?;
+        std::io::Write::write_all(&mut file, b"{\"video_pa")?;
+
+        let manifest = Manifest::open(clip_dir.path())?;
+        assert!(manifest.is_done(&clip("done.mkv")));
+        assert!(manifest.is_done(&clip("redone.mkv")));
+        assert!(!manifest.is_done(&clip("failed.mkv")));
+        assert!(!manifest.is_done(&clip("gone.mkv")));
+        let repadded = Clip {
+            duration_ms: 2500,
+            ..clip("done.mkv")
+        };
+        assert!(!manifest.is_done(&repadded));
+        Ok(())
+    }
+
+    #[test]
+    fn record_after_truncated_line() -> Result<()> {
+        let clip_dir = TempDir::new()?;
+        let outfile = clip_dir.path().join("clip.mkv");
+        std::fs::write(&outfile, "")?;
+        std::fs::write(
+            clip_dir.path().join(super::MANIFEST_FILENAME),
+            "{\"video_path\": \"vid",
+        )?;
+        let clip = Clip {
+            outfile,
+            start_ms: 0,
+            duration_ms: 1000,
+            options: 0,
+        };
+
+        Manifest::open(clip_dir.path())?.record(&Record {
+            video_path: "video.mkv".into(),
+            sub_num: 1,
+            clip: clip.clone(),
+            status: Status::Done,
+            error: None,
+        })?;
+        assert!(Manifest::open(clip_dir.path())?.is_done(&clip));
+        Ok(())
+    }
+}
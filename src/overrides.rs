@@ -0,0 +1,171 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context as _, Result};
+
+use crate::config;
+
+/// Fixes for how one video gets indexed, for the odd one with mistagged or
+/// broken subtitle streams. Read from a sidecar next to it (`movie.magiclip.toml`
+/// for `movie.mkv`), in the flat TOML of the config (see `config::Config`):
+///
+/// ```toml
+/// # only index this sub stream (counting sub streams only, from 0)
+/// stream = 2
+/// # what language streams really are
+/// languages = ["2:jpn", "3:eng"]
+/// # index these files (relative to the video) instead of the embedded streams
+/// subs = "movie.fixed.srt"
+/// ```
+///
+/// Adding, editing or deleting the sidecar makes the next scan index the video
+/// again.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Overrides {
+    stream: Option<usize>,
+    /// stream id and the language it really is
+    languages: Vec<(usize, String)>,
+    /// used instead of the embedded streams, next to any sibling sub files
+    pub subs: Vec<PathBuf>,
+}
+
+impl Overrides {
+    /// Where the overrides of `video` are read from.
+    pub fn sidecar(video: &Path) -> PathBuf {
+        video.with_extension("magiclip.toml")
+    }
+
+    /// The overrides of `video`, or none if it has no sidecar.
+    pub fn load(video: &Path) -> Result<Self> {
+        let sidecar = Self::sidecar(video);
+        let content = match std::fs::read_to_string(&sidecar) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).with_context(|| format!("reading overrides {sidecar:?}")),
+        };
+        let dir = video.parent().unwrap_or(Path::new(""));
+        Self::parse(&content, dir).with_context(|| format!("parsing overrides {sidecar:?}"))
+    }
+
+    fn parse(content: &str, video_dir: &Path) -> Result<Self> {
+        let mut overrides = Self::default();
+        for (key, values) in config::parse_flat(content)? {
+            match key.as_str() {
+                "stream" => {
+                    let [stream] = values.as_slice() else {
+                        bail!("`stream` takes a single stream");
+                    };
+                    overrides.stream = Some(stream.parse().context("`stream`")?);
+                }
+                "languages" => {
+                    overrides.languages = values
+                        .iter()
+                        .map(|value| {
+                            let (stream, language) = value.split_once(':').with_context(|| {
+                                format!("expected `stream:language`, got {value:?}")
+                            })?;
+                            let stream = stream
+                                .parse()
+                                .with_context(|| format!("stream of {value:?}"))?;
+                            Ok((stream, language.to_owned()))
+                        })
+                        .collect::<Result<_>>()?;
+                }
+                "subs" => {
+                    overrides.subs = values.iter().map(|sub| video_dir.join(sub)).collect();
+                }
+                _ => bail!("unknown key {key:?}, known are stream, languages and subs"),
+            }
+        }
+        Ok(overrides)
+    }
+
+    /// Errors if `stream` is out of range for `video`, which has `count` sub
+    /// streams, as it would silently index none of them.
+    pub fn check_stream_count(&self, video: &Path, count: usize) -> Result<()> {
+        if let Some(stream) = self.stream.filter(|stream| *stream >= count) {
+            bail!(
+                "`stream = {stream}` in {sidecar:?}, but {video:?} has only {count} sub streams \
+                 (counting from 0)",
+                sidecar = Self::sidecar(video)
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether the embedded sub stream `stream_id` gets indexed.
+    pub fn keeps_stream(&self, stream_id: usize) -> bool {
+        self.subs.is_empty() && self.stream.is_none_or(|stream| stream == stream_id)
+    }
+
+    /// The language of sub stream `stream_id`, `tagged` as in the video.
+    pub fn language(&self, stream_id: usize, tagged: Option<String>) -> Option<String> {
+        self.languages
+            .iter()
+            .find(|(stream, _)| *stream == stream_id)
+            .map(|(_, language)| language.clone())
+            .or(tagged)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use anyhow::Result;
+
+    use super::Overrides;
+
+    #[test]
+    fn parse() -> Result<()> {
+        let dir = Path::new("/shows");
+        let overrides = Overrides::parse(
+            r#"
+            stream = 2
+            languages = ["1:jpn", "2:eng"]
+            "#,
+            dir,
+        )?;
+        assert!(overrides.keeps_stream(2));
+        assert!(!overrides.keeps_stream(1));
+        assert_eq!(overrides.language(1, None).as_deref(), Some("jpn"));
+        assert_eq!(
+            overrides.language(2, Some("und".to_owned())).as_deref(),
+            Some("eng")
+        );
+        assert_eq!(
+            overrides.language(3, Some("fre".to_owned())).as_deref(),
+            Some("fre")
+        );
+
+        let overrides = Overrides::parse(r#"subs = "movie.fixed.srt""#, dir)?;
+        assert_eq!(overrides.subs, [dir.join("movie.fixed.srt")]);
+        assert!(!overrides.keeps_stream(0));
+
+        assert!(Overrides::default().keeps_stream(7));
+        assert_eq!(
+            Overrides::sidecar(Path::new("/shows/movie.mkv")),
+            Path::new("/shows/movie.magiclip.toml")
+        );
+        for invalid in [
+            "stream = [1, 2]",
+            "stream = \"x\"",
+            "languages = [\"jpn\"]",
+            "lang = 1",
+        ] {
+            assert!(Overrides::parse(invalid, dir).is_err(), "{invalid}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn check_stream_count() -> Result<()> {
+        let video = Path::new("/shows/movie.mkv");
+        let overrides = Overrides::parse("stream = 2", Path::new("/shows"))?;
+        assert!(overrides.check_stream_count(video, 3).is_ok());
+        let err = overrides.check_stream_count(video, 2).unwrap_err();
+        assert!(err.to_string().contains("movie.magiclip.toml"), "{err}");
+        assert!(overrides.check_stream_count(video, 0).is_err());
+        assert!(Overrides::default().check_stream_count(video, 0).is_ok());
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use std::{cmp::Reverse, collections::HashSet};
+
+use itertools::Itertools as _;
+use rayon::prelude::*;
+
+use crate::util;
+
+/// How well a subtitle's text matches a query, see `rank_match()`. Better
+/// matches compare greater.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Match {
+    /// has the query's chars in order, with others between them (like fzf)
+    Fuzzy,
+    /// has all the query's words, some only as parts of words
+    WordParts,
+    /// has the query as is, but starting or ending within a word
+    Substring,
+    /// has all the query's words as whole words, in any order
+    Words,
+    /// has the query's words as a phrase of whole words
+    Phrase,
+    /// is the query, punctuation aside
+    Exact,
+}
+
+/// How `text` matches `query`, case- and accent-insensitively, `None` if it
+/// doesn't at all.
+pub fn rank_match(query: &str, text: &str) -> Option<Match> {
+    let (query, text) = (util::fold_for_search(query), util::fold_for_search(text));
+    let query_words = words(&query);
+    let text_words = words(&text);
+    if query_words.is_empty() {
+        return Some(Match::Fuzzy);
+    }
+    if query_words == text_words {
+        Some(Match::Exact)
+    } else if text_words
+        .windows(query_words.len())
+        .any(|window| window == query_words)
+    {
+        Some(Match::Phrase)
+    } else if query_words.iter().all(|word| text_words.contains(word)) {
+        Some(Match::Words)
+    } else if text.contains(query.trim()) {
+        Some(Match::Substring)
+    } else if query_words.iter().all(|word| text.contains(word)) {
+        Some(Match::WordParts)
+    } else {
+        let mut text = text.chars();
+        query
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .all(|c| text.any(|t| t == c))
+            .then_some(Match::Fuzzy)
+    }
+}
+
+fn words(text: &str) -> Vec<&str> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect_vec()
+}
+
+/// The indices of the `texts` matching `query`, best matches first and
+/// otherwise in their order, at most `limit` of them.
+pub fn rank<S: AsRef<str> + Sync>(query: &str, texts: &[S], limit: Option<usize>) -> Vec<usize> {
+    let mut ranked = texts
+        .par_iter()
+        .enumerate()
+        .filter_map(|(i, text)| Some((rank_match(query, text.as_ref())?, i)))
+        .collect::<Vec<_>>();
+    // stable, so equal matches keep their order
+    ranked.par_sort_by_key(|(rank, _)| Reverse(*rank));
+    ranked
+        .into_iter()
+        .map(|(_, i)| i)
+        .take(limit.unwrap_or(usize::MAX))
+        .collect()
+}
+
+/// Like `rank()`, but keeping the `texts` not matching `query` at all, after
+/// the others.
+pub fn rank_all<S: AsRef<str> + Sync>(
+    query: &str,
+    texts: &[S],
+    limit: Option<usize>,
+) -> Vec<usize> {
+    let mut ranked = rank(query, texts, limit);
+    let limit = limit.unwrap_or(usize::MAX);
+    if ranked.len() < limit {
+        let matched = ranked.iter().copied().collect::<HashSet<_>>();
+        let rest = (0..texts.len()).filter(|i| !matched.contains(i));
+        ranked.extend(rest.take(limit - ranked.len()));
+    }
+    ranked
+}
+
+/// The `items` at `indices` (each at most once), in that order.
+pub fn pick<T>(items: Vec<T>, indices: &[usize]) -> Vec<T> {
+    let mut items = items.into_iter().map(Some).collect_vec();
+    indices
+        .iter()
+        .map(|&i| items[i].take().expect("[ASSERT] indices are unique"))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{pick, rank, rank_all, rank_match, Match};
+
+    #[test]
+    fn rank_match_tiers() {
+        let query = "the cake";
+        assert_eq!(rank_match(query, "The cake!"), Some(Match::Exact));
+        assert_eq!(rank_match(query, "Is the cake a lie?"), Some(Match::Phrase));
+        assert_eq!(rank_match(query, "Cake for the win"), Some(Match::Words));
+        assert_eq!(rank_match(query, "Bathe cakes"), Some(Match::Substring));
+        assert_eq!(rank_match(query, "Cakewalk, then"), Some(Match::WordParts));
+        assert_eq!(rank_match(query, "The scheme: a kite"), Some(Match::Fuzzy));
+        assert_eq!(rank_match(query, "Pie"), None);
+        assert_eq!(rank_match("café", "CAFE"), Some(Match::Exact));
+    }
+
+    #[test]
+    fn rank_is_stable_and_limited() {
+        let texts = [
+            "no match here",
+            "a cake walk",
+            "Cake",
+            "pancakes",
+            "Cake?",
+            "c, a, k, e",
+        ];
+        assert_eq!(rank("cake", &texts, None), [2, 4, 1, 3, 5]);
+        assert_eq!(rank("cake", &texts, Some(3)), [2, 4, 1]);
+        assert_eq!(rank_all("cake", &texts, None), [2, 4, 1, 3, 5, 0]);
+        assert_eq!(rank_all("cake", &texts, Some(5)), [2, 4, 1, 3, 5]);
+        assert_eq!(pick(texts.to_vec(), &[4, 0]), ["Cake?", "no match here"]);
+    }
+}
@@ -1,9 +1,16 @@
 use ::serde::{Deserialize, Serialize};
 use anyhow::{Context, Result};
 use itertools::Itertools as _;
-use std::path::Path;
+use log::warn;
+use regex::Regex;
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+    time::Duration,
+};
 
-use crate::{util, CLIP_FILENAME_PATH_LEN, CLIP_FILENAME_TEXT_LEN};
+use crate::{ffmpeg, util};
 
 // TODO check if module scopes are sufficiently granular, if I could encapsulate
 // more and if functions interdepend too much / use private apis/structs which
@@ -11,17 +18,18 @@ use crate::{util, CLIP_FILENAME_PATH_LEN, CLIP_FILENAME_TEXT_LEN};
 
 pub mod db {
 
-    use anyhow::{anyhow, ensure, Context, Result};
+    use anyhow::{anyhow, bail, ensure, Context, Result};
     use derive_getters::Getters;
     use itertools::Itertools;
-    use log::{error, warn};
+    use log::{debug, error, trace, warn};
     use rayon::iter::{IntoParallelRefIterator as _, ParallelIterator};
     use serde_with::serde_as;
     use std::{
+        borrow::Cow,
         collections::HashMap,
         fs::File,
         io::{BufReader, BufWriter},
-        os::unix::fs::MetadataExt as _,
+        ops::RangeInclusive,
         path::{Path, PathBuf},
         sync::Arc,
     };
@@ -30,9 +38,9 @@ pub mod db {
     use chrono::{DateTime, Utc};
     use serde::{Deserialize, Serialize};
 
-    use crate::{ffmpeg, to_anyhow};
+    use crate::{extract_cache::ExtractCache, ffmpeg, overrides::Overrides, to_anyhow, util};
 
-    use super::Subtitles;
+    use super::{IndexOptions, SearchLine, SearchOptions, Subtitle, Subtitles};
 
     #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
     pub struct Key {
@@ -41,8 +49,57 @@ pub mod db {
 
     #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
     pub enum SubPath {
-        InternalFFmpeg { stream_id: usize },
-        External { path: PathBuf },
+        InternalFFmpeg {
+            stream_id: usize,
+            #[serde(default)]
+            language: Option<String>,
+            #[serde(default)]
+            forced: bool,
+            /// flagged as the stream to show by default
+            #[serde(default)]
+            default: bool,
+        },
+        External {
+            path: PathBuf,
+            #[serde(default)]
+            language: Option<String>,
+        },
+    }
+
+    impl SubPath {
+        pub fn language(&self) -> Option<&str> {
+            match self {
+                SubPath::InternalFFmpeg { language, .. } | SubPath::External { language, .. } => {
+                    language.as_deref()
+                }
+            }
+        }
+
+        fn is_default(&self) -> bool {
+            match self {
+                SubPath::InternalFFmpeg { default, .. } => *default,
+                SubPath::External { .. } => false,
+            }
+        }
+
+        /// Short description of the stream for the search display, like
+        /// `[#1] [eng] [forced]` or `[srt] [en]`.
+        pub fn tag(&self) -> String {
+            let (source, language, forced) = match self {
+                SubPath::InternalFFmpeg {
+                    stream_id,
+                    language,
+                    forced,
+                    ..
+                } => (format!("#{stream_id}"), language, *forced),
+                SubPath::External { language, .. } => ("srt".to_owned(), language, false),
+            };
+            std::iter::once(source)
+                .chain(language.clone())
+                .chain(forced.then(|| "forced".to_owned()))
+                .map(|part| format!("[{part}]"))
+                .join(" ")
+        }
     }
 
     #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Getters)]
@@ -50,6 +107,135 @@ pub mod db {
         video_path: PathBuf,
         /// time the entry got indexed, not the vid was modified
         time: DateTime<Utc>,
+        /// video was missing during a scan with `--keep-missing`
+        #[serde(default)]
+        stale: bool,
+        /// only computed with `--dedup`
+        #[serde(default)]
+        fingerprint: Option<Fingerprint>,
+        /// same content as this (indexed) video, so this one has no subs of its
+        /// own, see `--dedup`
+        #[serde(default)]
+        alias_of: Option<PathBuf>,
+        /// why sub streams/files couldn't be read when indexing, kept so they
+        /// don't go unnoticed (and don't get logged on every re-index)
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        errors: Vec<String>,
+        /// `video_path` is a loose subtitle file rather than a video, see
+        /// `--loose-subs`
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        loose: bool,
+        /// the video a `loose` subtitle file belongs to, if one was found
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        video: Option<PathBuf>,
+        /// length of the video in ms, probed when indexing so it needn't be
+        /// again. `None` for entries of older schema versions until a scan
+        /// fills it in, and for loose subtitle files.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        duration_ms: Option<u64>,
+        /// of the video, like `duration_ms`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fps: Option<ffmpeg::FrameRate>,
+        /// whether the video got probed for `duration_ms` and `fps`, which
+        /// stay `None` for videos without them (e.g. audio only), so scans
+        /// don't keep probing those
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        probed: bool,
+        /// when the video's `Overrides` sidecar last changed (see
+        /// `sidecar_timestamp()`) as of indexing, `None` if it had none
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sidecar: Option<i64>,
+    }
+
+    impl Metadata {
+        /// The video to clip from: `video_path`, or the video of a `loose`
+        /// subtitle file.
+        pub fn clip_source(&self) -> Result<&Path> {
+            if !self.loose {
+                return Ok(&self.video_path);
+            }
+            self.video.as_deref().with_context(|| {
+                format!(
+                    "no video found for the loose subtitle file {path:?}, put it next to it \
+                     (named alike) or pass its dir as --video-dir and scan again with --rescan",
+                    path = self.video_path
+                )
+            })
+        }
+
+        /// `duration_ms`, or else probed from the clip source.
+        pub fn duration(&self) -> Result<u64> {
+            match self.duration_ms {
+                Some(duration) => Ok(duration),
+                None => ffmpeg::probe_duration(self.clip_source()?),
+            }
+        }
+
+        /// `fps`, or else probed from the clip source.
+        pub fn frame_rate(&self) -> Result<ffmpeg::FrameRate> {
+            match self.fps {
+                Some(fps) => Ok(fps),
+                None => ffmpeg::probe_frame_rate(self.clip_source()?),
+            }
+        }
+
+        /// Whether a scan should probe the video for `duration_ms` and `fps`,
+        /// e.g. for entries of older schema versions.
+        fn lacks_video_info(&self) -> bool {
+            self.duration_ms.is_none() && !self.probed && !self.loose && self.alias_of.is_none()
+        }
+    }
+
+    /// Identifies a video's content (not path) cheaply: its size and a hash of
+    /// its start and end.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    pub struct Fingerprint {
+        size: u64,
+        hash: u64,
+    }
+
+    impl Fingerprint {
+        /// How much of the start and the end get hashed.
+        const SAMPLE_LEN: u64 = 1 << 20;
+
+        pub fn of(path: &Path) -> Result<Self> {
+            use std::io::{Read as _, Seek as _, SeekFrom};
+
+            let mut file = File::open(path).with_context(|| format!("fingerprinting {path:?}"))?;
+            let size = file.metadata()?.len();
+            let mut sample = Vec::new();
+            file.by_ref()
+                .take(Self::SAMPLE_LEN)
+                .read_to_end(&mut sample)?;
+            if size > 2 * Self::SAMPLE_LEN {
+                file.seek(SeekFrom::End(-Self::SAMPLE_LEN.cast_signed()))?;
+            }
+            file.read_to_end(&mut sample)?;
+            Ok(Self {
+                size,
+                hash: util::fnv1a(&sample),
+            })
+        }
+
+        /// The fingerprint shown as `fingerprint` (see `Display`), if it is one.
+        pub fn parse(fingerprint: &str) -> Option<Self> {
+            let (size, hash) = fingerprint.split_once('-')?;
+            Some(Self {
+                size: u64::from_str_radix(size, 16).ok()?,
+                hash: u64::from_str_radix(hash, 16).ok()?,
+            })
+        }
+    }
+
+    impl std::fmt::Display for Fingerprint {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(
+                f,
+                "{size:x}-{hash:016x}",
+                size = self.size,
+                hash = self.hash
+            )
+        }
     }
 
     #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Getters)]
@@ -70,14 +256,248 @@ pub mod db {
         #[serde_as(as = "Vec<(_, _)>")]
         db: InternalDB,
         db_path: PathBuf,
+        /// indexed (non-alias) videos by content, see `--dedup`
+        #[serde(skip)]
+        fingerprints: HashMap<Fingerprint, Key>,
+        /// don't save on drop, see `set_read_only()`
+        #[serde(skip)]
+        read_only: bool,
+        /// changed since loading or the last `save()`, so dropping saves it
+        #[serde(skip)]
+        dirty: bool,
+    }
+
+    /// What's in the DB, see `SubDB::stats()`.
+    #[derive(Debug, Default, PartialEq, Eq)]
+    pub struct DbStats {
+        pub videos: usize,
+        /// copies of other videos, see `Metadata::alias_of`
+        pub copies: usize,
+        /// marked stale (see `--keep-missing`)
+        pub stale: usize,
+        pub sub_files: usize,
+        pub lines: usize,
+        /// of the videos that aren't copies, sorted
+        pub lines_per_video: Vec<usize>,
+        /// by `Entry::has_changed()`
+        pub unchanged: usize,
+        pub changed: usize,
+        pub gone: usize,
+        pub unreachable: usize,
+        /// `has_changed()` failed
+        pub unknown: usize,
+        /// sub streams/files that couldn't be read, see `Metadata::errors`
+        pub errors: usize,
+        pub videos_with_errors: usize,
+    }
+
+    impl std::fmt::Display for DbStats {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let per_video = &self.lines_per_video;
+            writeln!(
+                f,
+                "Videos:          {videos} ({copies} copies of others, {stale} marked stale)",
+                videos = self.videos,
+                copies = self.copies,
+                stale = self.stale,
+            )?;
+            writeln!(f, "Subtitle files:  {n}", n = self.sub_files)?;
+            writeln!(f, "Subtitle lines:  {n}", n = self.lines)?;
+            writeln!(
+                f,
+                "Unreadable subs: {errors} in {videos} videos (see `list --errors`)",
+                errors = self.errors,
+                videos = self.videos_with_errors,
+            )?;
+            writeln!(
+                f,
+                "Lines per video: min {min}, median {median}, max {max} ({without} without subtitles)",
+                min = per_video.first().unwrap_or(&0),
+                median = per_video.get(per_video.len() / 2).unwrap_or(&0),
+                max = per_video.last().unwrap_or(&0),
+                without = per_video.iter().take_while(|lines| **lines == 0).count(),
+            )?;
+            write!(
+                f,
+                "On disk:         {unchanged} unchanged, {changed} changed (rescan), {gone} gone (prune), {unreachable} unreachable, {unknown} unknown",
+                unchanged = self.unchanged,
+                changed = self.changed,
+                gone = self.gone,
+                unreachable = self.unreachable,
+                unknown = self.unknown,
+            )
+        }
+    }
+
+    /// Bump on incompatible changes to the DB format.
+    pub const DB_SCHEMA_VERSION: &str = "0.3";
+
+    /// Older schema versions whose entries read as current ones, with the
+    /// fields added since left to their defaults. They get written as
+    /// `DB_SCHEMA_VERSION` on the next save.
+    const MIGRATABLE_SCHEMA_VERSIONS: &[&str] = &[
+        // without `Metadata::duration_ms` and `fps`
+        "0.2",
+    ];
+
+    /// Whether entries of a DB of schema `version` can be read.
+    fn is_readable_schema(version: &str) -> bool {
+        version == DB_SCHEMA_VERSION || MIGRATABLE_SCHEMA_VERSIONS.contains(&version)
     }
 
-    #[derive(Clone, Debug, Serialize, Deserialize)]
+    /// The DB file is `{"<schema version>": [[key, entry], …]}` (JSON maps only
+    /// take string keys, hence the list of pairs).
+    #[derive(Clone, Debug)]
     enum SubDBVersioned {
-        #[serde(rename = "0.2")]
         Current(InternalDB),
-        #[serde(other)]
-        Unsupported,
+        /// any other version, whose entries don't get parsed at all
+        Other(String),
+    }
+
+    /// Serializes like `SubDBVersioned`, but borrows the DB instead of cloning it.
+    #[derive(Debug)]
+    struct SubDBVersionedRef<'a>(&'a InternalDB);
+
+    impl Serialize for SubDBVersionedRef<'_> {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            use serde::ser::SerializeMap as _;
+
+            let mut map = serializer.serialize_map(Some(1))?;
+            map.serialize_entry(DB_SCHEMA_VERSION, &self.0.iter().collect_vec())?;
+            map.end()
+        }
+    }
+
+    impl<'de> Deserialize<'de> for SubDBVersioned {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct Visitor;
+
+            impl<'de> serde::de::Visitor<'de> for Visitor {
+                type Value = SubDBVersioned;
+
+                fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    f.write_str("a map from schema version to DB entries")
+                }
+
+                fn visit_map<A: serde::de::MapAccess<'de>>(
+                    self,
+                    mut map: A,
+                ) -> Result<Self::Value, A::Error> {
+                    let version = map
+                        .next_key::<String>()?
+                        .ok_or_else(|| serde::de::Error::custom("missing schema version"))?;
+                    if is_readable_schema(&version) {
+                        let entries = map.next_value::<Vec<(Key, Val)>>()?;
+                        Ok(SubDBVersioned::Current(entries.into_iter().collect()))
+                    } else {
+                        map.next_value::<serde::de::IgnoredAny>()?;
+                        Ok(SubDBVersioned::Other(version))
+                    }
+                }
+            }
+
+            deserializer.deserialize_map(Visitor)
+        }
+    }
+
+    /// What `SubDB::salvage()` could read of a damaged DB file.
+    #[derive(Debug)]
+    pub struct Salvaged {
+        /// the readable entries, read-only until `SubDB::repair()`
+        pub db: SubDB,
+        /// why entries couldn't be read, one per entry
+        pub lost: Vec<String>,
+        /// the file ends within an entry (e.g. a truncated write), so an
+        /// unknown number of entries after it is lost as well
+        pub truncated: bool,
+    }
+
+    /// The entries of a DB file's JSON that parse, why the others didn't and
+    /// whether it's truncated, see `Salvaged`.
+    fn salvage_entries(json: &str) -> Result<(InternalDB, Vec<String>, bool)> {
+        let rest = json.trim_start_matches('\u{feff}').trim_start();
+        let rest = rest.strip_prefix('{').context("not a JSON object")?;
+        let mut version = serde_json::Deserializer::from_str(rest).into_iter::<String>();
+        let schema = version
+            .next()
+            .context("missing schema version")?
+            .context("reading the schema version")?;
+        ensure!(
+            is_readable_schema(&schema),
+            "can only salvage DBs of schema version {DB_SCHEMA_VERSION} (or migratable ones), not {schema}"
+        );
+        let mut rest = rest[version.byte_offset()..]
+            .trim_start()
+            .strip_prefix(':')
+            .and_then(|rest| rest.trim_start().strip_prefix('['))
+            .context("expected the list of entries after the schema version")?;
+
+        let mut db = InternalDB::new();
+        let mut lost = Vec::new();
+        let mut truncated = false;
+        for n in 1.. {
+            rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+            if rest.is_empty() {
+                truncated = true;
+                break;
+            }
+            if rest.starts_with(']') {
+                break;
+            }
+            let mut values =
+                serde_json::Deserializer::from_str(rest).into_iter::<serde_json::Value>();
+            match values.next() {
+                Some(Ok(value)) => {
+                    match serde_json::from_value::<(Key, Val)>(value) {
+                        Ok((key, entry)) => {
+                            db.insert(key, entry);
+                        }
+                        Err(e) => lost.push(format!("entry {n}: {e}")),
+                    }
+                    rest = &rest[values.byte_offset()..];
+                }
+                Some(Err(e)) if e.is_eof() => {
+                    truncated = true;
+                    break;
+                }
+                Some(Err(e)) => {
+                    lost.push(format!("entry {n}: {e}"));
+                    let Some(next) = next_entry(rest) else {
+                        truncated = true;
+                        break;
+                    };
+                    rest = next;
+                }
+                None => unreachable!("[ASSERT] rest isn't empty"),
+            }
+        }
+        Ok((db, lost, truncated))
+    }
+
+    /// `rest` from the next entry that parses on, after the syntax error at its
+    /// start: the next `[` that starts a valid `(Key, Val)`.
+    fn next_entry(rest: &str) -> Option<&str> {
+        rest.char_indices()
+            .skip(1)
+            .filter(|(_, c)| *c == '[')
+            .map(|(i, _)| &rest[i..])
+            .find(|candidate| {
+                serde_json::Deserializer::from_str(candidate)
+                    .into_iter::<(Key, Val)>()
+                    .next()
+                    .is_some_and(|parsed| parsed.is_ok())
+            })
+    }
+
+    /// Whether `version` is newer than `DB_SCHEMA_VERSION`. Unparsable versions
+    /// count as newer, since they're from nothing we ever wrote.
+    fn is_newer_schema(version: &str) -> bool {
+        fn parse(version: &str) -> Option<Vec<u32>> {
+            version.split('.').map(|n| n.parse().ok()).collect()
+        }
+        parse(version).is_none_or(|version| {
+            version > parse(DB_SCHEMA_VERSION).expect("valid DB_SCHEMA_VERSION")
+        })
     }
 
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -85,33 +505,127 @@ pub mod db {
         Yes,
         No,
         Gone,
+        /// missing, but so is its dir, so maybe just an unmounted drive
+        Unreachable,
     }
 
     #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, EnumDiscriminants)]
     pub enum EntryFound {
         YesButGone,
+        YesButUnreachable,
         YesButChanged,
         Yes(Val),
         No,
     }
 
+    /// Returns ctime or mtime (in ns), whatever changed more recently.
+    #[cfg(unix)]
+    fn relevant_timestamp(meta: &std::fs::Metadata) -> Result<i64> {
+        use std::os::unix::fs::MetadataExt as _;
+
+        let ctime = meta
+            .ctime()
+            .checked_mul(10i64.pow(9))
+            .and_then(|ts| ts.checked_add(meta.ctime_nsec()))
+            .ok_or_else(|| {
+                anyhow!("nanos not fitting in i64. either corruption or we have the year ~2540+")
+            })?;
+        let mtime = meta
+            .mtime()
+            .checked_mul(10i64.pow(9))
+            .and_then(|ts| ts.checked_add(meta.mtime_nsec()))
+            .ok_or_else(|| {
+                anyhow!("nanos not fitting in i64. either corruption or we have the year ~2540+")
+            })?;
+        ensure!(ctime > 0);
+        ensure!(mtime > 0);
+        Ok(Ord::max(ctime, mtime))
+    }
+
+    /// There's no ctime outside of unix, so returns the creation time (which
+    /// copying/moving a file onto the drive bumps on Windows) or mtime (in ns),
+    /// whatever is more recent. Just mtime where there's no creation time.
+    #[cfg(not(unix))]
+    fn relevant_timestamp(meta: &std::fs::Metadata) -> Result<i64> {
+        let nanos = |time: std::io::Result<std::time::SystemTime>| -> Result<i64> {
+            let since_epoch = time?.duration_since(std::time::UNIX_EPOCH)?;
+            Ok(i64::try_from(since_epoch.as_nanos())?)
+        };
+        let mtime = nanos(meta.modified())?;
+        ensure!(mtime > 0);
+        Ok(nanos(meta.created()).map_or(mtime, |created| Ord::max(created, mtime)))
+    }
+
+    /// When the `Overrides` sidecar of `video` last changed (see
+    /// `relevant_timestamp()`), `None` if there's none.
+    fn sidecar_timestamp(video: &Path) -> Result<Option<i64>> {
+        let sidecar = Overrides::sidecar(video);
+        match sidecar.metadata() {
+            Ok(meta) => relevant_timestamp(&meta).map(Some),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("accessing overrides {sidecar:?}")),
+        }
+    }
+
+    /// What `SubDB::prepare_update()` found, plus the freshly indexed entry if
+    /// the video is new or changed.
+    #[derive(Debug)]
+    pub struct PreparedUpdate {
+        found: EntryFound,
+        new_entry: Option<Entry>,
+        /// for up-to-date entries lacking it, see `Metadata::lacks_video_info()`
+        video_probe: Option<ffmpeg::VideoProbe>,
+    }
+
     impl Entry {
+        /// An entry without subs of its own, see `Metadata::alias_of`. `sidecar`
+        /// is as in `Metadata`, for `has_changed()`.
+        fn alias(
+            key: &Key,
+            fingerprint: Fingerprint,
+            canonical: &Key,
+            sidecar: Option<i64>,
+        ) -> Self {
+            Self {
+                meta: Metadata {
+                    video_path: key.video_path.clone(),
+                    time: Utc::now(),
+                    stale: false,
+                    fingerprint: Some(fingerprint),
+                    alias_of: Some(canonical.video_path.clone()),
+                    errors: Vec::new(),
+                    loose: false,
+                    video: None,
+                    duration_ms: None,
+                    fps: None,
+                    probed: false,
+                    sidecar,
+                },
+                sub_files: Vec::new(),
+            }
+        }
+
+        /// An entry of `video_path` without subs, for tests outside this module.
+        #[cfg(test)]
+        pub fn empty(video_path: PathBuf) -> Self {
+            Self {
+                meta: tests::meta(video_path),
+                sub_files: Vec::new(),
+            }
+        }
+
         /// # Important
-        /// This compares ctime instead of mtime to detect renames.
+        /// This compares ctime instead of mtime to detect renames (creation time
+        /// on Windows, see `relevant_timestamp()`).
         pub fn has_changed(&self) -> Result<EntryChanged> {
             #[allow(clippy::enum_glob_use)]
             use EntryChanged::*;
 
-            // return ctime or mtime, whatever changed more recently
-            fn relevant_timestamp(meta: &std::fs::Metadata) -> Result<i64> {
-                let ctime = meta.ctime().checked_mul(10i64.pow(9)).and_then(|ts| ts.checked_add(meta.ctime_nsec())).ok_or_else(|| anyhow!("nanos not fitting in i64. either corruption or we have the year ~2540+"))?;
-                let mtime = meta.mtime().checked_mul(10i64.pow(9)).and_then(|ts| ts.checked_add(meta.mtime_nsec())).ok_or_else(|| anyhow!("nanos not fitting in i64. either corruption or we have the year ~2540+"))?;
-                ensure!(ctime > 0);
-                ensure!(mtime > 0);
-                Ok(Ord::max(ctime, mtime))
+            if !self.meta.video_path.exists() {
+                let dir_exists = self.meta.video_path.parent().is_some_and(Path::is_dir);
+                return Ok(if dir_exists { Gone } else { Unreachable });
             }
-
-            if !self.meta.video_path.exists() || !self.meta.video_path.is_file() {
+            if !self.meta.video_path.is_file() {
                 return Ok(Gone);
             }
             let db_scan_nanos = self.meta.time.timestamp_nanos_opt().ok_or_else(|| anyhow!("nsec timestamp not in u64 range. Either corrupt SubDB or more than 580 years have passed.")).
@@ -137,14 +651,25 @@ pub mod db {
                 )
             })?;
 
-            if dbg!(fs_relevant_timestamp) >= dbg!(db_scan_nanos) {
+            trace!(
+                "{file:?}: changed at {fs_relevant_timestamp}ns, scanned at {db_scan_nanos}ns",
+                file = self.meta.video_path
+            );
+            // adding, editing or deleting the overrides counts as changing the video
+            let sidecar = sidecar_timestamp(&self.meta.video_path)?;
+            if fs_relevant_timestamp >= db_scan_nanos || sidecar != self.meta.sidecar {
                 Ok(Yes)
             } else {
                 Ok(No)
             }
         }
 
-        fn from_path(key: &Key) -> Result<(Self, Vec<anyhow::Error>)> {
+        /// The entry of `key`'s video, with errors of single sub streams/files
+        /// in `Metadata::errors`.
+        fn from_path(key: &Key, opts: &IndexOptions) -> Result<Self> {
+            if super::has_sub_ext(&key.video_path) {
+                return Self::from_loose_sub(key, opts);
+            }
             let ctx = |what: &str| {
                 let what = what.to_owned();
                 move || {
@@ -156,36 +681,401 @@ pub mod db {
             };
 
             let scan_time = Utc::now();
-            let temp_dir = tempfile::tempdir()?;
-
-            let subs = ffmpeg::extract_sub_files(&key.video_path, &temp_dir)
-                .with_context(ctx("Extracting"))?;
-            let subs = subs.iter().enumerate().map(|(stream_id, sub_file)| {
-                Ok((
-                    SubPath::InternalFFmpeg { stream_id },
-                    super::parse_from_file(sub_file).with_context(ctx("Parsing"))?,
-                ))
-            });
+            // before reading it, so changes while indexing don't go unnoticed
+            let sidecar = sidecar_timestamp(&key.video_path)?;
+            let overrides = Overrides::load(&key.video_path)?;
+            let probe = ffmpeg::probe_video(&key.video_path).with_context(ctx("Probing"))?;
+            overrides.check_stream_count(&key.video_path, probe.sub_streams.len())?;
+            let mut meta = Metadata {
+                video_path: key.video_path.clone(),
+                time: scan_time,
+                stale: false,
+                fingerprint: None,
+                alias_of: None,
+                errors: super::vobsub_errors(&key.video_path),
+                loose: false,
+                video: None,
+                duration_ms: probe.duration,
+                fps: probe.fps,
+                probed: true,
+                sidecar,
+            };
+
+            let streams = probe
+                .sub_streams
+                .into_iter()
+                .enumerate()
+                .filter(|(stream_id, _)| overrides.keeps_stream(*stream_id))
+                .collect_vec();
+            let siblings =
+                super::sub_files(&key.video_path, &overrides).with_context(ctx("Finding"))?;
+            // cheap way out for videos without any subs, so they don't cost a
+            // temp dir; storing the empty entry keeps them from being re-probed
+            if streams.is_empty() && siblings.is_empty() {
+                return Ok(Self {
+                    meta,
+                    sub_files: Vec::new(),
+                });
+            }
 
-            let (subs, errors): (Vec<_>, Vec<_>) = subs.partition_result();
-            Ok((
-                Self {
-                    meta: Metadata {
-                        video_path: key.video_path.clone(),
-                        time: scan_time,
+            let cache = match &opts.extract_cache {
+                Some(dir) if !streams.is_empty() => {
+                    let fingerprint = Fingerprint::of(&key.video_path)?;
+                    meta.fingerprint = Some(fingerprint);
+                    Some((ExtractCache::new(dir), fingerprint))
+                }
+                _ => None,
+            };
+            // text streams get read from ffmpeg in memory, files are only
+            // needed for OCR and the cache
+            let needs_files = cache.is_some()
+                || (opts.ocr && streams.iter().any(|(_, stream)| stream.is_image_sub()));
+            let temp_dir = needs_files
+                .then(|| util::temp_dir(opts.temp_dir.as_deref()))
+                .transpose()?;
+            let internal = streams.into_iter().map(|(stream_id, stream)| {
+                let subs = if stream.is_image_sub() && !opts.ocr {
+                    Err(anyhow!(
+                        "sub stream {stream_id} is image-based, pass --ocr to read it"
+                    ))
+                } else {
+                    Self::read_stream(
+                        key,
+                        stream_id,
+                        stream.is_image_sub(),
+                        opts,
+                        cache.as_ref(),
+                        temp_dir.as_ref().map(tempfile::TempDir::path),
+                    )
+                };
+                (
+                    SubPath::InternalFFmpeg {
+                        stream_id,
+                        language: overrides.language(stream_id, stream.tags.language),
+                        forced: stream.disposition.forced != 0,
+                        default: stream.disposition.default != 0,
                     },
-                    sub_files: subs,
+                    subs.with_context(ctx("Extracting")),
+                )
+            });
+            let external = siblings.into_iter().map(|(path, language)| {
+                let subs = super::parse_from_file(&path, opts.parsing);
+                (
+                    SubPath::External { path, language },
+                    subs.with_context(ctx("Parsing")),
+                )
+            });
+            let subs = internal
+                .chain(external)
+                .map(|(sub_path, subs)| Ok((sub_path, subs?)));
+
+            let (subs, errors): (Vec<_>, Vec<anyhow::Error>) = subs.partition_result();
+            meta.errors.extend(errors.iter().map(|e| format!("{e:#}")));
+            Ok(Self {
+                meta,
+                sub_files: subs,
+            })
+        }
+
+        /// The entry of a loose subtitle file (`key`), whose video may be
+        /// elsewhere or nowhere, see `Metadata::clip_source()`.
+        fn from_loose_sub(key: &Key, opts: &IndexOptions) -> Result<Self> {
+            let path = &key.video_path;
+            // one named alike counts, it might be meant for the video
+            let sidecar = sidecar_timestamp(path)?;
+            let is_srt = path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("srt"));
+            let subs = if is_srt {
+                super::parse_from_file(path, opts.parsing)?
+            } else {
+                // ffmpeg converts the other formats
+                let srt = ffmpeg::read_sub_stream(path, 0)?;
+                super::parse_from_bytes(&srt, opts.parsing, &format!("{path:?}"))?
+            };
+            let language = super::loose_sub_language(path);
+            let dirs = opts
+                .video_dir
+                .iter()
+                .map(PathBuf::as_path)
+                .chain(path.parent());
+            let video = super::loose_sub_video(path, dirs);
+            if video.is_none() {
+                debug!("No video found for {path:?}, it can be searched but not clipped");
+            }
+            Ok(Self {
+                meta: Metadata {
+                    video_path: path.clone(),
+                    time: Utc::now(),
+                    stale: false,
+                    fingerprint: None,
+                    alias_of: None,
+                    errors: Vec::new(),
+                    loose: true,
+                    video,
+                    duration_ms: None,
+                    fps: None,
+                    probed: false,
+                    sidecar,
                 },
-                errors,
-            ))
+                sub_files: vec![(
+                    SubPath::External {
+                        path: path.clone(),
+                        language,
+                    },
+                    subs,
+                )],
+            })
         }
 
-        pub fn as_identifying_strings(&self) -> impl Iterator<Item = String> + '_ {
-            self.sub_files
+        /// Subs of the `stream_id`-th sub stream of `key`'s video, from `cache`
+        /// if it has them. Otherwise they get extracted (or OCR'd, if `image`)
+        /// into `temp_dir` and cached, or without a `temp_dir` read straight
+        /// from ffmpeg.
+        fn read_stream(
+            key: &Key,
+            stream_id: usize,
+            image: bool,
+            opts: &IndexOptions,
+            cache: Option<&(ExtractCache, Fingerprint)>,
+            temp_dir: Option<&Path>,
+        ) -> Result<Subtitles> {
+            let cached = cache.and_then(|(cache, fingerprint)| cache.get(*fingerprint, stream_id));
+            if let Some(cached) = cached {
+                return super::parse_from_file(cached, opts.parsing);
+            }
+            let Some(temp_dir) = temp_dir else {
+                let srt = ffmpeg::read_sub_stream(&key.video_path, stream_id)?;
+                let origin = format!("sub stream {stream_id} of {key:?}");
+                return super::parse_from_bytes(&srt, opts.parsing, &origin);
+            };
+            let extracted = if image {
+                ffmpeg::ocr_sub_stream(&key.video_path, stream_id, temp_dir)
+            } else {
+                ffmpeg::extract_sub_stream(&key.video_path, stream_id, temp_dir)
+            }?;
+            if let Some((cache, fingerprint)) = cache {
+                if let Err(e) = cache.put(*fingerprint, stream_id, &extracted) {
+                    warn!("Couldn't cache sub stream {stream_id} of {key:?}: {e:#}");
+                }
+            }
+            super::parse_from_file(extracted, opts.parsing)
+        }
+
+        pub fn as_identifying_strings(
+            &self,
+            search_opts: SearchOptions,
+        ) -> impl Iterator<Item = SearchLine> + '_ {
+            let window = self.search_window(search_opts);
+            let translations = self.translations(search_opts);
+            let translation_stream = translations.as_ref().map(|(sub_path, _)| *sub_path);
+            let preferred = search_opts
+                .prefer_stream
+                .and_then(|preference| self.preferred_stream(preference, translation_stream));
+            self.subs(search_opts)
+                .filter(move |(_, sub)| window.contains(&ffmpeg::duration_to_millis(sub.start())))
+                // shown next to the original lines instead
+                .filter(move |(sub_path, _)| Some(*sub_path) != translation_stream)
+                .filter(move |(sub_path, _)| {
+                    preferred.is_none_or(|preferred| *sub_path == preferred)
+                })
+                .map(move |(sub_path, sub)| {
+                    let translation = translation_of(
+                        sub_path,
+                        &sub,
+                        translations.as_ref().map(|(_, subs)| subs.as_slice()),
+                        search_opts,
+                    );
+                    SearchLine {
+                        display: self.display_line(
+                            sub_path,
+                            &sub,
+                            translation.as_deref(),
+                            search_opts,
+                        ),
+                        search_key: sub.search_key(search_opts, translation.as_deref()),
+                        text: search_opts
+                            .keep_text
+                            .then(|| sub.dialogue(search_opts, translation.as_deref())),
+                        start: ffmpeg::duration_to_millis(sub.start()),
+                        end: ffmpeg::duration_to_millis(sub.end()),
+                    }
+                })
+        }
+
+        /// The only stream to search per `preference`, other than `except`.
+        fn preferred_stream(
+            &self,
+            preference: super::StreamPreference,
+            except: Option<&SubPath>,
+        ) -> Option<&SubPath> {
+            let candidates = || {
+                self.sub_files
+                    .iter()
+                    .filter(move |(sub_path, _)| Some(sub_path) != except)
+            };
+            // the first on ties
+            let most_lines = || {
+                candidates()
+                    .min_by_key(|(_, subs)| std::cmp::Reverse(subs.len()))
+                    .map(|(sub_path, _)| sub_path)
+            };
+            match preference {
+                super::StreamPreference::MostLines => most_lines(),
+                super::StreamPreference::Default => candidates()
+                    .map(|(sub_path, _)| sub_path)
+                    .find(|sub_path| sub_path.is_default())
+                    .or_else(most_lines),
+            }
+        }
+
+        /// The `--pair-langs` translation stream and its subtitles (as
+        /// searched), if `search_opts` pair languages and `self` has streams
+        /// of both.
+        fn translations(
+            &self,
+            search_opts: SearchOptions,
+        ) -> Option<(&SubPath, Vec<Cow<'_, Subtitle>>)> {
+            let pair = search_opts.pair_langs?;
+            let is = |sub_path: &SubPath, language: super::Language| {
+                sub_path.language().is_some_and(|tag| language.matches(tag))
+            };
+            if !self
+                .sub_files
                 .iter()
-                .flat_map(|(_, subs)| subs)
-                .map(|sub| sub.as_identifying_string(&self.meta.video_path, Default::default()))
+                .any(|(sub_path, _)| is(sub_path, pair.original))
+            {
+                return None;
+            }
+            let (stream, _) = self
+                .sub_files
+                .iter()
+                .find(|(sub_path, _)| is(sub_path, pair.translation))?;
+            let subs = self
+                .subs(search_opts)
+                .filter(|(sub_path, _)| *sub_path == stream)
+                .map(|(_, sub)| sub)
+                .collect();
+            Some((stream, subs))
+        }
+
+        /// The subtitles of all streams as searched, i.e. merged, rescaled and
+        /// shifted per `search_opts` (in that order). The stored ones stay as they are.
+        pub fn subs(
+            &self,
+            search_opts: SearchOptions,
+        ) -> impl Iterator<Item = (&SubPath, Cow<'_, Subtitle>)> + '_ {
+            self.sub_files.iter().flat_map(move |(sub_path, subs)| {
+                let subs = if search_opts.merge_overlapping {
+                    super::merge_overlapping(subs)
+                } else {
+                    subs.iter().map(Cow::Borrowed).collect_vec()
+                };
+                let subs = match search_opts.fps_correction {
+                    Some(correction) => subs
+                        .into_iter()
+                        .map(|sub| Cow::Owned(correction.apply(&sub)))
+                        .collect(),
+                    None => subs,
+                };
+                let subs = match search_opts.sub_offset {
+                    0 => subs,
+                    offset => subs
+                        .into_iter()
+                        .map(|sub| Cow::Owned(sub.shifted(offset)))
+                        .collect(),
+                };
+                subs.into_iter().map(move |sub| (sub_path, sub))
+            })
+        }
+
+        /// Where subtitles have to start (in ms) to be searched, after skipping
+        /// the intro and outro. Without the video (for its length), the outro
+        /// doesn't get skipped.
+        fn search_window(&self, search_opts: SearchOptions) -> RangeInclusive<u64> {
+            let end = match search_opts.skip_outro {
+                0 => u64::MAX,
+                outro => match self.meta.duration() {
+                    Ok(duration) => duration.saturating_sub(outro),
+                    Err(e) => {
+                        warn!(
+                            "Not skipping the outro of {path:?}: {e:#}",
+                            path = self.meta.video_path
+                        );
+                        u64::MAX
+                    }
+                },
+            };
+            search_opts.skip_intro..=end
+        }
+
+        /// What `as_identifying_strings()` shows for `sub`, so a selected line
+        /// can be found again.
+        pub fn display_string(
+            &self,
+            sub_path: &SubPath,
+            sub: &Subtitle,
+            search_opts: SearchOptions,
+        ) -> String {
+            let translations = self.translations(search_opts);
+            let translation = translation_of(
+                sub_path,
+                sub,
+                translations.as_ref().map(|(_, subs)| subs.as_slice()),
+                search_opts,
+            );
+            self.display_line(sub_path, sub, translation.as_deref(), search_opts)
+        }
+
+        fn display_line(
+            &self,
+            sub_path: &SubPath,
+            sub: &Subtitle,
+            translation: Option<&str>,
+            search_opts: SearchOptions,
+        ) -> String {
+            let line = sub.as_identifying_string(
+                &self.meta.video_path,
+                Some(sub_path),
+                translation,
+                search_opts.clean,
+            );
+            match search_opts
+                .show_episode
+                .then(|| super::episode_tag(&self.meta.video_path))
+                .flatten()
+            {
+                Some(episode) => format!("[{episode}] {line}"),
+                None => line,
+            }
+        }
+    }
+
+    /// The text of the `translations` shown along with `sub`, if it's of
+    /// the original language, see `Entry::translations()`.
+    fn translation_of(
+        sub_path: &SubPath,
+        sub: &Subtitle,
+        translations: Option<&[Cow<'_, Subtitle>]>,
+        search_opts: SearchOptions,
+    ) -> Option<String> {
+        let (pair, translations) = search_opts.pair_langs.zip(translations)?;
+        if !sub_path
+            .language()
+            .is_some_and(|tag| pair.original.matches(tag))
+        {
+            return None;
         }
+        let text = translations
+            .iter()
+            .filter(|translation| sub.is_aligned(translation))
+            .map(|translation| {
+                super::clean_text(&translation.0.text, search_opts.clean)
+                    .split_whitespace()
+                    .join(" ")
+            })
+            .join(" ");
+        (!text.is_empty()).then_some(text)
     }
 
     impl SubDB {
@@ -197,31 +1087,116 @@ pub mod db {
 
             let db = if db_file.exists() {
                 let db_version_wrapper =
-                    serde_json::from_reader(BufReader::new(File::open(db_file)?))?;
+                    serde_json::from_reader(BufReader::new(File::open(db_file)?))
+                        .with_context(|| format!("reading DB {db_file:?}"))?;
                 match db_version_wrapper {
                     Current(db) => db,
-                    Unsupported => {
-                        panic!("Wrong version in DB file detected (0.1 is the only supported)",)
-                    }
+                    Other(version) if is_newer_schema(&version) => bail!(
+                        "DB {db_file:?} has schema version {version}, which is newer than this \
+                         magiclip supports ({DB_SCHEMA_VERSION}). Please upgrade magiclip."
+                    ),
+                    Other(version) => bail!(
+                        "DB {db_file:?} has the outdated schema version {version} (this magiclip \
+                         uses {DB_SCHEMA_VERSION}). Delete it (or pass another --db-file) to \
+                         rescan from scratch."
+                    ),
                 }
             } else {
                 HashMap::default()
             };
 
-            Ok(Self {
+            Ok(Self::from_entries(db_file, db))
+        }
+
+        fn from_entries(db_file: &Path, db: InternalDB) -> Self {
+            let fingerprints = db
+                .iter()
+                .filter(|(_, entry)| entry.meta.alias_of.is_none())
+                .filter_map(|(key, entry)| Some((entry.meta.fingerprint?, key.clone())))
+                .collect();
+            Self {
                 db_path: db_file.to_owned(),
                 db,
+                fingerprints,
+                read_only: false,
+                dirty: false,
+            }
+        }
+
+        /// Reads what's readable of a DB file `load()` fails on, e.g. after a
+        /// crash mid-write: the entries that parse, reporting the others. Only
+        /// works for the current schema version.
+        pub fn salvage(db_file: impl AsRef<Path>) -> Result<Salvaged> {
+            let db_file = db_file.as_ref();
+            let json = std::fs::read(db_file).with_context(|| format!("reading DB {db_file:?}"))?;
+            // a write cut off within a multi-byte char mustn't fail it all
+            let json = String::from_utf8_lossy(&json);
+            let (db, lost, truncated) =
+                salvage_entries(&json).with_context(|| format!("salvaging DB {db_file:?}"))?;
+            let mut db = Self::from_entries(db_file, db);
+            db.set_read_only();
+            Ok(Salvaged {
+                db,
+                lost,
+                truncated,
             })
         }
 
-        pub fn save(&self) -> Result<()> {
-            // TODO clone is probably overkill, but I cannot use a ref in `SubDBVersioned`
-            // because then deserializing gets more complicated. ('d have to investigate tho)
-            let db_versioned = SubDBVersioned::Current(self.db.clone());
-            to_anyhow(serde_json::to_writer_pretty(
-                BufWriter::new(File::create(&self.db_path)?),
-                &db_versioned,
-            ))
+        /// Replaces the DB file with the `salvage()`d entries, after copying the
+        /// original to `<db file>.<time>.bak`, which gets returned.
+        pub fn repair(mut self) -> Result<PathBuf> {
+            let mut backup = self.db_path.clone().into_os_string();
+            backup.push(format!(".{}.bak", Utc::now().format("%Y%m%dT%H%M%S")));
+            let backup = PathBuf::from(backup);
+            std::fs::copy(&self.db_path, &backup)
+                .with_context(|| format!("backing up {:?} to {backup:?}", self.db_path))?;
+            self.save()?;
+            Ok(backup)
+        }
+
+        /// Where `--extract-cache` goes, next to the DB file.
+        pub fn extract_cache_dir(&self) -> PathBuf {
+            self.db_path
+                .parent()
+                .unwrap_or(Path::new(""))
+                .join(crate::extract_cache::EXTRACT_CACHE_DIRNAME)
+        }
+
+        /// Keeps dropping the DB from saving it even if it changed, for commands
+        /// that only read it (saving rewrites the whole file).
+        pub fn set_read_only(&mut self) {
+            self.read_only = true;
+        }
+
+        /// Writes to a temp file next to the DB first and renames it over the DB
+        /// afterwards, so a crash while saving doesn't leave a truncated DB.
+        pub fn save(&mut self) -> Result<()> {
+            let db_versioned = SubDBVersionedRef(&self.db);
+            let mut temp_path = self.db_path.clone().into_os_string();
+            temp_path.push(".tmp");
+
+            let mut writer = BufWriter::new(
+                File::create(&temp_path).with_context(|| format!("creating {temp_path:?}"))?,
+            );
+            to_anyhow(serde_json::to_writer_pretty(&mut writer, &db_versioned))?;
+            writer
+                .into_inner()
+                .map_err(std::io::IntoInnerError::into_error)?
+                .sync_all()?;
+            std::fs::rename(&temp_path, &self.db_path)
+                .with_context(|| format!("replacing {:?}", self.db_path))?;
+            self.dirty = false;
+            Ok(())
+        }
+
+        /// Errors if `save()` couldn't write the DB file, so a scan finds out
+        /// before doing the work rather than at its first checkpoint.
+        pub fn check_writable(&self) -> Result<()> {
+            let mut probe = self.db_path.clone().into_os_string();
+            probe.push(".tmp");
+            File::create(&probe)
+                .with_context(|| format!("DB {:?} isn't writable", self.db_path))?;
+            std::fs::remove_file(&probe).with_context(|| format!("removing {probe:?}"))
         }
 
         pub fn lookup(&self, key: &Key) -> Result<EntryFound> {
@@ -229,12 +1204,20 @@ pub mod db {
             use EntryFound::*;
 
             let found = if let Some(entry) = self.db.get(key).map(Clone::clone) {
+                let orphaned = entry.meta.alias_of.as_ref().is_some_and(|canonical| {
+                    !self.db.contains_key(&Key {
+                        video_path: canonical.clone(),
+                    })
+                });
                 match entry.has_changed().with_context(|| {
                     format!("determining if entry with key {key:#?} has changed")
                 })? {
                     EntryChanged::Yes => YesButChanged,
+                    // its subs went with the video it's an alias of
+                    EntryChanged::No if orphaned => YesButChanged,
                     EntryChanged::No => Yes(entry),
                     EntryChanged::Gone => YesButGone,
+                    EntryChanged::Unreachable => YesButUnreachable,
                 }
             } else {
                 No
@@ -243,43 +1226,283 @@ pub mod db {
             Ok(found)
         }
 
+        /// Gets the cached entry without checking the video on disk.
+        pub fn get(&self, key: &Key) -> Option<&Val> {
+            self.db.get(key)
+        }
+
         /// Gets the entry from the DB if it exists and is up-to-date (file hasn't
         /// been modified in between). Otherwise create it (from the file).
-        pub fn lookup_or_update(&mut self, key: &Key) -> Result<Option<Val>> {
-            fn insert(self_: &mut SubDB, key: &Key) -> Result<Val> {
-                // passing up errored sub files gets too complicated; bailing out by logging
-                let new_entry = Entry::from_path(key).context("creating DB entry from file")?;
-                for error in new_entry.1 {
-                    warn!("Error parsing subs:\n{error:#}");
+        ///
+        /// Also returns what the lookup found, to tell cache hits (`Yes`) from
+        /// freshly indexed entries.
+        pub fn lookup_or_update(
+            &mut self,
+            key: &Key,
+            opts: &IndexOptions,
+        ) -> Result<(Option<Val>, EntryFoundDiscriminants)> {
+            let prepared = self.prepare_update(key, opts)?;
+            Ok(self.apply_update(key, prepared, opts))
+        }
+
+        /// The slow, read-only half of `lookup_or_update()`: looks `key` up and
+        /// indexes its video if needed, so it can run in parallel.
+        pub fn prepare_update(&self, key: &Key, opts: &IndexOptions) -> Result<PreparedUpdate> {
+            let found = match self.lookup(key)? {
+                EntryFound::Yes(_) if opts.rescan.includes(&key.video_path) => {
+                    EntryFound::YesButChanged
+                }
+                found => found,
+            };
+            let new_entry = match found {
+                EntryFound::YesButChanged | EntryFound::No if opts.dedup => {
+                    let fingerprint = Fingerprint::of(&key.video_path)?;
+                    if let Some(canonical) = self.canonical(fingerprint, key) {
+                        let sidecar = sidecar_timestamp(&key.video_path)?;
+                        Some(Entry::alias(key, fingerprint, canonical, sidecar))
+                    } else {
+                        let mut entry = self.index(key, opts)?;
+                        entry.meta.fingerprint = Some(fingerprint);
+                        Some(entry)
+                    }
                 }
+                EntryFound::YesButChanged | EntryFound::No => Some(self.index(key, opts)?),
+                _ => None,
+            };
+            let loose = super::has_sub_ext(&key.video_path);
+            if let (Some(entry), Some(dir), false) = (&new_entry, &opts.thumbnails, loose) {
+                Self::contact_sheet(key, entry.meta.duration_ms, dir);
+            }
+            let video_probe = match &found {
+                EntryFound::Yes(entry) if entry.meta.lacks_video_info() => {
+                    ffmpeg::probe_video(&key.video_path)
+                        .inspect_err(|e| {
+                            debug!("Couldn't probe {key:?} for its length and frame rate: {e:#}");
+                        })
+                        .ok()
+                }
+                _ => None,
+            };
+            Ok(PreparedUpdate {
+                found,
+                new_entry,
+                video_probe,
+            })
+        }
+
+        fn index(&self, key: &Key, opts: &IndexOptions) -> Result<Entry> {
+            let entry = Entry::from_path(key, opts).context("creating DB entry from file")?;
+            // the ones known from indexing it before were logged back then
+            let known = self
+                .db
+                .get(key)
+                .map_or(&[][..], |old| old.meta.errors.as_slice());
+            for error in &entry.meta.errors {
+                if known.contains(error) {
+                    debug!("Error parsing subs (again):\n{error}");
+                } else {
+                    warn!("Error parsing subs:\n{error}");
+                }
+            }
+            Ok(entry)
+        }
 
-                let _ = self_.db.insert(key.clone(), Val::new(new_entry.0));
-                Ok(self_.db.get(key).unwrap().clone())
+        /// Writes the contact sheet of `key`'s video (`duration` ms long, if
+        /// known) into `dir`, named by `contact_sheet_name()`. Failing only
+        /// gets logged, the video is indexed either way.
+        fn contact_sheet(key: &Key, duration: Option<u64>, dir: &Path) {
+            let Some(name) = Self::contact_sheet_name(&key.video_path) else {
+                return;
+            };
+            let out = dir.join(name);
+            let result = std::fs::create_dir_all(dir)
+                .with_context(|| format!("creating {dir:?}"))
+                .and_then(|()| ffmpeg::contact_sheet(&key.video_path, duration, &out));
+            if let Err(e) = result {
+                warn!("Couldn't make a contact sheet of {key:?}: {e:#}");
             }
-            match self.lookup(key)? {
-                EntryFound::YesButGone => {
+        }
+
+        /// `<video name> [<8 hex digits>].jpg`, the digits hashing the whole
+        /// path, as videos in different dirs are often named alike.
+        fn contact_sheet_name(video: &Path) -> Option<String> {
+            let stem = video.file_stem()?.to_string_lossy();
+            let hash = util::fnv1a(video.as_os_str().as_encoded_bytes()) >> 32;
+            Some(format!("{stem} [{hash:08x}].jpg"))
+        }
+
+        /// Another indexed video with the same content as `key`.
+        fn canonical(&self, fingerprint: Fingerprint, key: &Key) -> Option<&Key> {
+            self.fingerprints.get(&fingerprint).filter(|canonical| {
+                *canonical != key
+                    && self.db.get(canonical).is_some_and(|entry| {
+                        entry.meta.alias_of.is_none() && entry.meta.fingerprint == Some(fingerprint)
+                    })
+            })
+        }
+
+        /// The other half of `lookup_or_update()`, updating the DB.
+        pub fn apply_update(
+            &mut self,
+            key: &Key,
+            prepared: PreparedUpdate,
+            opts: &IndexOptions,
+        ) -> (Option<Val>, EntryFoundDiscriminants) {
+            let discriminant = EntryFoundDiscriminants::from(&prepared.found);
+            let mut val = match prepared.found {
+                EntryFound::YesButGone if !opts.keep_missing => {
                     self.db.remove(key);
-                    Ok(None)
+                    self.dirty = true;
+                    None
+                }
+                EntryFound::YesButGone | EntryFound::YesButUnreachable => {
+                    Some(self.set_stale(key, true))
+                }
+                EntryFound::Yes(val) if val.meta.stale => Some(self.set_stale(key, false)),
+                EntryFound::Yes(val) => Some(val),
+                EntryFound::YesButChanged | EntryFound::No => {
+                    let mut entry = prepared
+                        .new_entry
+                        .expect("[ASSERT] indexed by prepare_update()");
+                    if let Some(fingerprint) = entry.meta.fingerprint {
+                        // identical videos indexed in parallel
+                        match (self.canonical(fingerprint, key), &entry.meta.alias_of) {
+                            (Some(canonical), None) => {
+                                let sidecar = entry.meta.sidecar;
+                                entry = Entry::alias(key, fingerprint, canonical, sidecar);
+                            }
+                            (None, None) => {
+                                self.fingerprints.insert(fingerprint, key.clone());
+                            }
+                            (_, Some(_)) => {}
+                        }
+                    }
+                    let entry = Val::new(entry);
+                    self.db.insert(key.clone(), entry.clone());
+                    self.dirty = true;
+                    Some(entry)
+                }
+            };
+            if let (Some(probe), Some(_)) = (prepared.video_probe, &val) {
+                val = Some(self.set_video_info(key, &probe));
+            }
+            (val, discriminant)
+        }
+
+        /// Fills in `Metadata::duration_ms` and `fps` of an entry indexed
+        /// without them.
+        fn set_video_info(&mut self, key: &Key, probe: &ffmpeg::VideoProbe) -> Val {
+            let entry = self
+                .db
+                .get_mut(key)
+                .expect("[ASSERT] only called for known keys");
+            if (entry.meta.duration_ms, entry.meta.fps, entry.meta.probed)
+                != (probe.duration, probe.fps, true)
+            {
+                let meta = &mut Arc::make_mut(entry).meta;
+                meta.duration_ms = probe.duration;
+                meta.fps = probe.fps;
+                meta.probed = true;
+                self.dirty = true;
+            }
+            entry.clone()
+        }
+
+        fn set_stale(&mut self, key: &Key, stale: bool) -> Val {
+            let entry = self
+                .db
+                .get_mut(key)
+                .expect("[ASSERT] only called for known keys");
+            if entry.meta.stale != stale {
+                Arc::make_mut(entry).meta.stale = stale;
+                self.dirty = true;
+            }
+            entry.clone()
+        }
+
+        /// Aggregates over all entries. Checks each video for changes, but only
+        /// by its file metadata.
+        pub fn stats(&self) -> DbStats {
+            let mut stats = DbStats::default();
+            for entry in self.db.values() {
+                stats.videos += 1;
+                stats.stale += usize::from(entry.meta.stale);
+                stats.errors += entry.meta.errors.len();
+                stats.videos_with_errors += usize::from(!entry.meta.errors.is_empty());
+                if entry.meta.alias_of.is_some() {
+                    stats.copies += 1;
+                } else {
+                    let lines = entry.sub_files.iter().map(|(_, subs)| subs.len()).sum();
+                    stats.sub_files += entry.sub_files.len();
+                    stats.lines += lines;
+                    stats.lines_per_video.push(lines);
+                }
+                match entry.has_changed() {
+                    Ok(EntryChanged::No) => stats.unchanged += 1,
+                    Ok(EntryChanged::Yes) => stats.changed += 1,
+                    Ok(EntryChanged::Gone) => stats.gone += 1,
+                    Ok(EntryChanged::Unreachable) => stats.unreachable += 1,
+                    Err(_) => stats.unknown += 1,
                 }
-                EntryFound::Yes(val) => Ok(Some(val)),
-                EntryFound::YesButChanged | EntryFound::No => Some(insert(self, key)).transpose(),
             }
+            stats.lines_per_video.sort_unstable();
+            stats
+        }
+
+        /// Removes all entries whose video is gone. Returns how many were removed.
+        /// Unreachable ones (see `EntryChanged::Unreachable`) are kept.
+        pub fn prune(&mut self) -> usize {
+            let before = self.db.len();
+            self.db.retain(|key, entry| match entry.has_changed() {
+                Ok(EntryChanged::Gone) => false,
+                Ok(EntryChanged::Yes | EntryChanged::No) => true,
+                Ok(EntryChanged::Unreachable) => {
+                    warn!("Keeping {key:?}, its dir is missing (unmounted drive?)");
+                    true
+                }
+                Err(e) => {
+                    warn!("Keeping {key:?}, couldn't check if it's gone: {e:#}");
+                    true
+                }
+            });
+            let removed = before - self.db.len();
+            self.dirty |= removed > 0;
+            removed
         }
 
-        pub fn as_identifying_strings(&self) -> impl ParallelIterator<Item = (&Key, String)> + '_ {
+        /// The search lines of all entries, or `only` that one.
+        pub fn as_identifying_strings<'a>(
+            &'a self,
+            search_opts: SearchOptions,
+            only: Option<&'a Key>,
+        ) -> impl ParallelIterator<Item = (&'a Key, SearchLine)> + 'a {
             self.db
                 .par_iter()
-                .map(|(key, entry)| entry.as_identifying_strings().map(move |id| (key, id)))
+                .filter(move |(key, _)| only.is_none_or(|only| *key == only))
+                .map(move |(key, entry)| {
+                    entry
+                        .as_identifying_strings(search_opts)
+                        .map(move |line| (key, line))
+                })
                 .flatten_iter()
         }
 
         pub fn len(&self) -> usize {
             self.db.len()
         }
+
+        pub fn iter(&self) -> impl Iterator<Item = (&Key, &Val)> {
+            self.db.iter()
+        }
     }
 
     impl Drop for SubDB {
+        /// Saves if `dirty`, but not while panicking: the DB may be half-updated
+        /// and a failing save would bury the panic.
         fn drop(&mut self) {
+            if self.read_only || !self.dirty || std::thread::panicking() {
+                return;
+            }
             self.save().unwrap_or_else(|e| error!("Saving failed: {e}"));
         }
     }
@@ -289,58 +1512,529 @@ pub mod db {
         #![allow(non_snake_case)]
 
         use anyhow::Result;
-        use std::{fs::File, io::Write as _, time::SystemTime};
+        use std::{
+            fs::File,
+            io::Write as _,
+            path::{Path, PathBuf},
+            time::SystemTime,
+        };
 
         use chrono::Utc;
         use tempfile::TempDir;
 
-        use super::{Entry, EntryChanged, Metadata};
+        use super::{
+            DbStats, Entry, EntryChanged, EntryFoundDiscriminants, Fingerprint, Key, Metadata,
+            SubDB, SubDBVersioned, SubPath, Subtitle,
+        };
+        use crate::overrides::Overrides;
+        use crate::sub::SearchOptions;
+        use crate::sub::{IndexOptions, Rescan};
+        use crate::util;
+
+        /// Metadata of `video_path` as if freshly indexed.
+        pub(super) fn meta(video_path: impl Into<PathBuf>) -> Metadata {
+            Metadata {
+                video_path: video_path.into(),
+                time: Utc::now(),
+                stale: false,
+                fingerprint: None,
+                alias_of: None,
+                errors: Vec::new(),
+                loose: false,
+                video: None,
+                duration_ms: None,
+                fps: None,
+                probed: true,
+                sidecar: None,
+            }
+        }
 
         #[test]
-        fn has_changed__no_longer_exists() -> Result<()> {
+        fn save_load_roundtrip() -> Result<()> {
             let temp_dir = TempDir::new()?;
-            let video_path = temp_dir.path().join("video.mp4");
-
-            let meta = Metadata {
-                video_path: video_path.clone(),
-                time: chrono::Utc::now(),
+            let db_path = temp_dir.path().join("db.json");
+            let key = Key {
+                video_path: temp_dir.path().join("video.mkv"),
             };
             let entry = Entry {
-                meta,
+                meta: meta(key.video_path.clone()),
                 sub_files: Vec::default(),
             };
 
-            assert_eq!(entry.has_changed()?, EntryChanged::Gone);
+            let mut db = SubDB::load(&db_path)?;
+            db.db.insert(key.clone(), entry.clone().into());
+            db.save()?;
+            drop(db);
+
+            let json = std::fs::read_to_string(&db_path)?;
+            assert!(matches!(
+                serde_json::from_str(&json)?,
+                SubDBVersioned::Current(db) if db.len() == 1
+            ));
 
+            let db = SubDB::load(&db_path)?;
+            assert_eq!(db.len(), 1);
+            assert_eq!(db.get(&key).map(AsRef::as_ref), Some(&entry));
             Ok(())
         }
 
         #[test]
-        fn has_changed__no_longer_exists_not_a_file() -> Result<()> {
+        fn set_video_info__nothing_found() -> Result<()> {
             let temp_dir = TempDir::new()?;
-            let video_path = temp_dir.path();
-
-            let meta = Metadata {
-                video_path: video_path.to_path_buf(),
-                time: chrono::Utc::now(),
+            let key = Key {
+                video_path: temp_dir.path().join("audio.mka"),
             };
-            let entry = Entry {
-                meta,
+            let mut entry = Entry {
+                meta: meta(key.video_path.clone()),
                 sub_files: Vec::default(),
             };
-
-            assert_eq!(entry.has_changed()?, EntryChanged::Gone);
-
+            entry.meta.probed = false;
+            assert!(entry.meta.lacks_video_info());
+
+            let mut db = SubDB::load(temp_dir.path().join("db.json"))?;
+            db.db.insert(key.clone(), entry.into());
+            let entry = db.set_video_info(&key, &crate::ffmpeg::VideoProbe::default());
+            assert!(db.dirty);
+            assert_eq!(entry.meta.duration_ms, None);
+            // so the next scan doesn't probe it again
+            assert!(!entry.meta.lacks_video_info());
             Ok(())
         }
 
         #[test]
-        fn has_changed__yes() -> Result<()> {
+        fn check_writable() -> Result<()> {
             let temp_dir = TempDir::new()?;
-            let video_path = temp_dir.path().join("video.mp4");
+            let db = SubDB::load(temp_dir.path().join("db.json"))?;
+            db.check_writable()?;
+            assert_eq!(std::fs::read_dir(temp_dir.path())?.count(), 0);
 
-            // IMPORTANT: call `sync_all()`/`sync_data()`, otherwise the timestamp is incorrect
-            {
+            let db = SubDB::load(temp_dir.path().join("missing").join("db.json"))?;
+            assert!(db.check_writable().is_err());
+            Ok(())
+        }
+
+        #[test]
+        fn contact_sheet_name() {
+            let name = |path: &str| SubDB::contact_sheet_name(Path::new(path)).unwrap();
+            let a = name("/shows/a/s01e01.mkv");
+            assert!(a.starts_with("s01e01 ["), "{a}");
+            assert!(a.ends_with("].jpg"), "{a}");
+            assert_eq!(a, name("/shows/a/s01e01.mkv"));
+            assert_ne!(a, name("/shows/b/s01e01.mkv"));
+        }
+
+        #[test]
+        fn drop__saves_if_dirty() -> Result<()> {
+            let temp_dir = TempDir::new()?;
+            let db_path = temp_dir.path().join("db.json");
+            let key = |name: &str| Key {
+                video_path: temp_dir.path().join(name),
+            };
+            let entry = |key: &Key| Entry::alias(key, Fingerprint { size: 1, hash: 2 }, key, None);
+            std::fs::write(temp_dir.path().join("here.mkv"), "video")?;
+
+            let mut db = SubDB::load(&db_path)?;
+            db.db
+                .insert(key("here.mkv"), entry(&key("here.mkv")).into());
+            assert_eq!(db.prune(), 0);
+            drop(db);
+            assert!(!db_path.exists(), "nothing changed through the API");
+
+            let mut db = SubDB::load(&db_path)?;
+            db.db
+                .insert(key("gone.mkv"), entry(&key("gone.mkv")).into());
+            db.save()?;
+            drop(db);
+            let saved = std::fs::read_to_string(&db_path)?;
+
+            let mut db = SubDB::load(&db_path)?;
+            assert_eq!(db.prune(), 1);
+            drop(db);
+            assert_ne!(std::fs::read_to_string(&db_path)?, saved);
+            Ok(())
+        }
+
+        #[test]
+        fn load__other_schema_version() -> Result<()> {
+            let temp_dir = TempDir::new()?;
+            let db_path = temp_dir.path().join("db.json");
+
+            std::fs::write(&db_path, r#"{"99.0": [["whatever"]]}"#)?;
+            let err = SubDB::load(&db_path).unwrap_err();
+            assert!(err.to_string().contains("upgrade magiclip"), "{err}");
+
+            std::fs::write(&db_path, r#"{"0.1": {}}"#)?;
+            let err = SubDB::load(&db_path).unwrap_err();
+            assert!(err.to_string().contains("outdated"), "{err}");
+            Ok(())
+        }
+
+        #[test]
+        fn load__migrates_older_schema() -> Result<()> {
+            let temp_dir = TempDir::new()?;
+            let db_path = temp_dir.path().join("db.json");
+            let key = Key {
+                video_path: temp_dir.path().join("a.mkv"),
+            };
+            let mut entry = Entry::alias(&key, Fingerprint { size: 1, hash: 2 }, &key, None);
+            let old = serde_json::to_string(&(&key, &entry))?;
+            std::fs::write(&db_path, format!(r#"{{"0.2": [{old}]}}"#))?;
+
+            let mut db = SubDB::load(&db_path)?;
+            let migrated = db.get(&key).expect("entry got lost");
+            assert_eq!(migrated.meta.duration_ms, None);
+            assert_eq!(migrated.meta.fps, None);
+            db.save()?;
+            drop(db);
+            let saved = std::fs::read_to_string(&db_path)?;
+            assert!(saved.contains(&format!("\"{}\"", super::DB_SCHEMA_VERSION)));
+
+            entry.meta.duration_ms = Some(1_420_512);
+            entry.meta.fps = crate::ffmpeg::FrameRate::parse("24000/1001");
+            let json = serde_json::to_string(&entry)?;
+            assert!(json.contains(r#""fps":"24000/1001""#), "{json}");
+            assert_eq!(serde_json::from_str::<Entry>(&json)?, entry);
+            Ok(())
+        }
+
+        #[test]
+        fn salvage() -> Result<()> {
+            let temp_dir = TempDir::new()?;
+            let db_path = temp_dir.path().join("db.json");
+            let pair = |name: &str| -> Result<String> {
+                let key = Key {
+                    video_path: temp_dir.path().join(name),
+                };
+                let entry = Entry::alias(&key, Fingerprint { size: 1, hash: 2 }, &key, None);
+                Ok(serde_json::to_string_pretty(&(key, entry))?)
+            };
+            let (a, b, c, d) = (
+                pair("a.mkv")?,
+                pair("b.mkv")?,
+                pair("c.mkv")?,
+                pair("d.mkv")?,
+            );
+            let wrong_type = b.replacen("\"stale\": false", "\"stale\": 1", 1);
+            let broken_syntax = c.replacen("\"time\":", "\"time\" ", 1);
+            let json = format!(
+                "{{\"{version}\": [{a}, {wrong_type},\n{broken_syntax}, {d}, {cut}",
+                version = super::DB_SCHEMA_VERSION,
+                cut = &a[..a.len() / 2],
+            );
+            std::fs::write(&db_path, &json)?;
+            assert!(SubDB::load(&db_path).is_err());
+
+            let salvaged = SubDB::salvage(&db_path)?;
+            assert_eq!(salvaged.db.len(), 2);
+            assert!(
+                salvaged.lost[0].starts_with("entry 2: "),
+                "{:?}",
+                salvaged.lost
+            );
+            assert!(
+                salvaged.lost[1].starts_with("entry 3: "),
+                "{:?}",
+                salvaged.lost
+            );
+            assert_eq!(salvaged.lost.len(), 2);
+            assert!(salvaged.truncated);
+            drop(salvaged.db);
+            assert_eq!(std::fs::read_to_string(&db_path)?, json, "read-only");
+
+            let backup = SubDB::salvage(&db_path)?.db.repair()?;
+            assert_eq!(std::fs::read_to_string(backup)?, json);
+            let repaired = SubDB::load(&db_path)?;
+            let mut videos = repaired
+                .iter()
+                .map(|(key, _)| key.video_path.clone())
+                .collect::<Vec<_>>();
+            videos.sort();
+            assert_eq!(
+                videos,
+                [temp_dir.path().join("a.mkv"), temp_dir.path().join("d.mkv")]
+            );
+
+            std::fs::write(&db_path, r#"{"0.1": []}"#)?;
+            assert!(SubDB::salvage(&db_path).is_err());
+            Ok(())
+        }
+
+        #[test]
+        fn is_newer_schema() {
+            assert!(super::is_newer_schema("0.10"));
+            assert!(super::is_newer_schema("1.0"));
+            assert!(super::is_newer_schema("future"));
+            assert!(!super::is_newer_schema("0.1"));
+            assert!(!super::is_newer_schema(super::DB_SCHEMA_VERSION));
+        }
+
+        #[test]
+        fn sub_path_tag() {
+            let internal = |language: Option<&str>, forced| SubPath::InternalFFmpeg {
+                stream_id: 1,
+                language: language.map(str::to_owned),
+                forced,
+                default: false,
+            };
+            assert_eq!(internal(None, false).tag(), "[#1]");
+            assert_eq!(internal(Some("eng"), true).tag(), "[#1] [eng] [forced]");
+
+            let external = SubPath::External {
+                path: "movie.en.srt".into(),
+                language: Some("en".to_owned()),
+            };
+            assert_eq!(external.tag(), "[srt] [en]");
+        }
+
+        #[test]
+        fn has_changed__no_longer_exists() -> Result<()> {
+            let temp_dir = TempDir::new()?;
+            let video_path = temp_dir.path().join("video.mp4");
+
+            let entry = Entry {
+                meta: meta(video_path.clone()),
+                sub_files: Vec::default(),
+            };
+
+            assert_eq!(entry.has_changed()?, EntryChanged::Gone);
+
+            Ok(())
+        }
+
+        #[test]
+        fn has_changed__no_longer_exists_not_a_file() -> Result<()> {
+            let temp_dir = TempDir::new()?;
+            let video_path = temp_dir.path();
+
+            let entry = Entry {
+                meta: meta(video_path),
+                sub_files: Vec::default(),
+            };
+
+            assert_eq!(entry.has_changed()?, EntryChanged::Gone);
+
+            Ok(())
+        }
+
+        #[test]
+        fn has_changed__unreachable() -> Result<()> {
+            let temp_dir = TempDir::new()?;
+            let video_path = temp_dir.path().join("unmounted").join("video.mp4");
+
+            let entry = Entry {
+                meta: meta(video_path),
+                sub_files: Vec::default(),
+            };
+
+            assert_eq!(entry.has_changed()?, EntryChanged::Unreachable);
+
+            Ok(())
+        }
+
+        #[test]
+        fn lookup_or_update__dedup() -> Result<()> {
+            let temp_dir = TempDir::new()?;
+            let path = |name: &str| temp_dir.path().join(name);
+            std::fs::write(path("a.mkv"), "video")?;
+            std::fs::write(path("copy.mkv"), "video")?;
+            std::fs::write(path("other.mkv"), "other")?;
+            let fingerprint = Fingerprint::of(&path("a.mkv"))?;
+            assert_eq!(fingerprint, Fingerprint::of(&path("copy.mkv"))?);
+            assert_ne!(fingerprint, Fingerprint::of(&path("other.mkv"))?);
+
+            let key = Key {
+                video_path: path("a.mkv"),
+            };
+            let entry = Entry {
+                meta: Metadata {
+                    fingerprint: Some(fingerprint),
+                    ..meta(key.video_path.clone())
+                },
+                sub_files: Vec::default(),
+            };
+            let mut db = SubDB::load(path("db.json"))?;
+            db.db.insert(key.clone(), entry.into());
+            db.fingerprints.insert(fingerprint, key.clone());
+
+            let dedup = IndexOptions {
+                dedup: true,
+                ..Default::default()
+            };
+            let copy = Key {
+                video_path: path("copy.mkv"),
+            };
+            let (val, found) = db.lookup_or_update(&copy, &dedup)?;
+            assert_eq!(found, EntryFoundDiscriminants::No);
+            assert_eq!(val.unwrap().meta().alias_of(), &Some(path("a.mkv")));
+
+            drop(db);
+            let db = SubDB::load(path("db.json"))?;
+            assert_eq!(db.fingerprints.get(&fingerprint), Some(&key));
+            Ok(())
+        }
+
+        #[test]
+        fn lookup_or_update__dedup_sidecar() -> Result<()> {
+            let temp_dir = TempDir::new()?;
+            let path = |name: &str| temp_dir.path().join(name);
+            std::fs::write(path("a.mkv"), "video")?;
+            std::fs::write(path("copy.mkv"), "video")?;
+            std::fs::write(Overrides::sidecar(&path("copy.mkv")), "stream = 1")?;
+            let fingerprint = Fingerprint::of(&path("a.mkv"))?;
+
+            let key = Key {
+                video_path: path("a.mkv"),
+            };
+            let entry = Entry {
+                meta: Metadata {
+                    fingerprint: Some(fingerprint),
+                    ..meta(key.video_path.clone())
+                },
+                sub_files: Vec::default(),
+            };
+            let mut db = SubDB::load(path("db.json"))?;
+            db.db.insert(key.clone(), entry.into());
+            db.fingerprints.insert(fingerprint, key);
+
+            let dedup = IndexOptions {
+                dedup: true,
+                ..Default::default()
+            };
+            let copy = Key {
+                video_path: path("copy.mkv"),
+            };
+            let (val, _) = db.lookup_or_update(&copy, &dedup)?;
+            let alias = val.expect("alias got lost");
+            assert_eq!(alias.meta().alias_of(), &Some(path("a.mkv")));
+            assert_eq!(alias.has_changed()?, EntryChanged::No);
+            let (_, found) = db.lookup_or_update(&copy, &dedup)?;
+            assert_eq!(found, EntryFoundDiscriminants::Yes);
+            Ok(())
+        }
+
+        #[test]
+        fn lookup_or_update__keep_missing() -> Result<()> {
+            let temp_dir = TempDir::new()?;
+            let key = Key {
+                video_path: temp_dir.path().join("video.mkv"),
+            };
+            let entry = Entry {
+                meta: meta(key.video_path.clone()),
+                sub_files: Vec::default(),
+            };
+            let mut db = SubDB::load(temp_dir.path().join("db.json"))?;
+            db.db.insert(key.clone(), entry.into());
+
+            let keep = IndexOptions {
+                keep_missing: true,
+                ..Default::default()
+            };
+            let (val, found) = db.lookup_or_update(&key, &keep)?;
+            assert_eq!(found, EntryFoundDiscriminants::YesButGone);
+            assert!(val.unwrap().meta().stale());
+            assert!(db.get(&key).unwrap().meta().stale());
+
+            let (val, _) = db.lookup_or_update(&key, &IndexOptions::default())?;
+            assert!(val.is_none());
+            assert!(db.get(&key).is_none());
+            Ok(())
+        }
+
+        #[test]
+        fn lookup_or_update__rescan() -> Result<()> {
+            let temp_dir = TempDir::new()?;
+            let key = Key {
+                video_path: temp_dir.path().join("video.mkv"),
+            };
+            std::fs::write(&key.video_path, "not really a video")?;
+            let entry = Entry {
+                meta: Metadata {
+                    time: Utc::now() + chrono::Duration::days(1),
+                    ..meta(key.video_path.clone())
+                },
+                sub_files: Vec::default(),
+            };
+            let mut db = SubDB::load(temp_dir.path().join("db.json"))?;
+            db.db.insert(key.clone(), entry.into());
+            let rescan = |rescan| IndexOptions {
+                rescan,
+                ..Default::default()
+            };
+
+            let (_, found) = db.lookup_or_update(&key, &rescan(Rescan::None))?;
+            assert_eq!(found, EntryFoundDiscriminants::Yes);
+            let other = Rescan::Matching(vec![util::Glob::new("other.mkv")?]);
+            let (_, found) = db.lookup_or_update(&key, &rescan(other))?;
+            assert_eq!(found, EntryFoundDiscriminants::Yes);
+            // re-indexing is attempted, which fails for this "video"
+            let this = Rescan::Matching(vec![util::Glob::new("*.mkv")?]);
+            assert!(db.lookup_or_update(&key, &rescan(this)).is_err());
+            assert!(db.lookup_or_update(&key, &rescan(Rescan::All)).is_err());
+            Ok(())
+        }
+
+        #[test]
+        fn stats__read_only() -> Result<()> {
+            let temp_dir = TempDir::new()?;
+            let path = |name: &str| temp_dir.path().join(name);
+            std::fs::write(path("here.mkv"), "video")?;
+            let sub = |text: &str| -> Subtitle {
+                srtlib::Subtitle::new(
+                    1,
+                    srtlib::Timestamp::new(0, 0, 0, 0),
+                    srtlib::Timestamp::new(0, 0, 1, 0),
+                    text.to_owned(),
+                )
+                .into()
+            };
+            let entry = |name: &str, subs: Vec<Subtitle>| Entry {
+                meta: Metadata {
+                    time: Utc::now() + chrono::Duration::days(1),
+                    ..meta(path(name))
+                },
+                sub_files: vec![(
+                    SubPath::External {
+                        path: path("x.srt"),
+                        language: None,
+                    },
+                    subs,
+                )],
+            };
+            let mut db = SubDB::load(path("db.json"))?;
+            for (name, subs) in [("here.mkv", vec![sub("a"), sub("b")]), ("gone.mkv", vec![])] {
+                let key = Key {
+                    video_path: path(name),
+                };
+                db.db.insert(key, entry(name, subs).into());
+            }
+            db.set_read_only();
+
+            let stats = db.stats();
+            assert_eq!(
+                stats,
+                DbStats {
+                    videos: 2,
+                    sub_files: 2,
+                    lines: 2,
+                    lines_per_video: vec![0, 2],
+                    unchanged: 1,
+                    gone: 1,
+                    ..Default::default()
+                }
+            );
+            assert!(stats.to_string().contains("min 0, median 2, max 2"));
+            drop(db);
+            assert!(!path("db.json").exists());
+            Ok(())
+        }
+
+        #[test]
+        fn has_changed__yes() -> Result<()> {
+            let temp_dir = TempDir::new()?;
+            let video_path = temp_dir.path().join("video.mp4");
+
+            // IMPORTANT: call `sync_all()`/`sync_data()`, otherwise the timestamp is incorrect
+            {
                 let mut file = File::create(&video_path)?;
                 writeln!(file, "Test content")?;
                 file.sync_all()?;
@@ -349,13 +2043,8 @@ pub mod db {
             dbg!(std::fs::read_to_string(&video_path)?);
 
             // first emulate scan
-            let current_time = Utc::now();
-            let meta = Metadata {
-                video_path: video_path.clone(),
-                time: current_time,
-            };
             let entry = Entry {
-                meta,
+                meta: meta(video_path.clone()),
                 sub_files: Vec::default(),
             };
 
@@ -381,13 +2070,11 @@ pub mod db {
             let mut file = File::create(&video_path)?;
             writeln!(file, "Test content")?;
 
-            let future_time = chrono::Utc::now() + chrono::Duration::days(1);
-            let meta = Metadata {
-                video_path: video_path.clone(),
-                time: future_time,
-            };
             let entry = Entry {
-                meta,
+                meta: Metadata {
+                    time: Utc::now() + chrono::Duration::days(1),
+                    ..meta(video_path.clone())
+                },
                 sub_files: Vec::default(),
             };
 
@@ -395,6 +2082,172 @@ pub mod db {
 
             Ok(())
         }
+
+        #[test]
+        fn has_changed__sidecar() -> Result<()> {
+            let temp_dir = TempDir::new()?;
+            let video_path = temp_dir.path().join("video.mp4");
+            File::create(&video_path)?.sync_all()?;
+            let sidecar = Overrides::sidecar(&video_path);
+            let entry = |sidecar| Entry {
+                meta: Metadata {
+                    time: Utc::now() + chrono::Duration::days(1),
+                    sidecar,
+                    ..meta(video_path.clone())
+                },
+                sub_files: Vec::default(),
+            };
+            assert_eq!(entry(None).has_changed()?, EntryChanged::No);
+
+            std::fs::write(&sidecar, "stream = 1")?;
+            assert_eq!(entry(None).has_changed()?, EntryChanged::Yes, "added");
+            let indexed = super::sidecar_timestamp(&video_path)?;
+            assert!(indexed.is_some());
+            assert_eq!(entry(indexed).has_changed()?, EntryChanged::No);
+
+            std::fs::remove_file(&sidecar)?;
+            assert_eq!(entry(indexed).has_changed()?, EntryChanged::Yes, "deleted");
+            Ok(())
+        }
+
+        /// An entry of a missing video with `gem_glow.srt` as its only subs.
+        fn gem_glow_entry() -> Result<(Entry, crate::sub::Subtitles)> {
+            let sub_file = [env!("CARGO_MANIFEST_DIR"), "test", "gem_glow.srt"]
+                .iter()
+                .collect::<std::path::PathBuf>();
+            let subs = crate::sub::parse_from_file(&sub_file, crate::sub::SubParsing::Strict)?;
+            let entry = Entry {
+                meta: meta("/gone/video.mkv"),
+                sub_files: vec![(
+                    SubPath::External {
+                        path: sub_file,
+                        language: None,
+                    },
+                    subs.clone(),
+                )],
+            };
+            Ok((entry, subs))
+        }
+
+        #[test]
+        fn as_identifying_strings__skip_intro() -> Result<()> {
+            let (entry, subs) = gem_glow_entry()?;
+            let count = |search_opts| entry.as_identifying_strings(search_opts).count();
+
+            assert_eq!(count(SearchOptions::default()), subs.len());
+            let skip_intro = crate::ffmpeg::duration_to_millis(subs[2].start());
+            let search_opts = SearchOptions {
+                skip_intro,
+                ..Default::default()
+            };
+            assert_eq!(count(search_opts), subs.len() - 2);
+            // can't probe the video's length, so it's all kept
+            let search_opts = SearchOptions {
+                skip_outro: 1000,
+                ..Default::default()
+            };
+            assert_eq!(count(search_opts), subs.len());
+            Ok(())
+        }
+
+        #[test]
+        fn as_identifying_strings__pair_langs() -> Result<()> {
+            let sub = |start: u8, end: u8, text: &str| -> Subtitle {
+                srtlib::Subtitle::new(
+                    1,
+                    srtlib::Timestamp::new(0, 0, start, 0),
+                    srtlib::Timestamp::new(0, 0, end, 0),
+                    text.to_owned(),
+                )
+                .into()
+            };
+            let stream = |stream_id, language: &str| SubPath::InternalFFmpeg {
+                stream_id,
+                language: Some(language.to_owned()),
+                forced: false,
+                default: false,
+            };
+            let (entry, _) = gem_glow_entry()?;
+            let entry = Entry {
+                sub_files: vec![
+                    (
+                        stream(1, "jpn"),
+                        vec![sub(1, 3, "こんにちは"), sub(5, 6, "はい")],
+                    ),
+                    (
+                        stream(2, "eng"),
+                        vec![sub(1, 2, "Hello"), sub(2, 4, "there"), sub(6, 8, "Yes")],
+                    ),
+                ],
+                ..entry
+            };
+            let lines = |search_opts: SearchOptions| {
+                entry
+                    .as_identifying_strings(search_opts)
+                    .map(|line| (line.display, line.search_key))
+                    .collect::<Vec<_>>()
+            };
+
+            assert_eq!(lines(SearchOptions::default()).len(), 5);
+            let search_opts = SearchOptions {
+                pair_langs: Some("ja:en".parse()?),
+                text_only: true,
+                ..Default::default()
+            };
+            let paired = lines(search_opts);
+            assert_eq!(paired.len(), 2);
+            assert!(
+                paired[0]
+                    .0
+                    .starts_with("こんにちは ⟨Hello there⟩ [00_00_01,000] [#1] [jpn]"),
+                "{paired:?}"
+            );
+            assert_eq!(paired[0].1.as_deref(), Some("こんにちは Hello there"));
+            // "Yes" overlaps too little
+            assert!(paired[1].0.starts_with("はい [00_00_05,000]"), "{paired:?}");
+            for (sub_path, sub) in &entry.sub_files[0..1] {
+                assert_eq!(
+                    entry.display_string(sub_path, &sub[0], search_opts),
+                    paired[0].0
+                );
+            }
+
+            let search_opts = SearchOptions {
+                pair_langs: Some("ja:de".parse()?),
+                ..Default::default()
+            };
+            assert_eq!(lines(search_opts).len(), 5, "no German, no pairing");
+            Ok(())
+        }
+
+        #[test]
+        fn as_identifying_strings__prefer_stream() -> Result<()> {
+            use crate::sub::StreamPreference;
+
+            let (entry, subs) = gem_glow_entry()?;
+            let signs = SubPath::InternalFFmpeg {
+                stream_id: 0,
+                language: None,
+                forced: false,
+                default: true,
+            };
+            let entry = Entry {
+                sub_files: vec![(signs, subs[..2].to_vec()), entry.sub_files[0].clone()],
+                ..entry
+            };
+            let count = |prefer_stream| {
+                let search_opts = SearchOptions {
+                    prefer_stream,
+                    ..Default::default()
+                };
+                entry.as_identifying_strings(search_opts).count()
+            };
+
+            assert_eq!(count(None), subs.len() + 2);
+            assert_eq!(count(Some(StreamPreference::MostLines)), subs.len());
+            assert_eq!(count(Some(StreamPreference::Default)), 2);
+            Ok(())
+        }
     }
 }
 
@@ -403,49 +2256,756 @@ pub struct Subtitle(#[serde(with = "serde::subtitle::Subtitle")] srtlib::Subtitl
 
 type Subtitles = Vec<Subtitle>;
 
+/// A subtitle line as shown in the finder, and optionally a separate key which
+/// queries get matched against instead of the displayed text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchLine {
+    pub display: String,
+    pub search_key: Option<String>,
+    /// the cleaned dialogue (with the translation), if
+    /// `SearchOptions::keep_text`, for ranking by `rank::rank()`
+    pub text: Option<String>,
+    /// of the subtitle, in ms
+    pub start: u64,
+    pub end: u64,
+}
+
+/// How the finder matches subtitle lines, see `SearchLine::search_key`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
-pub enum SubtitleStringFormatOptions {
-    Filename,
+#[allow(clippy::struct_excessive_bools)]
+pub struct SearchOptions {
+    /// ignore case and accents
+    pub fold: bool,
+    /// only match (and show) the dialogue, not timestamps or paths
+    pub text_only: bool,
+    /// prefix lines with the episode parsed from the video's filename
+    pub show_episode: bool,
+    /// applied to the text that's shown and searched (and clips are named by)
+    pub clean: CleanOptions,
+    /// leave out subtitles starting within this many ms of the start of the
+    /// video
+    pub skip_intro: u64,
+    /// leave out subtitles starting this many ms before the end of the video,
+    /// which needs to probe it
+    pub skip_outro: u64,
+    /// merge subtitles of a stream stacked on each other, see
+    /// `merge_overlapping()`
+    pub merge_overlapping: bool,
+    /// shown, searched and clipped with these times instead of the stored ones
+    pub fps_correction: Option<FpsCorrection>,
+    /// ms to shift subtitles by (after `fps_correction`), see
+    /// `Subtitle::shifted()`
+    pub sub_offset: i64,
+    /// show (and search) lines of the original language with the translation
+    /// of the same time, instead of the translation's lines on their own
+    pub pair_langs: Option<LangPair>,
+    /// only search one stream per video, `None` for all
+    pub prefer_stream: Option<StreamPreference>,
+    /// fill in `SearchLine::text`
+    pub keep_text: bool,
+}
+
+/// Which sub stream of a video `SearchOptions::prefer_stream` searches, e.g.
+/// to leave out signs-only and forced streams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum StreamPreference {
+    /// the one with the most lines, i.e. the most complete
+    MostLines,
+    /// the one flagged as default in the video, else the one with the most
+    /// lines
+    Default,
+}
+
+/// A language like `en`, `jpn` or `pt-BR`, as tagged on sub streams and
+/// suffixed to sub files. At most 8 bytes, so it's `Copy` like `SearchOptions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Language {
+    tag: [u8; 8],
+    len: u8,
+}
+
+/// ISO 639-1 codes with their 639-2 ones (bibliographic too), for
+/// `Language::matches()`. Just the common subtitle languages.
+static ISO_639: &[&[&str]] = &[
+    &["ar", "ara"],
+    &["cs", "ces", "cze"],
+    &["da", "dan"],
+    &["de", "deu", "ger"],
+    &["el", "ell", "gre"],
+    &["en", "eng"],
+    &["es", "spa"],
+    &["fi", "fin"],
+    &["fr", "fra", "fre"],
+    &["he", "heb"],
+    &["hi", "hin"],
+    &["hu", "hun"],
+    &["id", "ind"],
+    &["it", "ita"],
+    &["ja", "jpn"],
+    &["ko", "kor"],
+    &["nl", "nld", "dut"],
+    &["no", "nor"],
+    &["pl", "pol"],
+    &["pt", "por"],
+    &["ru", "rus"],
+    &["sv", "swe"],
+    &["th", "tha"],
+    &["tr", "tur"],
+    &["uk", "ukr"],
+    &["vi", "vie"],
+    &["zh", "zho", "chi"],
+];
+
+impl Language {
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.tag[..usize::from(self.len)]).expect("[ASSERT] ASCII")
+    }
+
+    /// Whether `tag` is this language: the same code (ignoring case and
+    /// regions like `-BR`), or the 2- and 3-letter codes of one language, like
+    /// `ja` and `jpn`.
+    pub fn matches(self, tag: &str) -> bool {
+        let primary = |tag: &str| {
+            tag.split(['-', '_'])
+                .next()
+                .unwrap_or_default()
+                .to_ascii_lowercase()
+        };
+        let (this, other) = (primary(self.as_str()), primary(tag));
+        this == other
+            || ISO_639
+                .iter()
+                .any(|codes| codes.contains(&this.as_str()) && codes.contains(&other.as_str()))
+    }
+}
+
+impl std::str::FromStr for Language {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut tag = [0; 8];
+        anyhow::ensure!(
+            !s.is_empty()
+                && s.len() <= tag.len()
+                && s.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-'),
+            "invalid language {s:?}, expected a code like `en` or `jpn`"
+        );
+        tag[..s.len()].copy_from_slice(s.as_bytes());
+        Ok(Self {
+            tag,
+            len: u8::try_from(s.len()).expect("[ASSERT] checked above"),
+        })
+    }
+}
+
+/// Languages of an original and a translation, see
+/// `SearchOptions::pair_langs`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LangPair {
+    pub original: Language,
+    pub translation: Language,
+}
+
+impl std::str::FromStr for LangPair {
+    type Err = anyhow::Error;
+
+    /// `ja:en`
+    fn from_str(s: &str) -> Result<Self> {
+        let (original, translation) = s
+            .split_once(':')
+            .context("expected `<original>:<translation>`, e.g. `ja:en`")?;
+        Ok(Self {
+            original: original.parse()?,
+            translation: translation.parse()?,
+        })
+    }
+}
+
+/// Rescales subtitle times authored for one frame rate to a video sped up or
+/// slowed down to another, e.g. 23.976 fps subs on a 25 fps PAL video.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FpsCorrection {
+    /// source over target frame rate, as a fraction
+    num: u64,
+    den: u64,
+}
+
+impl FpsCorrection {
+    /// From subtitles for `source` to a video at `target`, both in frames per
+    /// second as `(numerator, denominator)`.
+    pub fn new(source: (u64, u64), target: (u64, u64)) -> Option<Self> {
+        let num = source.0.checked_mul(target.1)?;
+        let den = source.1.checked_mul(target.0)?;
+        (num != 0 && den != 0).then_some(Self { num, den })
+    }
+
+    /// `millis` in the video, rounded to the nearest ms.
+    pub fn scale(self, millis: u64) -> u64 {
+        let (num, den) = (u128::from(self.num), u128::from(self.den));
+        u64::try_from((u128::from(millis) * num + den / 2) / den).unwrap_or(u64::MAX)
+    }
+
+    /// `sub` with its times scaled. Times beyond what SRT can hold stay as
+    /// they are.
+    pub fn apply(self, sub: &Subtitle) -> Subtitle {
+        let scale = |t: srtlib::Timestamp| {
+            ffmpeg::millis_to_timestamp(self.scale(ffmpeg::timestamp_to_millis(t))).unwrap_or(t)
+        };
+        Subtitle(srtlib::Subtitle {
+            start_time: scale(sub.0.start_time),
+            end_time: scale(sub.0.end_time),
+            ..sub.0.clone()
+        })
+    }
+}
+
+/// What `clean_text()` removes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct CleanOptions {
+    /// HTML-ish tags, like `<i>`
+    pub html: bool,
+    /// ASS/SSA override blocks, like `{\an8}`
+    pub tags: bool,
+    /// uppercase speaker labels at the start of lines, like `MAN:`
+    pub speakers: bool,
+}
+
+/// The values of `--strip`, see `CleanOptions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Strip {
+    /// HTML-ish tags, like `<i>`
+    Html,
+    /// ASS/SSA override blocks, like `{\an8}`
+    Tags,
+    /// uppercase speaker labels at the start of lines, like `MAN:`
+    Speakers,
+}
+
+impl FromIterator<Strip> for CleanOptions {
+    fn from_iter<T: IntoIterator<Item = Strip>>(iter: T) -> Self {
+        let mut opts = Self::default();
+        for strip in iter {
+            match strip {
+                Strip::Html => opts.html = true,
+                Strip::Tags => opts.tags = true,
+                Strip::Speakers => opts.speakers = true,
+            }
+        }
+        opts
+    }
+}
+
+static REGEX_EPISODE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(concat!(
+        r"(?i)(?:^|[^a-z0-9])s(\d{1,2}) ?e(\d{1,3})",
+        r"|\b(\d{1,2})x(\d{2,3})\b",
+        r"|\b(\d{4})[-. ](\d{2})[-. ](\d{2})\b",
+    ))
+    .expect("valid regex")
+});
+
+/// Episode from the filename of `video`, like `S01E04` (also from `1x04`) or
+/// `2021-03-04` for daily shows.
+pub fn episode_tag(video: &Path) -> Option<String> {
+    let stem = video.file_stem()?.to_string_lossy();
+    let caps = REGEX_EPISODE.captures(&stem)?;
+    let num = |i: usize| caps.get(i).and_then(|m| m.as_str().parse::<u32>().ok());
+    if let (Some(season), Some(episode)) = (num(1).or(num(3)), num(2).or(num(4))) {
+        return Some(format!("S{season:02}E{episode:02}"));
+    }
+    Some(format!("{}-{}-{}", &caps[5], &caps[6], &caps[7]))
+}
+
+/// How the start of a subtitle is rendered in clip filenames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum TimestampFormat {
+    /// `00:01:12,340` (colons get escaped)
     #[default]
-    None,
+    Hms,
+    /// `1m12s340`
+    Compact,
+    /// `72.340`
+    Seconds,
+    /// frame number, needs the video's frame rate
+    Frames,
+}
+
+impl TimestampFormat {
+    /// Falls back to `Hms` for `Frames` without `fps`.
+    fn render(self, t: srtlib::Timestamp, fps: Option<ffmpeg::FrameRate>) -> String {
+        let millis = ffmpeg::timestamp_to_millis(t);
+        let (h, m, s, ms) = (
+            millis / 3_600_000,
+            millis / 60_000 % 60,
+            millis / 1000 % 60,
+            millis % 1000,
+        );
+        match (self, fps) {
+            (Self::Compact, _) if h > 0 => format!("{h}h{m:02}m{s:02}s{ms:03}"),
+            (Self::Compact, _) => format!("{m}m{s:02}s{ms:03}"),
+            (Self::Seconds, _) => format!("{}.{ms:03}", millis / 1000),
+            (Self::Frames, Some(fps)) => fps.frame_at(millis).to_string(),
+            (Self::Hms | Self::Frames, _) => t.to_string(),
+        }
+    }
 }
 
 impl Subtitle {
+    /// The line as shown and searched: its text, `translation` (see
+    /// `SearchOptions::pair_langs`), start, `stream` and `path`. Clip
+    /// filenames come from `template::OutputTemplate` instead.
     pub fn as_identifying_string(
         &self,
         path: impl AsRef<Path>,
-        format_opts: SubtitleStringFormatOptions,
+        stream: Option<&db::SubPath>,
+        translation: Option<&str>,
+        clean: CleanOptions,
     ) -> String {
-        #[allow(clippy::enum_glob_use)]
-        use SubtitleStringFormatOptions::*;
-        let line_len = if format_opts == Filename {
-            CLIP_FILENAME_TEXT_LEN
-        } else {
-            usize::MAX
+        let line = clean_text(&self.0.text, clean);
+        let stream_tag = stream
+            .map(|stream| format!("{} ", stream.tag()))
+            .unwrap_or_default();
+        let translation = translation
+            .map(|translation| format!(" ⟨{translation}⟩"))
+            .unwrap_or_default();
+        // matching strings stay the same across platforms
+        util::escape_for_filename(
+            &format!(
+                "{line}{translation} [{timestamp}] {stream_tag}({path})",
+                timestamp = self.0.start_time,
+                path = path.as_ref().to_string_lossy(),
+            ),
+            util::FilenameTarget::Unix,
+        )
+    }
+
+    /// The cleaned text cut to `text_len` bytes (see
+    /// `util::truncate_at_word()`) and the start, as they go into clip
+    /// filenames before escaping.
+    pub fn filename_parts(
+        &self,
+        timestamp: TimestampFormat,
+        fps: Option<ffmpeg::FrameRate>,
+        text_len: usize,
+        clean: CleanOptions,
+    ) -> (String, String) {
+        let line = clean_text(&self.0.text, clean);
+        (
+            util::truncate_at_word(&line, text_len).into_owned(),
+            timestamp.render(self.0.start_time, fps),
+        )
+    }
+}
+
+impl Subtitle {
+    /// One subtitle from the earliest start to the latest end of `subs`, with
+    /// their lines joined.
+    pub fn spanning<'a>(num: usize, subs: impl IntoIterator<Item = &'a Subtitle>) -> Option<Self> {
+        let subs = subs.into_iter().collect_vec();
+        let start = subs.iter().map(|sub| sub.0.start_time).min()?;
+        let end = subs.iter().map(|sub| sub.0.end_time).max()?;
+        let text = subs.iter().map(|sub| sub.0.text.as_str()).join("\n");
+        Some(Self(srtlib::Subtitle::new(num, start, end, text)))
+    }
+}
+
+/// `subs` ordered by start, with those overlapping by more than half of the
+/// shorter one merged into one (see `Subtitle::spanning()`), e.g. a sign
+/// translation stacked on dialogue. Unmerged ones stay borrowed.
+pub fn merge_overlapping(subs: &[Subtitle]) -> Vec<Cow<'_, Subtitle>> {
+    // with the latest end of each group
+    let mut groups: Vec<(Duration, Vec<&Subtitle>)> = Vec::new();
+    for sub in subs.iter().sorted_by_key(|sub| sub.start()) {
+        let stacked = |end: Duration, first: &Subtitle| {
+            let overlap = end.min(sub.end()).saturating_sub(sub.start());
+            let shorter = end.saturating_sub(first.start()).min(sub.duration());
+            overlap * 2 > shorter
+        };
+        match groups.last_mut() {
+            Some((end, group)) if stacked(*end, group[0]) => {
+                *end = (*end).max(sub.end());
+                group.push(sub);
+            }
+            _ => groups.push((sub.end(), vec![sub])),
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(_, group)| match group[..] {
+            [sub] => Cow::Borrowed(sub),
+            _ => Cow::Owned(
+                Subtitle::spanning(group[0].num, group).expect("[ASSERT] groups aren't empty"),
+            ),
+        })
+        .collect()
+}
+
+impl From<srtlib::Subtitle> for Subtitle {
+    fn from(sub: srtlib::Subtitle) -> Self {
+        Self(sub)
+    }
+}
+
+static REGEX_HTML: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>").expect("valid regex"));
+static REGEX_ASS_TAGS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\\[^}]*\}").expect("valid regex"));
+/// Keeps a leading dialogue dash (group 1). Needs two uppercase letters, so
+/// `I: …` stays.
+static REGEX_SPEAKER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?m)^([ \t]*(?:-[ \t]*)?)\p{Lu}[\p{Lu}\d .'’&-]*\p{Lu}(?:[ \t]*\([^)\n]*\))?[ \t]*:[ \t]*")
+        .expect("valid regex")
+});
+
+/// `text` without what `opts` call for, e.g. for searching and filenames.
+/// Otherwise untouched, including line breaks.
+pub fn clean_text(text: &str, opts: CleanOptions) -> String {
+    let mut text = Cow::Borrowed(text);
+    if opts.html {
+        text = Cow::Owned(REGEX_HTML.replace_all(&text, "").into_owned());
+    }
+    if opts.tags {
+        text = Cow::Owned(REGEX_ASS_TAGS.replace_all(&text, "").into_owned());
+    }
+    if opts.speakers {
+        text = Cow::Owned(REGEX_SPEAKER.replace_all(&text, "$1").into_owned());
+    }
+    text.into_owned()
+}
+
+impl Subtitle {
+    /// The dialogue on one line, without HTML-ish tags (`<i>`) and ASS override
+    /// blocks (`{\an8}`).
+    pub fn plain_text(&self) -> String {
+        let markup = CleanOptions {
+            html: true,
+            tags: true,
+            speakers: false,
         };
+        clean_text(&self.0.text, markup)
+            .split_whitespace()
+            .join(" ")
+    }
 
-        let path_len = if format_opts == Filename {
-            CLIP_FILENAME_PATH_LEN
+    /// The key the finder matches against instead of the identifying string,
+    /// if `opts` call for one. Includes `translation`, if any.
+    fn search_key(&self, opts: SearchOptions, translation: Option<&str>) -> Option<String> {
+        let text = match opts {
+            SearchOptions {
+                text_only: true, ..
+            } => self.dialogue(opts, translation),
+            SearchOptions { fold: true, .. } => {
+                let text = clean_text(&self.0.text, opts.clean);
+                match translation {
+                    Some(translation) => format!("{text} {translation}"),
+                    None => text,
+                }
+            }
+            SearchOptions { .. } => return None,
+        };
+        Some(if opts.fold {
+            util::fold_for_search(&text)
         } else {
-            usize::MAX
+            text
+        })
+    }
+
+    /// Just the words of the line (and its `translation`), without markup or
+    /// line breaks.
+    fn dialogue(&self, opts: SearchOptions, translation: Option<&str>) -> String {
+        let clean = CleanOptions {
+            html: true,
+            tags: true,
+            ..opts.clean
         };
+        let text = clean_text(&self.0.text, clean).split_whitespace().join(" ");
+        match translation {
+            Some(translation) => format!("{text} {translation}"),
+            None => text,
+        }
+    }
 
-        util::escape_for_unix_filename(&format!(
-            "{line:.line_len$} [{timestamp}] ({path:.path_len$})",
-            line = &self.0.text,
-            line_len = line_len,
-            timestamp = self.0.start_time,
-            path = path.as_ref().to_string_lossy(),
-            path_len = path_len,
-        ))
+    /// Offset of the subtitle's start from the beginning of the video.
+    pub fn start(&self) -> Duration {
+        Duration::from_millis(ffmpeg::timestamp_to_millis(self.0.start_time))
+    }
+
+    /// Offset of the subtitle's end from the beginning of the video.
+    pub fn end(&self) -> Duration {
+        Duration::from_millis(ffmpeg::timestamp_to_millis(self.0.end_time))
+    }
+
+    /// `self` moved `offset_ms` later (earlier if negative), stopping at the
+    /// beginning of the video.
+    pub fn shifted(&self, offset_ms: i64) -> Self {
+        let shift = |t: srtlib::Timestamp| {
+            let millis = ffmpeg::timestamp_to_millis(t).saturating_add_signed(offset_ms);
+            ffmpeg::millis_to_timestamp(millis).unwrap_or(t)
+        };
+        Subtitle(srtlib::Subtitle {
+            start_time: shift(self.0.start_time),
+            end_time: shift(self.0.end_time),
+            ..self.0.clone()
+        })
+    }
+
+    /// Whether `self` and `other` are shown at about the same time, e.g. a line
+    /// and its translation: for at least half of the shorter one.
+    pub fn is_aligned(&self, other: &Self) -> bool {
+        let overlap = self
+            .end()
+            .min(other.end())
+            .saturating_sub(self.start().max(other.start()));
+        !overlap.is_zero() && overlap * 2 >= self.duration().min(other.duration())
+    }
+
+    /// How long the subtitle is shown (zero if it ends before it starts).
+    pub fn duration(&self) -> Duration {
+        self.end().saturating_sub(self.start())
+    }
+
+    /// Writes `subs` to an SRT file at `path`, numbered in the given order but
+    /// keeping their times.
+    pub fn write_srt<'a>(subs: impl IntoIterator<Item = &'a Self>, path: &Path) -> Result<()> {
+        let subs = subs
+            .into_iter()
+            .enumerate()
+            .map(|(i, sub)| srtlib::Subtitle {
+                num: i + 1,
+                ..sub.0.clone()
+            })
+            .collect();
+        srtlib::Subtitles::new_from_vec(subs)
+            .write_to_file(path, None)
+            .with_context(|| format!("writing {path:?}"))
+    }
+
+    /// Writes `self` as the only entry of an SRT file at `path`, shifted
+    /// `offset_ms` earlier (e.g. to line up with a clip starting there).
+    pub fn write_shifted_srt(&self, offset_ms: u64, path: impl AsRef<Path>) -> Result<()> {
+        let shift = |ts| {
+            ffmpeg::millis_to_timestamp(ffmpeg::timestamp_to_millis(ts).saturating_sub(offset_ms))
+        };
+        let shifted = srtlib::Subtitle::new(
+            1,
+            shift(self.0.start_time)?,
+            shift(self.0.end_time)?,
+            self.0.text.clone(),
+        );
+        srtlib::Subtitles::new_from_vec(vec![shifted])
+            .write_to_file(&path, None)
+            .with_context(|| format!("writing {:?}", path.as_ref()))
     }
 }
 
-pub fn parse_from_file(path: impl AsRef<Path>) -> Result<Subtitles> {
-    // TODO maybe convert non-UTF8 charsets with crates `encoding_rs` and `chardetng`
+/// Subtitle files next to `video` sharing its name, like `movie.srt` or
+/// `movie.en.srt`, along with their language suffix (if any).
+fn sibling_sub_files(video: &Path) -> Result<Vec<(PathBuf, Option<String>)>> {
+    let (Some(dir), Some(stem)) = (video.parent(), video.file_stem()) else {
+        return Ok(Vec::new());
+    };
+    let pattern = Regex::new(&format!(
+        "^{stem}{suffix}$",
+        stem = regex::escape(&stem.to_string_lossy()),
+        suffix = crate::REGEX_SUBFILE,
+    ))?;
+
+    let mut siblings = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        let Some(name) = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+        else {
+            continue;
+        };
+        if let Some(captures) = pattern.captures(&name) {
+            let language = captures.get(1).map(|lang| lang.as_str().to_owned());
+            siblings.push((path, language));
+        }
+    }
+    siblings.sort();
+    Ok(siblings)
+}
+
+/// `VobSub` pairs next to `video` (`movie.idx` with `movie.sub`, optionally
+/// with a language suffix like sibling SRTs).
+fn sibling_vobsubs(video: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(stem)) = (video.parent(), video.file_stem()) else {
+        return Vec::new();
+    };
+    let pattern = Regex::new(&format!(
+        r"^{stem}(?:\.\w{{2,3}})?\.idx$",
+        stem = regex::escape(&stem.to_string_lossy()),
+    ))
+    .expect("[ASSERT] valid regex");
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|name| pattern.is_match(&name.to_string_lossy()))
+                && path.with_extension("sub").is_file()
+        })
+        .sorted()
+        .collect()
+}
+
+/// The errors noting `video`'s `VobSub` files, which aren't indexed: OCR
+/// (`--ocr`) renders subs onto the video's frames, which a separate `.idx`
+/// doesn't have. The `.sub` halves aren't mistaken for text subs either, see
+/// `ffmpeg::SUB_EXTS`.
+fn vobsub_errors(video: &Path) -> Vec<String> {
+    sibling_vobsubs(video)
+        .into_iter()
+        .map(|idx| {
+            format!(
+                "{idx:?} is a VobSub file, which isn't read, mux it into the video and pass --ocr"
+            )
+        })
+        .collect()
+}
+
+/// The sub files indexed along with `video`'s streams: its siblings and the
+/// ones its `overrides` add.
+fn sub_files(
+    video: &Path,
+    overrides: &crate::overrides::Overrides,
+) -> Result<Vec<(PathBuf, Option<String>)>> {
+    let mut files = sibling_sub_files(video)?;
+    files.retain(|(path, _)| !overrides.subs.contains(path));
+    files.extend(
+        overrides
+            .subs
+            .iter()
+            .map(|path| (path.clone(), loose_sub_language(path))),
+    );
+    Ok(files)
+}
+
+/// Whether `path` is a subtitle file `--loose-subs` indexes.
+pub fn has_sub_ext(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        ffmpeg::SUB_EXTS
+            .iter()
+            .any(|sub_ext| ext.eq_ignore_ascii_case(sub_ext))
+    })
+}
+
+/// The language suffix of a subtitle file, like `en` in `movie.en.srt`.
+fn loose_sub_language(sub: &Path) -> Option<String> {
+    let stem = Path::new(sub.file_stem()?);
+    let language = stem.extension()?.to_str()?;
+    let is_language =
+        (2..=3).contains(&language.len()) && language.chars().all(char::is_alphanumeric);
+    is_language.then(|| language.to_owned())
+}
+
+/// The first video in `dirs` named like `sub`, with or without its language
+/// suffix (`movie.mkv` for `movie.en.srt`).
+pub fn loose_sub_video<'a>(
+    sub: &Path,
+    dirs: impl IntoIterator<Item = &'a Path>,
+) -> Option<PathBuf> {
+    let stem = Path::new(sub.file_stem()?);
+    let stems = loose_sub_language(sub)
+        .and_then(|_| stem.file_stem())
+        .into_iter()
+        .chain([stem.as_os_str()])
+        .collect_vec();
+    dirs.into_iter()
+        .flat_map(|dir| {
+            stems.iter().flat_map(move |stem| {
+                ffmpeg::VIDEO_EXTS.iter().map(move |ext| {
+                    let mut name = stem.to_os_string();
+                    name.push(".");
+                    name.push(ext);
+                    dir.join(name)
+                })
+            })
+        })
+        .find(|video| video.is_file())
+}
+
+/// How videos get turned into DB entries.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct IndexOptions {
+    pub parsing: SubParsing,
+    /// keep entries of missing videos (marked stale) instead of removing them
+    pub keep_missing: bool,
+    /// index videos with the same content only once, see `Metadata::alias_of`
+    pub dedup: bool,
+    /// OCR image-based sub streams instead of skipping them
+    pub ocr: bool,
+    /// where sub streams get extracted to when they need to be files (for OCR
+    /// and `extract_cache`), instead of the system's temp dir
+    pub temp_dir: Option<PathBuf>,
+    /// videos to re-index even if they seem unchanged
+    pub rescan: Rescan,
+    /// where extracted sub streams are kept for re-indexing, see
+    /// `ExtractCache`
+    pub extract_cache: Option<PathBuf>,
+    /// where newly indexed videos get a `ffmpeg::contact_sheet()`
+    pub thumbnails: Option<PathBuf>,
+    /// also index subtitle files without a video next to them, see
+    /// `Metadata::loose`
+    pub loose_subs: bool,
+    /// where videos of loose subtitle files are looked for, besides next to
+    /// them
+    pub video_dir: Option<PathBuf>,
+}
+
+/// Which videos get re-indexed even if `Entry::has_changed()` says they
+/// didn't, e.g. after fixing their subtitle files in place.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Rescan {
+    #[default]
+    None,
+    All,
+    /// videos whose full path matches any of these
+    Matching(Vec<util::Glob>),
+}
+
+impl Rescan {
+    pub fn includes(&self, video: &Path) -> bool {
+        match self {
+            Rescan::None => false,
+            Rescan::All => true,
+            Rescan::Matching(globs) => globs.iter().any(|glob| glob.is_match(video)),
+        }
+    }
+}
+
+/// How to deal with malformed entries in subtitle files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SubParsing {
+    /// fail the whole file
+    #[default]
+    Strict,
+    /// skip (and log) malformed entries, keeping the rest
+    Lenient,
+}
+
+pub fn parse_from_file(path: impl AsRef<Path>, parsing: SubParsing) -> Result<Subtitles> {
     let content =
         std::fs::read(&path).with_context(|| String::from(path.as_ref().to_string_lossy()))?;
-    let utf8_content = String::from_utf8_lossy(&content);
+    parse_from_bytes(&content, parsing, &format!("{:?}", path.as_ref()))
+}
+
+/// Parses SRT `content`, e.g. piped from ffmpeg. `origin` names where it's
+/// from in warnings.
+pub fn parse_from_bytes(content: &[u8], parsing: SubParsing, origin: &str) -> Result<Subtitles> {
+    // TODO maybe convert non-UTF8 charsets with crates `encoding_rs` and `chardetng`
+    let utf8_content = String::from_utf8_lossy(content);
+
+    if parsing == SubParsing::Lenient {
+        return Ok(parse_lenient(&utf8_content, origin));
+    }
 
     Ok(srtlib::Subtitles::parse_from_str(utf8_content.into_owned())
         .map_err(Into::<anyhow::Error>::into)?
@@ -455,6 +3015,33 @@ pub fn parse_from_file(path: impl AsRef<Path>) -> Result<Subtitles> {
         .collect_vec())
 }
 
+/// Parses each blank line separated block of `content` on its own, like
+/// `srtlib::Subtitles::parse_from_str()` but logging and skipping bad blocks.
+fn parse_lenient(content: &str, origin: &str) -> Subtitles {
+    let content = content.trim_start_matches('\u{feff}').replace('\r', "");
+    let blocks = Regex::new(r"\n[ \t]*\n")
+        .expect("valid regex")
+        .split(&content)
+        // like srtlib, skip blocks without anything in them
+        .filter(|block| block.contains(char::is_alphanumeric))
+        .map(|block| block.trim_matches('\n'))
+        .collect_vec();
+
+    blocks
+        .iter()
+        .enumerate()
+        .filter_map(
+            |(i, block)| match srtlib::Subtitle::parse((*block).to_owned()) {
+                Ok(sub) => Some(Subtitle(sub)),
+                Err(e) => {
+                    warn!("Skipping malformed block #{n} in {origin}: {e}", n = i + 1);
+                    None
+                }
+            },
+        )
+        .collect()
+}
+
 pub(super) mod serde {
     pub(super) mod subtitle {
         use serde::{Deserialize, Serialize};
@@ -591,8 +3178,11 @@ pub(super) mod serde {
 #[cfg(test)]
 mod test {
 
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
     use std::sync::LazyLock;
+    use std::time::Duration;
+
+    use super::SubParsing;
 
     static TEST_SUB: LazyLock<PathBuf> = LazyLock::new(|| {
         [env!("CARGO_MANIFEST_DIR"), "test", "gem_glow.srt"]
@@ -602,11 +3192,397 @@ mod test {
 
     #[test]
     fn parse() {
-        let result = super::parse_from_file(TEST_SUB.as_path()).unwrap();
+        let result = super::parse_from_file(TEST_SUB.as_path(), SubParsing::Strict).unwrap();
         insta::assert_debug_snapshot!(result);
     }
+
+    #[test]
+    fn parse_from_bytes() {
+        let content = std::fs::read(TEST_SUB.as_path()).unwrap();
+        assert_eq!(
+            super::parse_from_bytes(&content, SubParsing::Strict, "test").unwrap(),
+            super::parse_from_file(TEST_SUB.as_path(), SubParsing::Strict).unwrap()
+        );
+        assert!(super::parse_from_bytes(b"1\nnonsense\n", SubParsing::Strict, "test").is_err());
+        assert!(
+            super::parse_from_bytes(b"1\nnonsense\n", SubParsing::Lenient, "test")
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn parse_lenient() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("broken.srt");
+        std::fs::write(
+            &path,
+            "1\r\n00:00:01,000 --> 00:00:02,000\r\nFirst\r\n\r\n\
+             two\n00:00:03,000 --> 00:00:04,000\nBroken index\n\n\
+             3\n00:00:05,000 --> nonsense\nBroken time\n \n\
+             4\n00:00:07,000 --> 00:00:08,000\nLast\nline\n",
+        )
+        .unwrap();
+
+        assert!(super::parse_from_file(&path, SubParsing::Strict).is_err());
+        let texts = super::parse_from_file(&path, SubParsing::Lenient)
+            .unwrap()
+            .into_iter()
+            .map(|sub| sub.0.text)
+            .collect::<Vec<_>>();
+        assert_eq!(texts, ["First", "Last\nline"]);
+    }
+
+    #[test]
+    fn plain_text() {
+        let sub = |text: &str| -> super::Subtitle {
+            let ts = srtlib::Timestamp::new(0, 0, 0, 0);
+            srtlib::Subtitle::new(1, ts, ts, text.to_owned()).into()
+        };
+        assert_eq!(
+            sub("<i>♪ We are\nthe Crystal Gems ♪</i>").plain_text(),
+            "♪ We are the Crystal Gems ♪"
+        );
+        assert_eq!(
+            sub("{\\an8}<font color=\"red\">Hi</font>").plain_text(),
+            "Hi"
+        );
+    }
+
+    #[test]
+    fn clean_text() {
+        use super::{CleanOptions, Strip};
+
+        let clean = |text: &str, strip: &[Strip]| {
+            super::clean_text(text, strip.iter().copied().collect::<CleanOptions>())
+        };
+        let text = "{\\an8}<i>MAN (ON PHONE): Hi.</i>\n- WOMAN: Who's there?\n- I: me. At 10:30.";
+        assert_eq!(clean(text, &[]), text);
+        assert_eq!(
+            clean(text, &[Strip::Html]),
+            "{\\an8}MAN (ON PHONE): Hi.\n- WOMAN: Who's there?\n- I: me. At 10:30."
+        );
+        assert_eq!(
+            clean(text, &[Strip::Html, Strip::Tags, Strip::Speakers]),
+            "Hi.\n- Who's there?\n- I: me. At 10:30."
+        );
+        // the first speaker label is behind the tags
+        assert_eq!(
+            clean(text, &[Strip::Speakers]),
+            "{\\an8}<i>MAN (ON PHONE): Hi.</i>\n- Who's there?\n- I: me. At 10:30."
+        );
+        assert_eq!(clean("DR. O'NEIL: Hello", &[Strip::Speakers]), "Hello");
+        assert_eq!(
+            clean("Note: lowercase", &[Strip::Speakers]),
+            "Note: lowercase"
+        );
+    }
+
+    #[test]
+    fn timing() {
+        let subs = super::parse_from_file(TEST_SUB.as_path(), SubParsing::Strict).unwrap();
+        let first = &subs[0];
+        assert_eq!(first.start(), Duration::from_millis(200));
+        assert_eq!(first.end(), Duration::from_millis(3598));
+        assert_eq!(first.duration(), Duration::from_millis(3398));
+        assert!(subs
+            .iter()
+            .all(|sub| sub.start() + sub.duration() == sub.end()));
+    }
+
+    #[test]
+    fn timestamp_format() {
+        use super::TimestampFormat::{Compact, Frames, Hms, Seconds};
+
+        let ts = srtlib::Timestamp::new(0, 1, 12, 340);
+        let fps = crate::ffmpeg::FrameRate::parse("25");
+        assert_eq!(Hms.render(ts, None), "00:01:12,340");
+        assert_eq!(Compact.render(ts, None), "1m12s340");
+        assert_eq!(
+            Compact.render(srtlib::Timestamp::new(2, 3, 4, 5), None),
+            "2h03m04s005"
+        );
+        assert_eq!(Seconds.render(ts, None), "72.340");
+        assert_eq!(Frames.render(ts, fps), "1808");
+        assert_eq!(Frames.render(ts, None), "00:01:12,340");
+
+        let sub: super::Subtitle = srtlib::Subtitle::new(1, ts, ts, "Hi there".to_owned()).into();
+        assert_eq!(
+            sub.filename_parts(Compact, None, 100, Default::default()),
+            ("Hi there".to_owned(), "1m12s340".to_owned())
+        );
+        assert_eq!(
+            sub.as_identifying_string("/v.mkv", None, None, Default::default()),
+            "Hi there [00_01_12,340] (_v.mkv)"
+        );
+    }
+
+    #[test]
+    fn language() {
+        use super::{LangPair, Language};
+
+        let language = |tag: &str| tag.parse::<Language>().unwrap();
+        assert!(language("ja").matches("jpn"));
+        assert!(language("JPN").matches("ja"));
+        assert!(language("ger").matches("de"));
+        assert!(language("pt").matches("pt-BR"));
+        assert!(language("und").matches("und"));
+        assert!(!language("en").matches("jpn"));
+        assert!(!language("de").matches("dan"));
+        assert_eq!(language("pt-BR").as_str(), "pt-BR");
+        for invalid in ["", "toolonglang", "e n"] {
+            assert!(invalid.parse::<Language>().is_err(), "{invalid}");
+        }
+
+        let pair = "ja:en".parse::<LangPair>().unwrap();
+        assert_eq!(
+            (pair.original.as_str(), pair.translation.as_str()),
+            ("ja", "en")
+        );
+        assert!("ja".parse::<LangPair>().is_err());
+    }
+
+    #[test]
+    fn is_aligned() {
+        let sub = |start: u32, end: u32| -> super::Subtitle {
+            let ts = |millis| crate::ffmpeg::millis_to_timestamp(u64::from(millis)).unwrap();
+            srtlib::Subtitle::new(1, ts(start), ts(end), String::new()).into()
+        };
+        assert!(sub(1000, 3000).is_aligned(&sub(1000, 3000)));
+        assert!(sub(1000, 3000).is_aligned(&sub(2000, 6000)));
+        assert!(sub(1000, 5000).is_aligned(&sub(2000, 2500)));
+        assert!(!sub(1000, 3000).is_aligned(&sub(2500, 6000)));
+        assert!(!sub(1000, 3000).is_aligned(&sub(3000, 4000)));
+    }
+
+    #[test]
+    fn spanning() {
+        let subs = super::parse_from_file(TEST_SUB.as_path(), SubParsing::Strict).unwrap();
+        let span = super::Subtitle::spanning(2, &subs[..3]).unwrap();
+        assert_eq!(span.num, 2);
+        assert_eq!(span.start(), subs[0].start());
+        assert_eq!(span.end(), subs[2].end());
+        assert_eq!(
+            span.text.lines().count(),
+            subs[..3]
+                .iter()
+                .map(|sub| sub.text.lines().count())
+                .sum::<usize>()
+        );
+        assert_eq!(super::Subtitle::spanning(1, []), None);
+    }
+
+    #[test]
+    fn episode_tag() {
+        let tag = |path: &str| super::episode_tag(Path::new(path));
+        assert_eq!(tag("/tv/Show.S01E04.1080p.mkv").as_deref(), Some("S01E04"));
+        assert_eq!(tag("Show_s3e9.mkv").as_deref(), Some("S03E09"));
+        assert_eq!(tag("Show - 2x10 - Title.mkv").as_deref(), Some("S02E10"));
+        assert_eq!(tag("Daily 2021.03.04.mp4").as_deref(), Some("2021-03-04"));
+        assert_eq!(tag("Movie 1920x1080.mkv"), None);
+        assert_eq!(tag("Movie.mkv"), None);
+    }
+
+    #[test]
+    fn sibling_sub_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let touch = |name: &str| std::fs::write(temp_dir.path().join(name), "").unwrap();
+        for name in [
+            "movie.mkv",
+            "movie.srt",
+            "movie.en.srt",
+            "movie.eng.srt",
+            "movie 2.srt",
+            "movie.en.ass",
+            "other.srt",
+        ] {
+            touch(name);
+        }
+
+        let siblings = super::sibling_sub_files(&temp_dir.path().join("movie.mkv")).unwrap();
+        let names = siblings
+            .iter()
+            .map(|(path, language)| {
+                (
+                    path.file_name().unwrap().to_str().unwrap(),
+                    language.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                ("movie.en.srt", Some("en")),
+                ("movie.eng.srt", Some("eng")),
+                ("movie.srt", None)
+            ]
+        );
+    }
+
+    #[test]
+    fn vobsub_errors() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let touch = |name: &str| std::fs::write(temp_dir.path().join(name), "").unwrap();
+        for name in [
+            "movie.mkv",
+            "movie.idx",
+            "movie.sub",
+            "movie.en.idx",
+            "movie.en.sub",
+            // without its .sub
+            "movie.fr.idx",
+            "other.idx",
+            "other.sub",
+        ] {
+            touch(name);
+        }
+
+        let video = temp_dir.path().join("movie.mkv");
+        assert_eq!(
+            super::sibling_vobsubs(&video),
+            [
+                temp_dir.path().join("movie.en.idx"),
+                temp_dir.path().join("movie.idx")
+            ]
+        );
+        let errors = super::vobsub_errors(&video);
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].contains("movie.en.idx") && errors[0].contains("--ocr"));
+        assert!(super::vobsub_errors(&temp_dir.path().join("none.mkv")).is_empty());
+        assert!(!super::has_sub_ext(&temp_dir.path().join("movie.sub")));
+    }
+
+    #[test]
+    fn loose_sub_video() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let (subs, videos) = (temp_dir.path().join("subs"), temp_dir.path().join("videos"));
+        std::fs::create_dir_all(&subs).unwrap();
+        std::fs::create_dir_all(&videos).unwrap();
+        for video in ["movie.mkv", "show.s01e01.mp4"] {
+            std::fs::write(videos.join(video), "").unwrap();
+        }
+        let video = |sub: &str| {
+            super::loose_sub_video(&subs.join(sub), [videos.as_path(), subs.as_path()])
+                .map(|video| video.strip_prefix(&videos).unwrap().to_owned())
+        };
+        assert_eq!(video("movie.srt"), Some("movie.mkv".into()));
+        assert_eq!(video("movie.en.vtt"), Some("movie.mkv".into()));
+        assert_eq!(video("show.s01e01.ass"), Some("show.s01e01.mp4".into()));
+        assert_eq!(video("other.srt"), None);
+        assert_eq!(
+            super::loose_sub_language(Path::new("movie.en.srt")),
+            Some("en".into())
+        );
+        assert_eq!(super::loose_sub_language(Path::new("movie.srt")), None);
+        assert_eq!(
+            super::loose_sub_language(Path::new("show.s01e01.srt")),
+            None
+        );
+    }
+
+    #[test]
+    fn write_shifted_srt() {
+        let subs = super::parse_from_file(TEST_SUB.as_path(), SubParsing::Strict).unwrap();
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("clip.srt");
+
+        // 00:00:03,699 --> 00:00:06,220, as if clipped with 500ms lead-in
+        subs[1].write_shifted_srt(3199, &path).unwrap();
+
+        let written = super::parse_from_file(&path, SubParsing::Strict).unwrap();
+        assert_eq!(written.len(), 1);
+        assert_eq!(written[0].num, 1);
+        assert_eq!(written[0].start_time, srtlib::Timestamp::new(0, 0, 0, 500));
+        assert_eq!(written[0].end_time, srtlib::Timestamp::new(0, 0, 3, 21));
+        assert_eq!(written[0].text, subs[1].text);
+    }
+
+    #[test]
+    fn merge_overlapping() {
+        let sub = |num, start: u16, end: u16, text: &str| -> super::Subtitle {
+            srtlib::Subtitle::new(
+                num,
+                srtlib::Timestamp::new(0, 0, 0, start),
+                srtlib::Timestamp::new(0, 0, 0, end),
+                text.to_owned(),
+            )
+            .into()
+        };
+        let subs = [
+            sub(1, 0, 400, "Dialogue"),
+            sub(2, 100, 300, "SIGN"),
+            // only grazes the merged one
+            sub(3, 350, 800, "Next"),
+            sub(4, 900, 950, "Last"),
+        ];
+
+        let merged = super::merge_overlapping(&subs);
+        assert_eq!(merged.len(), 3);
+        assert_eq!(*merged[0], sub(1, 0, 400, "Dialogue\nSIGN"));
+        assert!(matches!(merged[1], std::borrow::Cow::Borrowed(next) if *next == subs[2]));
+        assert_eq!(*merged[2], subs[3]);
+    }
+
+    #[test]
+    fn fps_correction() {
+        let pal = super::FpsCorrection::new((24000, 1001), (25, 1)).unwrap();
+        // an hour of NTSC film runs ~2.4 minutes shorter sped up to PAL
+        assert_eq!(pal.scale(3_600_000), 3_452_547);
+        assert_eq!(pal.scale(0), 0);
+        let back = super::FpsCorrection::new((25, 1), (24000, 1001)).unwrap();
+        assert_eq!(back.scale(pal.scale(3_600_000)), 3_600_000);
+        assert_eq!(super::FpsCorrection::new((0, 1), (25, 1)), None);
+
+        let sub: super::Subtitle = srtlib::Subtitle::new(
+            7,
+            srtlib::Timestamp::new(1, 0, 0, 0),
+            srtlib::Timestamp::new(1, 0, 2, 500),
+            "Hi".to_owned(),
+        )
+        .into();
+        let scaled = pal.apply(&sub);
+        assert_eq!(scaled.start_time, srtlib::Timestamp::new(0, 57, 32, 547));
+        assert_eq!(scaled.end_time, srtlib::Timestamp::new(0, 57, 34, 945));
+        assert_eq!((scaled.num, scaled.text.as_str()), (7, "Hi"));
+    }
+
+    #[test]
+    fn shifted() {
+        let sub: super::Subtitle = srtlib::Subtitle::new(
+            7,
+            srtlib::Timestamp::new(0, 0, 1, 0),
+            srtlib::Timestamp::new(0, 0, 2, 500),
+            "Hi".to_owned(),
+        )
+        .into();
+        let later = sub.shifted(1200);
+        assert_eq!(later.start_time, srtlib::Timestamp::new(0, 0, 2, 200));
+        assert_eq!(later.end_time, srtlib::Timestamp::new(0, 0, 3, 700));
+        assert_eq!((later.num, later.text.as_str()), (7, "Hi"));
+        let earlier = sub.shifted(-1500);
+        assert_eq!(earlier.start_time, srtlib::Timestamp::new(0, 0, 0, 0));
+        assert_eq!(earlier.end_time, srtlib::Timestamp::new(0, 0, 1, 0));
+        assert_eq!(sub.shifted(0), sub);
+    }
+
+    #[test]
+    fn write_srt() {
+        let subs = super::parse_from_file(TEST_SUB.as_path(), SubParsing::Strict).unwrap();
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("window.srt");
+
+        super::Subtitle::write_srt([&subs[0], &subs[2]], &path).unwrap();
+
+        let written = super::parse_from_file(&path, SubParsing::Strict).unwrap();
+        assert_eq!(written.len(), 2);
+        assert_eq!((written[0].num, written[1].num), (1, 2));
+        assert_eq!(written[1].start_time, subs[2].start_time);
+        assert_eq!(written[1].end_time, subs[2].end_time);
+        assert_eq!(written[1].text, subs[2].text);
+    }
 }
 
+#[allow(dead_code)]
 pub mod old {
     use std::{ops::Deref, path::Path};
 
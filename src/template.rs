@@ -0,0 +1,315 @@
+use std::{borrow::Cow, path::PathBuf, str::FromStr};
+
+use anyhow::{bail, Context as _, Error, Result};
+use itertools::Itertools as _;
+
+use crate::{ffmpeg, sub::db, util};
+
+/// Where a clip goes, relative to the clip dir, see `--output-template`.
+/// `/`s separate dirs, which get created as needed. Tokens:
+///
+/// - `{text}`: the subtitle's cleaned text, cut to `--filename-text-len`
+/// - `{start}`: its start, per `--timestamp-format`
+/// - `{num}`: its number in its stream
+/// - `{path}`: the source video's path, cut to fit the filename
+/// - `{video_stem}`: the source video's name without extension
+/// - `{lang}`: the subtitle stream's language, `und` if it's unknown
+/// - `{stream}`: the subtitle stream, like `#2` or `srt`
+/// - `{hash}`: see `--hash-suffix`
+/// - `{profile}`: the encoding profile
+/// - `{ext}`: the container's extension
+///
+/// It has to end with `.{ext}` and may start with `{clip_dir}/`. Token values
+/// are escaped like filenames, so they never add dirs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputTemplate {
+    /// of the path without the final `.{ext}`
+    components: Vec<Vec<Piece>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Literal(String),
+    Token(Token),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Text,
+    Start,
+    Num,
+    Path,
+    VideoStem,
+    Lang,
+    Stream,
+    Hash,
+    Profile,
+    Ext,
+}
+
+impl Token {
+    const ALL: [(&str, Token); 10] = [
+        ("text", Token::Text),
+        ("start", Token::Start),
+        ("num", Token::Num),
+        ("path", Token::Path),
+        ("video_stem", Token::VideoStem),
+        ("lang", Token::Lang),
+        ("stream", Token::Stream),
+        ("hash", Token::Hash),
+        ("profile", Token::Profile),
+        ("ext", Token::Ext),
+    ];
+}
+
+/// What the tokens of an `OutputTemplate` stand for, for one selected
+/// subtitle.
+pub struct Vars<'a> {
+    pub video: &'a std::path::Path,
+    pub stream: &'a db::SubPath,
+    pub sub_num: usize,
+    /// see `Subtitle::filename_parts()`
+    pub text: String,
+    pub start: String,
+    /// bytes of `video`'s path to keep
+    pub path_len: usize,
+    pub hash: String,
+}
+
+impl OutputTemplate {
+    /// The template of the layout picked by `--subdir-per-profile`,
+    /// `--subdir-per-source` and `--hash-suffix`.
+    pub fn from_flags(
+        subdir_per_profile: bool,
+        subdir_per_source: bool,
+        hash_suffix: bool,
+    ) -> Self {
+        let template = format!(
+            "{profile}{source}{{text}} [{{start}}] ({{path}}){hash}.{{ext}}",
+            profile = if subdir_per_profile { "{profile}/" } else { "" },
+            source = if subdir_per_source {
+                "{video_stem}/"
+            } else {
+                ""
+            },
+            hash = if hash_suffix { " {hash}" } else { "" },
+        );
+        template
+            .parse()
+            .expect("[ASSERT] the flags make valid templates")
+    }
+
+    /// Whether clips of different profiles can only collide if their `ext`s do.
+    pub fn has_profile(&self) -> bool {
+        self.components
+            .iter()
+            .flatten()
+            .any(|piece| *piece == Piece::Token(Token::Profile))
+    }
+
+    /// The path of the clip of `vars` with `opts`, without extension (see
+    /// `ClipOptions::outfile()`).
+    pub fn render(&self, vars: &Vars, opts: &ffmpeg::ClipOptions) -> PathBuf {
+        let target = util::FilenameTarget::native();
+        self.components
+            .iter()
+            .map(|pieces| {
+                let component: String = pieces
+                    .iter()
+                    .map(|piece| match piece {
+                        Piece::Literal(literal) => Cow::Borrowed(literal.as_str()),
+                        Piece::Token(token) => vars.value(*token, opts),
+                    })
+                    .collect();
+                let component = util::escape_for_filename(&component, target);
+                if component.is_empty() || component == "." || component == ".." {
+                    "_".to_owned()
+                } else {
+                    component
+                }
+            })
+            .collect()
+    }
+}
+
+impl Vars<'_> {
+    fn value<'a>(&'a self, token: Token, opts: &'a ffmpeg::ClipOptions) -> Cow<'a, str> {
+        match token {
+            Token::Text => Cow::Borrowed(&self.text),
+            Token::Start => Cow::Borrowed(&self.start),
+            Token::Num => Cow::Owned(self.sub_num.to_string()),
+            Token::Path => Cow::Owned(
+                util::truncate_bytes(&self.video.to_string_lossy(), self.path_len).to_owned(),
+            ),
+            Token::VideoStem => self
+                .video
+                .file_stem()
+                .map(|stem| stem.to_string_lossy())
+                .unwrap_or_default(),
+            Token::Lang => Cow::Borrowed(self.stream.language().unwrap_or("und")),
+            Token::Stream => Cow::Owned(match self.stream {
+                db::SubPath::InternalFFmpeg { stream_id, .. } => format!("#{stream_id}"),
+                db::SubPath::External { .. } => "srt".to_owned(),
+            }),
+            Token::Hash => Cow::Borrowed(&self.hash),
+            Token::Profile => Cow::Owned(opts.profile.to_string()),
+            Token::Ext => Cow::Borrowed(opts.ext()),
+        }
+    }
+}
+
+impl FromStr for OutputTemplate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // the clip dir is where templates are relative to anyway
+        let path = s.strip_prefix("{clip_dir}/").unwrap_or(s);
+        let path = path
+            .strip_suffix(".{ext}")
+            .context("the template has to end with `.{ext}`")?;
+        let components = path
+            .split('/')
+            .map(parse_component)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { components })
+    }
+}
+
+fn parse_component(component: &str) -> Result<Vec<Piece>> {
+    if component.is_empty() || component == "." || component == ".." {
+        bail!("{component:?} isn't a valid dir or file name, the template has to be relative to the clip dir");
+    }
+    let mut pieces = Vec::new();
+    let mut rest = component;
+    while let Some(open) = rest.find('{') {
+        if open > 0 {
+            pieces.push(Piece::Literal(rest[..open].to_owned()));
+        }
+        let close = rest[open..]
+            .find('}')
+            .with_context(|| format!("unclosed `{{` in {component:?}"))?
+            + open;
+        let name = &rest[open + 1..close];
+        let Some((_, token)) = Token::ALL.iter().find(|(known, _)| *known == name) else {
+            bail!(
+                "unknown token {{{name}}}, known are {known}",
+                known = Token::ALL
+                    .iter()
+                    .map(|(known, _)| format!("{{{known}}}"))
+                    .join(", ")
+            );
+        };
+        pieces.push(Piece::Token(*token));
+        rest = &rest[close + 1..];
+    }
+    if !rest.is_empty() {
+        pieces.push(Piece::Literal(rest.to_owned()));
+    }
+    Ok(pieces)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use anyhow::Result;
+
+    use super::{OutputTemplate, Vars};
+    use crate::{ffmpeg, sub::db};
+
+    #[test]
+    fn render() -> Result<()> {
+        let stream = db::SubPath::InternalFFmpeg {
+            stream_id: 2,
+            language: Some("jpn".to_owned()),
+            forced: false,
+            default: false,
+        };
+        let vars = Vars {
+            video: Path::new("/shows/Show: 01.mkv"),
+            stream: &stream,
+            sub_num: 7,
+            text: "Nani/sore".to_owned(),
+            start: "00:01:12,340".to_owned(),
+            path_len: 15,
+            hash: "0123abcd".to_owned(),
+        };
+        let opts = ffmpeg::ClipOptions {
+            profile: ffmpeg::EncodingProfile::AV1,
+            container: None,
+            pad_start: 0,
+            pad_end: 0,
+            overwrite: ffmpeg::Overwrite::default(),
+            audio: None,
+            stage_dir: None,
+            loudnorm: false,
+            fade: 0,
+            crf: None,
+            preset: None,
+            sub_codec: None,
+        };
+        let render = |template: &OutputTemplate| template.render(&vars, &opts);
+        let profile = opts.profile.to_string();
+
+        assert_eq!(
+            render(&OutputTemplate::from_flags(false, false, false)),
+            PathBuf::from("Nani_sore [00_01_12,340] (_shows_Show_ 01)")
+        );
+        assert_eq!(
+            render(&OutputTemplate::from_flags(true, true, true)),
+            Path::new(&profile)
+                .join("Show_ 01")
+                .join("Nani_sore [00_01_12,340] (_shows_Show_ 01) 0123abcd")
+        );
+        assert_eq!(
+            render(&"{clip_dir}/{video_stem}/{lang}/{stream}-{num}_{text}.{ext}".parse()?),
+            Path::new("Show_ 01").join("jpn").join("#2-7_Nani_sore")
+        );
+        assert_eq!(
+            render(&"{ext}/{text}.{ext}".parse()?),
+            Path::new(opts.ext()).join("Nani_sore")
+        );
+        assert!(OutputTemplate::from_flags(true, false, false).has_profile());
+        assert!(!OutputTemplate::from_flags(false, true, true).has_profile());
+
+        for invalid in [
+            "{text}",
+            "{text}.mp4",
+            "/abs/{text}.{ext}",
+            "a//{text}.{ext}",
+            "../{text}.{ext}",
+            "{txt}.{ext}",
+            "{text.{ext}",
+        ] {
+            assert!(invalid.parse::<OutputTemplate>().is_err(), "{invalid}");
+        }
+
+        // a component can't step out of the clip dir by rendering to `.` or `..`
+        for text in [".", ".."] {
+            let vars = Vars {
+                text: text.to_owned(),
+                start: vars.start.clone(),
+                hash: vars.hash.clone(),
+                ..vars
+            };
+            assert_eq!(
+                "{text}/{num}.{ext}"
+                    .parse::<OutputTemplate>()?
+                    .render(&vars, &opts),
+                Path::new("_").join("7")
+            );
+        }
+
+        // `ï` (2 bytes) doesn't fit whole, so it's left out rather than cut
+        let vars = Vars {
+            video: Path::new("/shows/Ünï.mkv"),
+            path_len: 11,
+            ..vars
+        };
+        assert_eq!(
+            OutputTemplate::from_flags(false, false, false).render(&vars, &opts),
+            PathBuf::from("Nani_sore [00_01_12,340] (_shows_Ün)")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,416 @@
+use std::{collections::BTreeSet, io::Stderr};
+
+use anyhow::{Context as _, Result};
+use itertools::Itertools as _;
+use ratatui::{
+    backend::CrosstermBackend,
+    crossterm::{
+        event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+        execute,
+        terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+    },
+    layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, List, ListItem, Paragraph, Wrap},
+    Frame, Terminal,
+};
+use rayon::iter::{IntoParallelRefIterator as _, ParallelIterator as _};
+
+use crate::{
+    ffmpeg,
+    fzf::{PlayTarget, SearchKeys},
+};
+
+/// Lines of context around the current one in the side panel, each way.
+const CONTEXT_LINES: usize = 3;
+
+/// Like `fzf::select()`, but in a built-in finder instead of fzf, see
+/// `--finder tui`. Typing filters fuzzily (case-insensitive), tab or
+/// shift-tab select lines, enter confirms (the current line if none are
+/// selected) and Esc cancels. With `targets`, a side panel shows the current
+/// line's video, time and neighbouring lines. The help line calls what enter
+/// does `action`, e.g. `clip`.
+pub fn select(
+    strings: &[impl AsRef<str>],
+    search_keys: SearchKeys,
+    targets: Option<&[PlayTarget]>,
+    action: &str,
+) -> Result<Vec<String>> {
+    if let Some(targets) = targets {
+        assert_eq!(strings.len(), targets.len(), "one target per string");
+    }
+    let mut finder = Finder::new(strings, search_keys);
+
+    terminal::enable_raw_mode().context("switching the terminal to raw mode")?;
+    // also when panicking, or the shell stays unusable
+    let _restore = scopeguard::guard((), |()| {
+        let _ = execute!(std::io::stderr(), LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    });
+    execute!(std::io::stderr(), EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(std::io::stderr()))?;
+
+    let picked = run(&mut finder, &mut terminal, targets, action)?;
+    Ok(picked
+        .into_iter()
+        .map(|i| strings[i].as_ref().to_owned())
+        .collect())
+}
+
+fn run(
+    finder: &mut Finder,
+    terminal: &mut Terminal<CrosstermBackend<Stderr>>,
+    targets: Option<&[PlayTarget]>,
+    action: &str,
+) -> Result<Vec<usize>> {
+    loop {
+        terminal.draw(|frame| draw(frame, finder, targets, action))?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind == KeyEventKind::Release {
+            continue;
+        }
+        // the list area without its borders
+        let page = terminal.size()?.height.saturating_sub(3).max(1);
+        if let Some(outcome) = finder.handle_key(key, usize::from(page)) {
+            return Ok(outcome);
+        }
+    }
+}
+
+/// The finder's state, apart from the terminal.
+#[derive(Debug)]
+struct Finder {
+    /// shown per string
+    displays: Vec<String>,
+    /// matched per string, lowercase
+    haystacks: Vec<Vec<char>>,
+    query: String,
+    /// indices of the strings matching `query`, best first
+    matches: Vec<usize>,
+    /// position in `matches`
+    cursor: usize,
+    /// first position in `matches` shown
+    offset: usize,
+    /// indices of the strings
+    selected: BTreeSet<usize>,
+}
+
+impl Finder {
+    fn new(strings: &[impl AsRef<str>], search_keys: SearchKeys) -> Self {
+        let haystack = |s: &str| s.chars().flat_map(char::to_lowercase).collect_vec();
+        let strings = strings.iter().map(AsRef::as_ref);
+        let (displays, haystacks): (Vec<String>, _) = match search_keys {
+            SearchKeys::None => strings.map(|s| (s.to_owned(), haystack(s))).unzip(),
+            SearchKeys::Appended(keys) => strings
+                .zip_eq(keys)
+                .map(|(s, key)| (s.to_owned(), haystack(&format!("{s} {key}"))))
+                .unzip(),
+            SearchKeys::Only(keys) => strings
+                .zip_eq(keys)
+                .map(|(_, key)| (key.clone(), haystack(key)))
+                .unzip(),
+        };
+        let matches = (0..displays.len()).collect();
+        Self {
+            displays: displays
+                .into_iter()
+                .map(|display| display.replace('\n', " ⏎ "))
+                .collect(),
+            haystacks,
+            query: String::new(),
+            matches,
+            cursor: 0,
+            offset: 0,
+            selected: BTreeSet::new(),
+        }
+    }
+
+    /// Reacts to `key`, with `page` lines in view. Returns the picked strings'
+    /// indices once done, none if cancelled.
+    fn handle_key(&mut self, key: KeyEvent, page: usize) -> Option<Vec<usize>> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => return Some(Vec::new()),
+            KeyCode::Char('c') if ctrl => return Some(Vec::new()),
+            KeyCode::Enter => {
+                return Some(if self.selected.is_empty() {
+                    self.matches.get(self.cursor).copied().into_iter().collect()
+                } else {
+                    self.selected.iter().copied().collect()
+                })
+            }
+            // not space, which goes into the query
+            KeyCode::Tab => {
+                self.toggle();
+                self.move_cursor(1, page);
+            }
+            KeyCode::BackTab => {
+                self.toggle();
+                self.move_cursor(-1, page);
+            }
+            KeyCode::Char('a') if ctrl => self.selected.extend(&self.matches),
+            KeyCode::Char('u') if ctrl => self.set_query(String::new()),
+            KeyCode::Up => self.move_cursor(-1, page),
+            KeyCode::Char('k' | 'p') if ctrl => self.move_cursor(-1, page),
+            KeyCode::Down => self.move_cursor(1, page),
+            KeyCode::Char('j' | 'n') if ctrl => self.move_cursor(1, page),
+            KeyCode::PageUp => self.move_cursor(-isize::try_from(page).unwrap_or(1), page),
+            KeyCode::PageDown => self.move_cursor(isize::try_from(page).unwrap_or(1), page),
+            KeyCode::Backspace => {
+                let mut query = self.query.clone();
+                query.pop();
+                self.set_query(query);
+            }
+            KeyCode::Char(c) if !ctrl => {
+                let mut query = self.query.clone();
+                query.push(c);
+                self.set_query(query);
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn toggle(&mut self) {
+        let Some(&i) = self.matches.get(self.cursor) else {
+            return;
+        };
+        if !self.selected.remove(&i) {
+            self.selected.insert(i);
+        }
+    }
+
+    /// Moves the cursor by `by` lines, scrolling so it stays among the `page`
+    /// lines shown.
+    fn move_cursor(&mut self, by: isize, page: usize) {
+        let last = self.matches.len().saturating_sub(1);
+        self.cursor = self.cursor.saturating_add_signed(by).min(last);
+        if self.cursor < self.offset {
+            self.offset = self.cursor;
+        } else if self.cursor >= self.offset + page {
+            self.offset = self.cursor + 1 - page;
+        }
+    }
+
+    /// Filters by `query`. Narrowing it down only searches the current matches.
+    fn set_query(&mut self, query: String) {
+        let pattern = query.chars().flat_map(char::to_lowercase).collect_vec();
+        let candidates = if query.starts_with(&self.query) {
+            std::mem::take(&mut self.matches)
+        } else {
+            (0..self.haystacks.len()).collect()
+        };
+        let mut scored = candidates
+            .par_iter()
+            .filter_map(|&i| Some((score(&pattern, &self.haystacks[i])?, i)))
+            .collect::<Vec<_>>();
+        scored.sort_by_key(|&(score, i)| (std::cmp::Reverse(score), i));
+        self.matches = scored.into_iter().map(|(_, i)| i).collect();
+        self.query = query;
+        self.cursor = 0;
+        self.offset = 0;
+    }
+}
+
+/// How well `pattern` matches `haystack` (both lowercase) as a subsequence,
+/// higher is better, or `None` if it doesn't. Like in fzf, consecutive chars
+/// and ones at the start of words count more, gaps less.
+fn score(pattern: &[char], haystack: &[char]) -> Option<i64> {
+    let mut score = 0;
+    let mut last: Option<usize> = None;
+    let mut chars = haystack.iter().enumerate();
+    for c in pattern {
+        let (i, _) = chars.find(|(_, other)| *other == c)?;
+        score += match last {
+            Some(last) if last + 1 == i => 8,
+            Some(last) => 1 - i64::try_from(i - last - 1).unwrap_or(i64::MAX).min(3),
+            None => 1,
+        };
+        if i == 0 || !haystack[i - 1].is_alphanumeric() {
+            score += 4;
+        }
+        last = Some(i);
+    }
+    Some(score)
+}
+
+fn draw(frame: &mut Frame, finder: &Finder, targets: Option<&[PlayTarget]>, action: &str) {
+    let [main, prompt] =
+        Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(frame.area());
+    let list = match targets {
+        Some(targets) => {
+            let [list, panel] =
+                Layout::horizontal([Constraint::Percentage(65), Constraint::Percentage(35)])
+                    .areas(main);
+            draw_context(frame, panel, finder, targets);
+            list
+        }
+        None => main,
+    };
+
+    let items = finder
+        .matches
+        .iter()
+        .enumerate()
+        .skip(finder.offset)
+        .take(usize::from(list.height.saturating_sub(2)))
+        .map(|(position, &i)| {
+            let marker = if finder.selected.contains(&i) {
+                "● "
+            } else {
+                "  "
+            };
+            let item = ListItem::new(format!("{marker}{}", finder.displays[i]));
+            if position == finder.cursor {
+                item.style(Style::new().add_modifier(Modifier::REVERSED))
+            } else {
+                item
+            }
+        })
+        .collect_vec();
+    let title = format!(
+        " {matched}/{total} ({selected} selected) ",
+        matched = finder.matches.len(),
+        total = finder.displays.len(),
+        selected = finder.selected.len(),
+    );
+    frame.render_widget(List::new(items).block(Block::bordered().title(title)), list);
+
+    let help = format!("tab: select · enter: {action} · esc: cancel");
+    frame.render_widget(
+        Paragraph::new(format!("> {query}    {help}", query = finder.query)),
+        prompt,
+    );
+    // after "> "
+    let x = prompt.x + 2 + u16::try_from(finder.query.chars().count()).unwrap_or(u16::MAX);
+    frame.set_cursor_position((x.min(prompt.right().saturating_sub(1)), prompt.y));
+}
+
+/// The current line's video and time, between its neighbours in the same video.
+fn draw_context(frame: &mut Frame, area: Rect, finder: &Finder, targets: &[PlayTarget]) {
+    let Some(&current) = finder.matches.get(finder.cursor) else {
+        frame.render_widget(Block::bordered().title(" Context "), area);
+        return;
+    };
+    let target = &targets[current];
+    #[allow(clippy::cast_precision_loss)] // ms of a line
+    let seconds = target.end.saturating_sub(target.start) as f64 / 1000.0;
+    let same_video = |i: &usize| targets[*i].video == target.video;
+    let before = (current.saturating_sub(CONTEXT_LINES)..current)
+        .rev()
+        .take_while(same_video)
+        .collect_vec();
+    let after = (current + 1..targets.len())
+        .take(CONTEXT_LINES)
+        .take_while(same_video);
+    let mut lines = vec![
+        Line::from(
+            target
+                .video
+                .file_name()
+                .unwrap_or(target.video.as_os_str())
+                .to_string_lossy()
+                .into_owned(),
+        ),
+        Line::from(format!(
+            "{start} – {end} ({seconds:.1}s)",
+            start = ffmpeg::millis_to_string(target.start),
+            end = ffmpeg::millis_to_string(target.end),
+        )),
+        Line::from(""),
+    ];
+    lines.extend(
+        before
+            .into_iter()
+            .rev()
+            .chain([current])
+            .chain(after)
+            .map(|i| {
+                let line = Line::from(finder.displays[i].clone());
+                if i == current {
+                    line.style(Style::new().add_modifier(Modifier::BOLD))
+                } else {
+                    line.style(Style::new().add_modifier(Modifier::DIM))
+                }
+            }),
+    );
+    frame.render_widget(
+        Paragraph::new(lines)
+            .block(Block::bordered().title(" Context "))
+            .wrap(Wrap { trim: false }),
+        area,
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use super::Finder;
+    use crate::fzf::SearchKeys;
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    #[test]
+    fn score() {
+        let score = |pattern: &str, haystack: &str| super::score(&chars(pattern), &chars(haystack));
+        assert_eq!(score("", "anything"), Some(0));
+        assert_eq!(score("xyz", "hello"), None);
+        assert_eq!(score("olleh", "hello"), None);
+        assert!(score("hello", "hello there") > score("hello", "h e l l o"));
+        assert!(score("there", "hello there") > score("there", "hellothere"));
+    }
+
+    #[test]
+    fn finder() {
+        let strings = ["Hello there", "General Kenobi", "Hello\nagain"];
+        let mut finder = Finder::new(&strings, SearchKeys::None);
+        let press = |finder: &mut Finder, code: KeyCode| {
+            finder.handle_key(KeyEvent::new(code, KeyModifiers::NONE), 10)
+        };
+        assert_eq!(finder.displays[2], "Hello ⏎ again");
+        assert_eq!(finder.matches, [0, 1, 2]);
+
+        for c in "hel".chars() {
+            assert_eq!(press(&mut finder, KeyCode::Char(c)), None);
+        }
+        assert_eq!(finder.matches, [0, 2]);
+        press(&mut finder, KeyCode::Char('a'));
+        assert_eq!(finder.matches, [2]);
+        press(&mut finder, KeyCode::Backspace);
+        assert_eq!(finder.matches, [0, 2]);
+        // space is part of the query
+        press(&mut finder, KeyCode::Char(' '));
+        assert!(finder.selected.is_empty());
+        assert_eq!(finder.query, "hel ");
+        assert_eq!(finder.matches, [0]);
+        press(&mut finder, KeyCode::Backspace);
+
+        // selects and moves on
+        press(&mut finder, KeyCode::Tab);
+        press(&mut finder, KeyCode::Tab);
+        press(&mut finder, KeyCode::BackTab);
+        assert_eq!(finder.selected.iter().copied().collect::<Vec<_>>(), [0]);
+        assert_eq!(press(&mut finder, KeyCode::Enter), Some(vec![0]));
+
+        let mut finder = Finder::new(&strings, SearchKeys::None);
+        press(&mut finder, KeyCode::Down);
+        assert_eq!(
+            press(&mut finder, KeyCode::Enter),
+            Some(vec![1]),
+            "the current one"
+        );
+        assert_eq!(press(&mut finder, KeyCode::Esc), Some(vec![]));
+
+        let keys = ["a".to_owned(), "b".to_owned(), "c".to_owned()];
+        let mut finder = Finder::new(&strings, SearchKeys::Only(&keys));
+        press(&mut finder, KeyCode::Char('b'));
+        assert_eq!(finder.matches, [1]);
+        assert_eq!(finder.displays[1], "b");
+    }
+}
@@ -1,10 +1,50 @@
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    collections::BTreeSet,
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering as AtomicOrdering},
+        LazyLock,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context as _, Result};
+use itertools::{EitherOrBoth, Itertools as _};
+use regex::Regex;
+
+/// Which filesystem's naming rules `escape_for_filename()` satisfies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilenameTarget {
+    Unix,
+    /// also fine for NTFS/SMB shares mounted on unix
+    Windows,
+}
+
+impl FilenameTarget {
+    pub fn native() -> Self {
+        if cfg!(windows) {
+            Self::Windows
+        } else {
+            Self::Unix
+        }
+    }
+}
+
+const WINDOWS_RESERVED_NAMES: [&str; 24] = [
+    "CON", "PRN", "AUX", "NUL", "COM0", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7",
+    "COM8", "COM9", "LPT0", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
 // gerüst from ChatGTFO
-pub fn escape_for_unix_filename(input: &str) -> String {
+pub fn escape_for_filename(input: &str, target: FilenameTarget) -> String {
     // TODO use unicode for / and :
     let mut result: String = input
         .chars()
         .map(|c| match c {
             '/' | '*' | '?' | ':' | '|' | '\'' | '"' | '\0' => '_',
+            '<' | '>' | '\\' if target == FilenameTarget::Windows => '_',
             _ => c,
         })
         .enumerate()
@@ -14,5 +54,748 @@ pub fn escape_for_unix_filename(input: &str) -> String {
     for pat in ["\r\n", "\n", "\r"] {
         result = result.replace(pat, "___");
     }
+
+    if target == FilenameTarget::Windows {
+        result = result
+            .chars()
+            .map(|c| if c.is_control() { '_' } else { c })
+            .collect();
+        result.truncate(result.trim_end_matches(['.', ' ']).len());
+        if result.is_empty() {
+            result.push('_');
+        }
+        // reserved even with an extension, e.g. `nul.txt`
+        let stem_len = result.find('.').unwrap_or(result.len());
+        if WINDOWS_RESERVED_NAMES
+            .iter()
+            .any(|name| result[..stem_len].eq_ignore_ascii_case(name))
+        {
+            result.insert(stem_len, '_');
+        }
+    }
     result
 }
+
+/// `text` cut to at most `max_bytes` bytes, including a trailing `…` if it's
+/// cut (which is all that's left if `max_bytes` can't even hold it). Cuts at
+/// the last whitespace if there is one in the second half, so words stay
+/// whole, and never within a char.
+pub fn truncate_at_word(text: &str, max_bytes: usize) -> Cow<'_, str> {
+    if text.len() <= max_bytes {
+        return Cow::Borrowed(text);
+    }
+    let keep = max_bytes.saturating_sub('…'.len_utf8());
+    let cut = truncate_bytes(text, keep);
+    // unless the cut is at a word boundary already
+    let cut = if text[cut.len()..].starts_with(char::is_whitespace) {
+        cut
+    } else {
+        cut.char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+            .map(|(i, _)| &cut[..i])
+            .filter(|word_cut| word_cut.len() >= keep / 2)
+            .unwrap_or(cut)
+    };
+    Cow::Owned(format!("{}…", cut.trim_end()))
+}
+
+/// The longest start of `text` of at most `max_bytes` bytes that doesn't end
+/// within a char.
+pub fn truncate_bytes(text: &str, max_bytes: usize) -> &str {
+    let end = (0..=max_bytes.min(text.len()))
+        .rev()
+        .find(|&i| text.is_char_boundary(i))
+        .unwrap_or(0);
+    &text[..end]
+}
+
+/// How many bytes of the subtitle text and of the video's path go into clip
+/// filenames (before escaping), as filesystems limit names in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilenameLens {
+    pub text: usize,
+    pub path: usize,
+}
+
+impl FilenameLens {
+    /// `CLIP_FILENAME_TEXT_LEN` and `CLIP_FILENAME_PATH_LEN`, scaled down
+    /// alike to fit names of at most `name_max` bytes (e.g. 143 on eCryptfs),
+    /// given `CLIP_FILENAME_OVERHEAD`. Never more than those, and at least one
+    /// byte each.
+    pub fn fitting(name_max: Option<usize>) -> Self {
+        let defaults = crate::CLIP_FILENAME_TEXT_LEN + crate::CLIP_FILENAME_PATH_LEN;
+        let budget = name_max
+            .map_or(defaults, |name_max| {
+                name_max.saturating_sub(crate::CLIP_FILENAME_OVERHEAD)
+            })
+            .min(defaults);
+        let text = (crate::CLIP_FILENAME_TEXT_LEN * budget / defaults).max(1);
+        Self {
+            text,
+            path: budget.saturating_sub(text).max(1),
+        }
+    }
+}
+
+/// The longest file name (in bytes) allowed in `dir`, or its closest existing
+/// ancestor. `None` if the filesystem doesn't tell.
+#[cfg(unix)]
+pub fn name_max(dir: &Path) -> Option<usize> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt as _};
+
+    let dir = dir.ancestors().find(|dir| dir.is_dir())?;
+    let dir = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    // SAFETY: `dir` is a valid C string for the duration of the call
+    let name_max = unsafe { libc::pathconf(dir.as_ptr(), libc::_PC_NAME_MAX) };
+    // -1 on errors and for "no limit"
+    usize::try_from(name_max).ok()
+}
+
+/// Names are at most 255 UTF-16 units on NTFS, like the defaults assume.
+#[cfg(not(unix))]
+pub fn name_max(_dir: &Path) -> Option<usize> {
+    None
+}
+
+/// Expands a leading `~`/`~user` and makes relative paths absolute (against the
+/// current dir). Doesn't touch the filesystem otherwise, so `path` needn't exist.
+pub fn resolve_path(path: &Path) -> Result<PathBuf> {
+    let mut components = path.components();
+    let expanded = match components.next() {
+        Some(Component::Normal(first)) if first.as_encoded_bytes().starts_with(b"~") => {
+            let user = &first.as_encoded_bytes()[1..];
+            let home = if user.is_empty() {
+                home_dir()
+            } else {
+                home_dir_of(user)
+            }
+            .with_context(|| format!("couldn't find home dir for {first:?}"))?;
+            home.join(components.as_path())
+        }
+        _ => path.to_owned(),
+    };
+    std::path::absolute(&expanded).with_context(|| format!("making {expanded:?} absolute"))
+}
+
+/// The current user's home dir, from the environment.
+pub fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| {
+            cfg!(windows)
+                .then(|| std::env::var_os("USERPROFILE"))
+                .flatten()
+        })
+        .map(PathBuf::from)
+}
+
+/// Where config files go: `$XDG_CONFIG_HOME`, falling back to `~/.config`
+/// (`%APPDATA%` on Windows).
+pub fn config_dir() -> Option<PathBuf> {
+    let xdg = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        // relative ones are invalid per spec
+        .filter(|dir| dir.is_absolute());
+    xdg.or_else(|| {
+        if cfg!(windows) {
+            std::env::var_os("APPDATA").map(PathBuf::from)
+        } else {
+            home_dir().map(|home| home.join(".config"))
+        }
+    })
+}
+
+/// Where app data goes: `$XDG_DATA_HOME`, falling back to `~/.local/share`
+/// (`%LOCALAPPDATA%` on Windows).
+pub fn data_dir() -> Option<PathBuf> {
+    let xdg = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute());
+    xdg.or_else(|| {
+        if cfg!(windows) {
+            std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+        } else {
+            home_dir().map(|home| home.join(".local").join("share"))
+        }
+    })
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether Ctrl-C was pressed since `handle_interrupts()`.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(AtomicOrdering::Relaxed)
+}
+
+/// Makes Ctrl-C (`SIGINT`) only set `interrupted()`, so long running work can
+/// stop at the next sensible point and the DB gets saved. A second Ctrl-C
+/// kills right away, as usual.
+#[cfg(unix)]
+pub fn handle_interrupts() {
+    extern "C" fn on_sigint(_: libc::c_int) {
+        INTERRUPTED.store(true, AtomicOrdering::Relaxed);
+        // SAFETY: `signal()` is async-signal-safe
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+        }
+    }
+
+    // SAFETY: the handler only touches an atomic and calls `signal()`, which
+    // are both async-signal-safe
+    unsafe {
+        libc::signal(libc::SIGINT, on_sigint as *const () as libc::sighandler_t);
+    }
+}
+
+/// Ctrl-C keeps killing right away outside of unix.
+#[cfg(not(unix))]
+pub fn handle_interrupts() {}
+
+#[cfg(unix)]
+fn home_dir_of(user: &[u8]) -> Option<PathBuf> {
+    use std::{
+        ffi::{CStr, CString, OsStr},
+        os::unix::ffi::OsStrExt as _,
+    };
+
+    let user = CString::new(user).ok()?;
+    // SAFETY: `getpwnam()` returns null or a pointer to a valid passwd entry in
+    // static storage, which we copy out of right away. Only called at startup,
+    // so there are no concurrent calls clobbering it.
+    unsafe {
+        let passwd = libc::getpwnam(user.as_ptr());
+        if passwd.is_null() || (*passwd).pw_dir.is_null() {
+            return None;
+        }
+        let dir = CStr::from_ptr((*passwd).pw_dir);
+        Some(PathBuf::from(OsStr::from_bytes(dir.to_bytes())))
+    }
+}
+
+/// Other users' home dirs (`~user`) aren't supported outside of unix.
+#[cfg(not(unix))]
+fn home_dir_of(_user: &[u8]) -> Option<PathBuf> {
+    None
+}
+
+/// Shell-style glob matched against relative paths: `*` and `?` stay inside a
+/// path component, `**` spans several, `[...]`/`[!...]` are char classes.
+/// Globs without a `/` (ignoring a trailing one) match a single component at
+/// any depth, like in `.gitignore`, e.g. `sample` or `*.txt`.
+#[derive(Debug, Clone)]
+pub struct Glob(Regex);
+
+impl Glob {
+    pub fn new(glob: &str) -> Result<Self> {
+        let trimmed = glob.trim_end_matches('/');
+        let mut regex = String::from(if trimmed.contains('/') {
+            "^"
+        } else {
+            "(?:^|/)"
+        });
+        let mut chars = trimmed.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.next_if_eq(&'*').is_some() => {
+                    if chars.next_if_eq(&'/').is_some() {
+                        regex.push_str("(?:.*/)?");
+                    } else {
+                        regex.push_str(".*");
+                    }
+                }
+                '*' => regex.push_str("[^/]*"),
+                '?' => regex.push_str("[^/]"),
+                '[' => {
+                    let class = chars.by_ref().take_while(|&c| c != ']').collect::<String>();
+                    let (negated, class) = match class.strip_prefix('!') {
+                        Some(class) => ("^", class),
+                        None => ("", class.as_str()),
+                    };
+                    let class = class.replace('\\', r"\\").replace('[', r"\[");
+                    regex.extend(["[", negated, &class, "]"]);
+                }
+                c => regex.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        regex.push('$');
+        Ok(Self(
+            Regex::new(&regex).with_context(|| format!("invalid glob {glob:?}"))?,
+        ))
+    }
+
+    pub fn is_match(&self, path: &Path) -> bool {
+        self.0.is_match(&path.to_string_lossy())
+    }
+}
+
+impl PartialEq for Glob {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for Glob {}
+
+/// 64-bit FNV-1a, which (unlike std's hashers) is stable across Rust versions,
+/// so it can be stored.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Lowercases `input` and strips diacritics from Latin letters (`Café` =>
+/// `cafe`), for matching queries regardless of case and accents.
+pub fn fold_for_search(input: &str) -> String {
+    let mut folded = String::with_capacity(input.len());
+    for c in input.chars().flat_map(char::to_lowercase) {
+        match fold_latin(c) {
+            Some(base) => folded.push_str(base),
+            None => folded.push(c),
+        }
+    }
+    folded
+}
+
+fn fold_latin(c: char) -> Option<&'static str> {
+    Some(match c {
+        'à'..='å' | 'ā' | 'ă' | 'ą' => "a",
+        'æ' => "ae",
+        'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => "c",
+        'ð' | 'ď' | 'đ' => "d",
+        'è'..='ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+        'ĝ' | 'ğ' | 'ġ' | 'ģ' => "g",
+        'ĥ' | 'ħ' => "h",
+        'ì'..='ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => "i",
+        'ĵ' => "j",
+        'ķ' => "k",
+        'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => "l",
+        'ñ' | 'ń' | 'ņ' | 'ň' => "n",
+        'ò'..='ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => "o",
+        'œ' => "oe",
+        'ŕ' | 'ŗ' | 'ř' => "r",
+        'ś' | 'ŝ' | 'ş' | 'š' => "s",
+        'ß' => "ss",
+        'ţ' | 'ť' | 'ŧ' => "t",
+        'þ' => "th",
+        'ù'..='ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => "u",
+        'ŵ' => "w",
+        'ý' | 'ÿ' | 'ŷ' => "y",
+        'ź' | 'ż' | 'ž' => "z",
+        _ => return None,
+    })
+}
+
+static REGEX_DIGITS_OR_NOT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\d+|\D+").expect("valid regex"));
+
+/// Compares runs of digits by value (`e2` < `e10`), everything else as usual.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let chunks = |s| REGEX_DIGITS_OR_NOT.find_iter(s).map(|m| m.as_str());
+    let numeric = |chunk: &str| chunk.starts_with(|c: char| c.is_ascii_digit());
+    for pair in chunks(a).zip_longest(chunks(b)) {
+        let ordering = match pair {
+            EitherOrBoth::Both(a, b) if numeric(a) && numeric(b) => {
+                let (a, b) = (a.trim_start_matches('0'), b.trim_start_matches('0'));
+                a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+            }
+            EitherOrBoth::Both(a, b) => a.cmp(b),
+            EitherOrBoth::Left(_) => Ordering::Greater,
+            EitherOrBoth::Right(_) => Ordering::Less,
+        };
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+    a.cmp(b)
+}
+
+/// Creates the parent dirs of all `files`, each only once.
+pub fn create_parent_dirs<'a>(files: impl IntoIterator<Item = &'a Path>) -> Result<()> {
+    let dirs = files
+        .into_iter()
+        .filter_map(Path::parent)
+        .collect::<BTreeSet<_>>();
+    for dir in dirs {
+        std::fs::create_dir_all(dir).with_context(|| format!("creating dir {dir:?}"))?;
+    }
+    Ok(())
+}
+
+/// Moves `from` to `to` like `std::fs::rename()`, but also across filesystems
+/// (e.g. out of a `--stage-dir` on another disk) by copying and deleting. The
+/// copy goes to a hidden temp file next to `to` first, so a failed one never
+/// leaves a partial file under `to`.
+pub fn move_file(from: &Path, to: &Path) -> Result<()> {
+    match std::fs::rename(from, to) {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => copy_then_remove(from, to),
+        result => result.with_context(|| format!("moving {from:?} to {to:?}")),
+    }
+}
+
+fn copy_then_remove(from: &Path, to: &Path) -> Result<()> {
+    let dir = to
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let temp = staging_file(dir, "")?;
+    std::fs::copy(from, temp.path()).with_context(|| format!("copying {from:?} to {dir:?}"))?;
+    temp.persist(to)
+        .map_err(|e| e.error)
+        .with_context(|| format!("moving the copy of {from:?} to {to:?}"))?;
+    std::fs::remove_file(from).with_context(|| format!("removing {from:?} after copying it"))
+}
+
+/// A hidden temp file in `dir`, ending in `suffix`, for something to be moved
+/// into place once it's done. Unlike tempfile's usual private `0600`, it gets
+/// the permissions of a plainly created file (`0666` minus the umask), which
+/// it keeps when moved.
+pub fn staging_file(dir: &Path, suffix: &str) -> Result<tempfile::NamedTempFile> {
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(".magiclip-").suffix(suffix);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+        // `open()` applies the umask to it
+        builder.permissions(std::fs::Permissions::from_mode(0o666));
+    }
+    builder
+        .tempfile_in(dir)
+        .with_context(|| format!("creating a temp file in {dir:?}"))
+}
+
+/// A fresh temp dir (removed when dropped) in `parent`, or in the system's
+/// (`$TMPDIR`) if `None`.
+pub fn temp_dir(parent: Option<&Path>) -> Result<tempfile::TempDir> {
+    match parent {
+        Some(parent) => {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("creating temp dir parent {parent:?}"))?;
+            tempfile::tempdir_in(parent).with_context(|| format!("creating temp dir in {parent:?}"))
+        }
+        None => tempfile::tempdir().context("creating temp dir"),
+    }
+}
+
+/// Wall time of the phases of a run, in order, see `--time-phases`.
+#[derive(Debug, Default)]
+pub struct Timings(Vec<(&'static str, Duration)>);
+
+/// A phase being timed, see `Timings::start()`.
+#[must_use = "a span only counts once finished"]
+pub struct Span {
+    phase: &'static str,
+    start: Instant,
+}
+
+impl Timings {
+    /// Starts timing `phase`, until `finish()`ed.
+    pub fn start(phase: &'static str) -> Span {
+        Span {
+            phase,
+            start: Instant::now(),
+        }
+    }
+
+    #[allow(clippy::needless_pass_by_value)] // so it can't be finished twice
+    pub fn finish(&mut self, span: Span) {
+        self.0.push((span.phase, span.start.elapsed()));
+    }
+
+    /// Runs `f` as `phase`.
+    pub fn time<T>(&mut self, phase: &'static str, f: impl FnOnce() -> T) -> T {
+        let span = Self::start(phase);
+        let result = f();
+        self.finish(span);
+        result
+    }
+
+    /// All the time spent in `phase`.
+    pub fn of(&self, phase: &str) -> Duration {
+        self.0
+            .iter()
+            .filter(|(other, _)| *other == phase)
+            .map(|(_, duration)| *duration)
+            .sum()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, Duration)> + '_ {
+        self.0.iter().copied()
+    }
+
+    pub fn total(&self) -> Duration {
+        self.0.iter().map(|(_, duration)| *duration).sum()
+    }
+}
+
+impl std::fmt::Display for Timings {
+    /// Like `scan 1.23s, format 45.68ms`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let phases = self
+            .0
+            .iter()
+            .map(|(phase, duration)| format!("{phase} {duration:.2?}"))
+            .join(", ");
+        f.write_str(&phases)
+    }
+}
+
+/// `n` per second of `duration`, `None` if it's too short to tell.
+pub fn per_second(n: usize, duration: Duration) -> Option<f64> {
+    #[allow(clippy::cast_precision_loss)] // counts way below 2^52
+    (!duration.is_zero()).then(|| n as f64 / duration.as_secs_f64())
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+    use tempfile::TempDir;
+
+    use super::{escape_for_filename, FilenameTarget};
+
+    #[test]
+    fn escape_for_filename_unix() {
+        assert_eq!(
+            escape_for_filename("-a/b:c\nd. ", FilenameTarget::Unix),
+            "_a_b_c___d. "
+        );
+        assert_eq!(escape_for_filename("CON", FilenameTarget::Unix), "CON");
+    }
+
+    #[test]
+    fn escape_for_filename_windows() {
+        use FilenameTarget::Windows;
+
+        assert_eq!(escape_for_filename("a<b>c\\d\te", Windows), "a_b_c_d_e");
+        assert_eq!(escape_for_filename("Wait... ", Windows), "Wait");
+        assert_eq!(escape_for_filename(" . ", Windows), "_");
+        assert_eq!(escape_for_filename("con", Windows), "con_");
+        assert_eq!(escape_for_filename("NUL.txt", Windows), "NUL_.txt");
+        assert_eq!(escape_for_filename("com0", Windows), "com0_");
+        assert_eq!(escape_for_filename("LPT0.log", Windows), "LPT0_.log");
+        assert_eq!(escape_for_filename("CONSOLE", Windows), "CONSOLE");
+    }
+
+    #[test]
+    fn truncate_at_word() {
+        use super::truncate_at_word;
+
+        assert_eq!(truncate_at_word("short", 64), "short");
+        assert_eq!(truncate_at_word("one two three", 10), "one two…");
+        assert_eq!(truncate_at_word("one two three", 8), "one…");
+        assert_eq!(truncate_at_word("Donaudampfschiff", 8), "Donau…");
+        // multibyte chars (2 bytes each) right at the cut
+        let text = format!("{}ääää ö", "a".repeat(58));
+        let cut = truncate_at_word(&text, 64);
+        assert_eq!(cut, format!("{}ä…", "a".repeat(58)));
+        assert_eq!(cut.len(), 63);
+        let cut = truncate_at_word("🦀🦀🦀", 8);
+        assert_eq!(cut, "🦀…");
+        assert!(cut.len() <= 8);
+        assert_eq!(truncate_at_word("🦀🦀🦀", 2), "…");
+    }
+
+    #[test]
+    fn truncate_bytes() {
+        use super::truncate_bytes;
+
+        assert_eq!(truncate_bytes("abc", 5), "abc");
+        assert_eq!(truncate_bytes("äöü", 3), "ä");
+        assert_eq!(truncate_bytes("äöü", 4), "äö");
+        assert_eq!(truncate_bytes("🦀", 3), "");
+    }
+
+    #[test]
+    fn filename_lens() {
+        use super::FilenameLens;
+        use crate::{CLIP_FILENAME_PATH_LEN, CLIP_FILENAME_TEXT_LEN};
+
+        let defaults = FilenameLens {
+            text: CLIP_FILENAME_TEXT_LEN,
+            path: CLIP_FILENAME_PATH_LEN,
+        };
+        assert_eq!(FilenameLens::fitting(None), defaults);
+        assert_eq!(FilenameLens::fitting(Some(255)), defaults);
+        assert_eq!(FilenameLens::fitting(Some(1024)), defaults);
+        // eCryptfs
+        assert_eq!(
+            FilenameLens::fitting(Some(143)),
+            FilenameLens { text: 26, path: 54 }
+        );
+        assert_eq!(
+            FilenameLens::fitting(Some(10)),
+            FilenameLens { text: 1, path: 1 }
+        );
+        #[cfg(unix)]
+        assert!(super::name_max(&std::env::temp_dir().join("not/there")).is_some());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn resolve_path() -> Result<()> {
+        let home = std::path::PathBuf::from(std::env::var_os("HOME").unwrap());
+        let cwd = std::env::current_dir()?;
+
+        assert_eq!(super::resolve_path("~/clips".as_ref())?, home.join("clips"));
+        assert_eq!(super::resolve_path("~".as_ref())?, home);
+        assert_eq!(
+            super::resolve_path("~root/x".as_ref())?
+                .file_name()
+                .unwrap(),
+            "x"
+        );
+        assert_eq!(
+            super::resolve_path("clips/~".as_ref())?,
+            cwd.join("clips/~")
+        );
+        assert_eq!(
+            super::resolve_path("/abs".as_ref())?,
+            std::path::Path::new("/abs")
+        );
+        assert!(super::resolve_path("~no-such-user-hopefully/x".as_ref()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn glob() {
+        let matches = |glob, path: &str| super::Glob::new(glob).unwrap().is_match(path.as_ref());
+
+        assert!(matches("sample", "sample"));
+        assert!(matches("sample/", "show/s01/sample"));
+        assert!(!matches("sample", "show/samples"));
+        assert!(matches("*.txt", "a/b.txt"));
+        assert!(matches("extras/*", "extras/a.mkv"));
+        assert!(!matches("extras/*", "extras/deeper/a.mkv"));
+        assert!(!matches("extras/*", "show/extras/a.mkv"));
+        assert!(matches("**/extras", "show/extras"));
+        assert!(matches("**/extras", "extras"));
+        assert!(matches("show/**", "show/s01/e01.mkv"));
+        assert!(matches("e0?.mkv", "e01.mkv"));
+        assert!(matches("e[!2-9].mkv", "e1.mkv"));
+        assert!(!matches("e[!2-9].mkv", "e2.mkv"));
+        assert!(matches("a.b", "a.b"));
+        assert!(!matches("a.b", "axb"));
+    }
+
+    #[test]
+    fn fold_for_search() {
+        assert_eq!(super::fold_for_search("Café CRÈME"), "cafe creme");
+        assert_eq!(super::fold_for_search("Straße, Œuvre"), "strasse, oeuvre");
+        assert_eq!(super::fold_for_search("Łódź"), "lodz");
+        assert_eq!(super::fold_for_search("日本 ÷"), "日本 ÷");
+    }
+
+    #[test]
+    fn natural_cmp() {
+        use std::cmp::Ordering::{Equal, Greater, Less};
+
+        assert_eq!(super::natural_cmp("S01E2.mkv", "S01E10.mkv"), Less);
+        assert_eq!(super::natural_cmp("S02E01.mkv", "S01E10.mkv"), Greater);
+        assert_eq!(super::natural_cmp("e007", "e7"), Less);
+        assert_eq!(super::natural_cmp("show", "show 2"), Less);
+        assert_eq!(super::natural_cmp("a10", "a10"), Equal);
+    }
+
+    #[test]
+    fn create_parent_dirs() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let nested = temp_dir.path().join("does").join("not").join("exist");
+        let files = [
+            nested.join("a"),
+            nested.join("b"),
+            temp_dir.path().join("c"),
+        ];
+
+        super::create_parent_dirs(files.iter().map(AsRef::as_ref))?;
+
+        assert!(nested.is_dir());
+        Ok(())
+    }
+
+    #[test]
+    fn fnv1a() {
+        // reference values of the algorithm
+        assert_eq!(super::fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(super::fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn move_file() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let file = |name: &str| temp_dir.path().join(name);
+        std::fs::write(file("a"), "clip")?;
+
+        super::move_file(&file("a"), &file("b"))?;
+        assert!(!file("a").exists());
+        assert_eq!(std::fs::read_to_string(file("b"))?, "clip");
+
+        // what crossing filesystems falls back to
+        super::copy_then_remove(&file("b"), &file("c"))?;
+        assert!(!file("b").exists());
+        assert_eq!(std::fs::read_to_string(file("c"))?, "clip");
+
+        assert!(super::copy_then_remove(&file("gone"), &file("d")).is_err());
+        assert_eq!(
+            std::fs::read_dir(temp_dir.path())?.count(),
+            1,
+            "no partial copy left behind"
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn staging_file() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let temp_dir = TempDir::new()?;
+        let mode = |path: &std::path::Path| -> Result<u32> {
+            Ok(path.metadata()?.permissions().mode() & 0o777)
+        };
+        // whatever the umask makes of a plain file
+        let plain = temp_dir.path().join("plain");
+        std::fs::write(&plain, "")?;
+
+        let staged = super::staging_file(temp_dir.path(), ".mkv")?;
+        let name = staged.path().file_name().unwrap().to_string_lossy();
+        assert!(name.starts_with(".magiclip-") && name.ends_with(".mkv"));
+        assert_eq!(mode(staged.path())?, mode(&plain)?);
+        Ok(())
+    }
+
+    #[test]
+    fn temp_dir() -> Result<()> {
+        let parent = TempDir::new()?;
+        let scratch = parent.path().join("scratch");
+
+        let temp_dir = super::temp_dir(Some(&scratch))?;
+        assert_eq!(temp_dir.path().parent(), Some(scratch.as_path()));
+        let path = temp_dir.path().to_owned();
+        drop(temp_dir);
+        assert!(!path.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn timings() {
+        use std::time::Duration;
+
+        let mut timings = super::Timings(vec![
+            ("scan", Duration::from_millis(1500)),
+            ("format", Duration::from_micros(45_678)),
+        ]);
+        assert_eq!(timings.time("select", || 42), 42);
+        timings.0.last_mut().unwrap().1 = Duration::from_secs(2);
+        timings.0.push(("scan", Duration::from_millis(500)));
+
+        assert_eq!(timings.of("scan"), Duration::from_secs(2));
+        assert_eq!(timings.of("clip"), Duration::ZERO);
+        assert_eq!(timings.total(), Duration::from_micros(4_045_678));
+        assert_eq!(
+            timings.to_string(),
+            "scan 1.50s, format 45.68ms, select 2.00s, scan 500.00ms"
+        );
+        assert_eq!(super::per_second(3, Duration::from_millis(1500)), Some(2.0));
+        assert_eq!(super::per_second(3, Duration::ZERO), None);
+    }
+}
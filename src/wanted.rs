@@ -0,0 +1,152 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context as _, Result};
+use serde::Deserialize;
+
+/// A subtitle line somebody wants clipped, see `--from-list`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct WantedLine {
+    /// the exact text, line breaks and extra whitespace aside
+    pub text: String,
+    /// only look in videos whose path ends with this
+    #[serde(default)]
+    pub video_path: Option<PathBuf>,
+}
+
+impl WantedLine {
+    /// What subtitle texts get matched by: `match_key()` of theirs equals this.
+    pub fn match_key(&self) -> String {
+        match_key(&self.text)
+    }
+
+    /// Whether subtitles of `video` may match.
+    pub fn wants_video(&self, video: &Path) -> bool {
+        self.video_path
+            .as_ref()
+            .is_none_or(|wanted| video.ends_with(wanted))
+    }
+}
+
+/// `text` with whitespace (including line breaks) collapsed to single spaces.
+pub fn match_key(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Reads a list of wanted lines: a JSON array of `{"text": …, "video_path": …}`
+/// if `file` ends with `.json`, otherwise CSV with a `text` column and an
+/// optional `video_path` column.
+pub fn read(file: &Path) -> Result<Vec<WantedLine>> {
+    let content =
+        std::fs::read_to_string(file).with_context(|| format!("reading list {file:?}"))?;
+    let is_json = file
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    if is_json {
+        serde_json::from_str(&content).with_context(|| format!("parsing list {file:?}"))
+    } else {
+        parse_csv(&content).with_context(|| format!("parsing list {file:?}"))
+    }
+}
+
+fn parse_csv(content: &str) -> Result<Vec<WantedLine>> {
+    let mut rows = csv_rows(content)?.into_iter();
+    let header = rows.next().unwrap_or_default();
+    let column = |name: &str| header.iter().position(|column| column.trim() == name);
+    let text = column("text").context("no `text` column in the header")?;
+    let video_path = column("video_path");
+    rows.enumerate()
+        .filter(|(_, row)| row.iter().any(|field| !field.is_empty()))
+        .map(|(i, row)| {
+            Ok(WantedLine {
+                text: row
+                    .get(text)
+                    .with_context(|| format!("no text in row {n}", n = i + 2))?
+                    .clone(),
+                video_path: video_path
+                    .and_then(|column| row.get(column))
+                    .filter(|path| !path.is_empty())
+                    .map(PathBuf::from),
+            })
+        })
+        .collect()
+}
+
+/// Fields of each record, as in RFC 4180: quoted fields may contain commas,
+/// line breaks and `""` for a quote.
+fn csv_rows(content: &str) -> Result<Vec<Vec<String>>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut chars = content.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.next_if_eq(&'"').is_some() => field.push('"'),
+            (true, '"') => quoted = false,
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => {}
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (_, c) => field.push(c),
+        }
+    }
+    if quoted {
+        bail!("unterminated quoted field");
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use anyhow::Result;
+
+    use super::WantedLine;
+
+    #[test]
+    fn parse_csv() -> Result<()> {
+        let lines = super::parse_csv(
+            "video_path,text\r\n\
+             ,Hello\r\n\
+             show/ep1.mkv,\"Wait, what?\"\r\n\
+             ,\"Two\nlines, \"\"quoted\"\"\"\n\
+             \n",
+        )?;
+        let line = |text: &str, video_path: Option<&str>| WantedLine {
+            text: text.to_owned(),
+            video_path: video_path.map(Into::into),
+        };
+        assert_eq!(
+            lines,
+            [
+                line("Hello", None),
+                line("Wait, what?", Some("show/ep1.mkv")),
+                line("Two\nlines, \"quoted\"", None),
+            ]
+        );
+        assert!(super::parse_csv("video_path\nx.mkv").is_err());
+        assert!(super::parse_csv("text\n\"open").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn matching() {
+        let wanted = WantedLine {
+            text: "Wait,  what?".to_owned(),
+            video_path: Some("show/ep1.mkv".into()),
+        };
+        assert_eq!(wanted.match_key(), super::match_key(" Wait,\nwhat?"));
+        assert_ne!(wanted.match_key(), super::match_key("Wait, what"));
+        assert!(wanted.wants_video(Path::new("/media/show/ep1.mkv")));
+        assert!(!wanted.wants_video(Path::new("/media/other/ep1.mkv")));
+        assert!(!wanted.wants_video(Path::new("/media/show/xep1.mkv")));
+    }
+}